@@ -0,0 +1,63 @@
+/* ============================================================================
+ * File:   tokenizer.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Compares per-character and coalesced character tokens on a large,
+//   text-heavy document. Run with `cargo bench --bench tokenizer`.
+use iron::html::tokenizer::HtmlTokenizer;
+use std::time::{Duration, Instant};
+
+const PARAGRAPH: &str = "<p>Lorem ipsum dolor sit amet, consectetur adipiscing elit, \
+    sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim \
+    ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip \
+    ex ea commodo consequat &amp; duis aute irure dolor in <b>reprehenderit</b> \
+    in voluptate velit esse cillum dolore eu fugiat nulla pariatur.</p>\n";
+
+fn document() -> String {
+    let mut html = String::from("<!DOCTYPE html><html><body>\n");
+    while html.len() < 4 * 1024 * 1024 {
+        html.push_str(PARAGRAPH);
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn run(html: &str, coalesce: bool) -> (Duration, usize) {
+    let start = Instant::now();
+    let mut tokenizer = HtmlTokenizer::new(html);
+    tokenizer.set_coalesce_characters(coalesce);
+    let count = tokenizer.count();
+    (start.elapsed(), count)
+}
+
+fn main() {
+    let html = document();
+    for &coalesce in &[false, true] {
+        // the first pass warms up the allocator and caches
+        run(&html, coalesce);
+        let (elapsed, count) = run(&html, coalesce);
+        let mb_per_sec = html.len() as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0);
+        println!(
+            "coalesce={:5}: {:>8} tokens in {:?} ({:.1} MiB/s)",
+            coalesce, count, elapsed, mb_per_sec
+        );
+    }
+}
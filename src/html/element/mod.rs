@@ -22,6 +22,7 @@
  */
 // Heavily inspired by <https://github.com/servo/html5ever/blob/master/rcdom/lib.rs>
 use crate::html::parser::quirks::QuirksMode;
+use crate::html::parser::sink::TreeSink;
 use crate::infra::namespace::Namespace;
 use std::cell::{Cell, RefCell};
 use std::mem;
@@ -36,6 +37,22 @@ pub struct QualName {
     pub local: String,
 }
 
+impl QualName {
+    pub fn new(prefix: Option<String>, ns: Namespace, local: String) -> QualName {
+        QualName { prefix, ns, local }
+    }
+}
+
+impl Clone for QualName {
+    fn clone(&self) -> QualName {
+        QualName {
+            prefix: self.prefix.clone(),
+            ns: self.ns.clone(),
+            local: self.local.clone(),
+        }
+    }
+}
+
 pub struct ExpandedName {
     pub ns: Namespace,
     pub local: String,
@@ -46,6 +63,15 @@ pub struct Attribute {
     pub value: String,
 }
 
+impl Clone for Attribute {
+    fn clone(&self) -> Attribute {
+        Attribute {
+            name: self.name.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
 pub enum NodeData {
     Comment {
         content: String,
@@ -97,11 +123,53 @@ impl Drop for Node {
     }
 }
 
-// fn append(new_parent: &Handle, child: Handle) {
-//     let prev_parent = child.parent.replace(Some(Rc::downgrade(new_parent)));
-//     assert_eq!(prev_parent.is_none(), true);
-//     new_parent.children.borrow_mut().push(child);
-// }
+/// Something to insert into the tree
+pub enum NodeOrText<H = Handle> {
+    AppendNode(H),
+    /// Text is merged into an adjacent text node if there is one
+    AppendText(String),
+}
+
+fn append(new_parent: &Handle, child: Handle) {
+    let prev_parent = child.parent.replace(Some(Rc::downgrade(new_parent)));
+    assert!(prev_parent.is_none());
+    new_parent.children.borrow_mut().push(child);
+}
+
+// gets a node's parent and its index among the parent's children
+fn get_parent_and_index(target: &Handle) -> Option<(Handle, usize)> {
+    let weak = target.parent.take()?;
+    let parent = weak.upgrade().expect("dangling weak pointer");
+    target.parent.set(Some(weak));
+    let i = match parent
+        .children
+        .borrow()
+        .iter()
+        .position(|child| Rc::ptr_eq(child, target))
+    {
+        Some(i) => i,
+        None => panic!("have parent but couldn't find in parent's children!"),
+    };
+    Some((parent, i))
+}
+
+// appends to a text node; returns false if `node` isn't one
+fn append_to_existing_text(node: &Handle, text: &str) -> bool {
+    match node.data {
+        NodeData::Text { ref content } => {
+            content.borrow_mut().push_str(text);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn remove_from_parent(target: &Handle) {
+    if let Some((parent, i)) = get_parent_and_index(target) {
+        parent.children.borrow_mut().remove(i);
+        target.parent.set(None);
+    }
+}
 
 pub struct RcDom {
     pub document: Handle,
@@ -115,4 +183,216 @@ impl RcDom {
             quirks: QuirksMode::None,
         }
     }
+
+    /// Creates an element; a `<template>` also gets the node holding its
+    ///   contents, and MathML `<annotation-xml>` is marked if it's an HTML
+    ///   integration point
+    pub fn create_element(&self, name: QualName, attrs: Vec<Attribute>) -> Handle {
+        let template_contents = match name.ns {
+            Namespace::Html if name.local == "template" => Some(Node::new(NodeData::Document)),
+            _ => None,
+        };
+        // https://html.spec.whatwg.org/multipage/parsing.html#html-integration-point
+        let integration_point = name.ns == Namespace::MathML
+            && name.local == "annotation-xml"
+            && attrs.iter().any(|attr| {
+                attr.name.local == "encoding"
+                    && (attr.value.eq_ignore_ascii_case("text/html")
+                        || attr.value.eq_ignore_ascii_case("application/xhtml+xml"))
+            });
+        Node::new(NodeData::Element {
+            name,
+            attrs: RefCell::new(attrs),
+            template_contents,
+            mathml_annotation_xml_integration_point: integration_point,
+        })
+    }
+
+    pub fn create_comment(&self, text: String) -> Handle {
+        Node::new(NodeData::Comment { content: text })
+    }
+
+    pub fn create_doctype(&self, name: String, public_id: String, system_id: String) -> Handle {
+        Node::new(NodeData::Doctype {
+            name,
+            public_id,
+            system_id,
+        })
+    }
+
+    /// Gets the parent of a node, if it has one
+    pub fn parent(&self, target: &Handle) -> Option<Handle> {
+        get_parent_and_index(target).map(|(parent, _)| parent)
+    }
+
+    /// Appends a node or text as the last child of `parent`
+    pub fn append(&self, parent: &Handle, child: NodeOrText) {
+        match child {
+            NodeOrText::AppendNode(node) => append(parent, node),
+            NodeOrText::AppendText(text) => {
+                // merge with the last child if it's a text node
+                if let Some(last) = parent.children.borrow().last() {
+                    if append_to_existing_text(last, &text) {
+                        return;
+                    }
+                }
+                append(
+                    parent,
+                    Node::new(NodeData::Text {
+                        content: RefCell::new(text),
+                    }),
+                );
+            }
+        }
+    }
+
+    /// Inserts a node or text right before `sibling`
+    pub fn append_before_sibling(&self, sibling: &Handle, child: NodeOrText) {
+        // detach a node being moved first, as that can shift `sibling`
+        if let NodeOrText::AppendNode(ref node) = child {
+            remove_from_parent(node);
+        }
+        let (parent, i) = get_parent_and_index(sibling)
+            .expect("append_before_sibling called on node without parent");
+
+        let child = match (child, i) {
+            // merge with the sibling before if it's a text node
+            (NodeOrText::AppendText(text), 0) => Node::new(NodeData::Text {
+                content: RefCell::new(text),
+            }),
+            (NodeOrText::AppendText(text), i) => {
+                let children = parent.children.borrow();
+                if append_to_existing_text(&children[i - 1], &text) {
+                    return;
+                }
+                Node::new(NodeData::Text {
+                    content: RefCell::new(text),
+                })
+            }
+            (NodeOrText::AppendNode(node), _) => node,
+        };
+
+        child.parent.set(Some(Rc::downgrade(&parent)));
+        parent.children.borrow_mut().insert(i, child);
+    }
+
+    /// Removes a node from its parent, if it has one
+    pub fn remove_from_parent(&self, target: &Handle) {
+        remove_from_parent(target);
+    }
+
+    /// Moves all the children of `node` to the end of `new_parent`
+    pub fn reparent_children(&self, node: &Handle, new_parent: &Handle) {
+        let mut children = node.children.borrow_mut();
+        let mut new_children = new_parent.children.borrow_mut();
+        for child in children.iter() {
+            let previous_parent = child.parent.replace(Some(Rc::downgrade(new_parent)));
+            assert!(Rc::ptr_eq(
+                node,
+                &previous_parent.unwrap().upgrade().expect("dangling weak")
+            ));
+        }
+        new_children.extend(mem::take(&mut *children));
+    }
+}
+
+impl TreeSink for RcDom {
+    type Handle = Handle;
+
+    fn document(&self) -> Handle {
+        self.document.clone()
+    }
+
+    fn elem_name(&self, target: &Handle) -> ExpandedName {
+        match target.data {
+            NodeData::Element { ref name, .. } => ExpandedName {
+                ns: name.ns.clone(),
+                local: name.local.clone(),
+            },
+            _ => panic!("not an element"),
+        }
+    }
+
+    fn create_element(&mut self, name: QualName, attrs: Vec<Attribute>) -> Handle {
+        RcDom::create_element(self, name, attrs)
+    }
+
+    fn create_comment(&mut self, text: String) -> Handle {
+        RcDom::create_comment(self, text)
+    }
+
+    fn append_doctype_to_document(&mut self, name: String, public_id: String, system_id: String) {
+        let doctype = self.create_doctype(name, public_id, system_id);
+        append(&self.document, doctype);
+    }
+
+    fn append(&mut self, parent: &Handle, child: NodeOrText) {
+        RcDom::append(self, parent, child);
+    }
+
+    fn append_before_sibling(&mut self, sibling: &Handle, child: NodeOrText) {
+        RcDom::append_before_sibling(self, sibling, child);
+    }
+
+    fn parent(&self, target: &Handle) -> Option<Handle> {
+        RcDom::parent(self, target)
+    }
+
+    fn children(&self, target: &Handle) -> Vec<Handle> {
+        target.children.borrow().clone()
+    }
+
+    fn remove_from_parent(&mut self, target: &Handle) {
+        remove_from_parent(target);
+    }
+
+    fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+        RcDom::reparent_children(self, node, new_parent);
+    }
+
+    fn template_contents(&self, target: &Handle) -> Option<Handle> {
+        match target.data {
+            NodeData::Element {
+                ref template_contents,
+                ..
+            } => template_contents.clone(),
+            _ => None,
+        }
+    }
+
+    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+        Rc::ptr_eq(x, y)
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, target: &Handle) -> bool {
+        match target.data {
+            NodeData::Element {
+                mathml_annotation_xml_integration_point,
+                ..
+            } => mathml_annotation_xml_integration_point,
+            _ => false,
+        }
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+        if let NodeData::Element {
+            attrs: ref existing,
+            ..
+        } = target.data
+        {
+            let mut existing = existing.borrow_mut();
+            for attr in attrs {
+                if existing
+                    .iter()
+                    .all(|other| other.name.local != attr.name.local)
+                {
+                    existing.push(attr);
+                }
+            }
+        }
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.quirks = mode;
+    }
 }
@@ -22,4 +22,6 @@
  */
 pub mod element;
 pub mod parser;
+pub mod rewriter;
+pub mod serializer;
 pub mod tokenizer;
@@ -22,6 +22,7 @@
  */
 use crate::infra::namespace::Namespace;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ParseHtmlError {
     AbruptClosingOfEmptyComment,
     AbruptDoctypePublicIdentifier,
@@ -72,6 +73,124 @@ pub enum ParseHtmlError {
     UnexpectedQuestionMarkInsteadOfTagName,
     UnexpectedSolidusInTag,
     UnknownNamedCharacterReference,
+
+    // tree construction errors
+    // the standard doesn't name these, so the codes are our own
+    ExpectedDoctype,
+    MisnestedTag,
+    NonConformingDoctype,
+    UnclosedElements,
+    UnexpectedCharacter,
+    UnexpectedDoctype,
+    UnexpectedEndTag,
+    UnexpectedEof,
+    UnexpectedStartTag,
+}
+
+impl ParseHtmlError {
+    /// Gets the error code used for this error by the HTML standard
+    /// Tree construction errors aren't named by the standard, so they get
+    ///   codes in the same style.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ParseHtmlError::AbruptClosingOfEmptyComment => "abrupt-closing-of-empty-comment",
+            ParseHtmlError::AbruptDoctypePublicIdentifier => "abrupt-doctype-public-identifier",
+            ParseHtmlError::AbruptDoctypeSystemIdentifier => "abrupt-doctype-system-identifier",
+            ParseHtmlError::AbsenseOfDigitsInNumericCharacterReference => {
+                "absence-of-digits-in-numeric-character-reference"
+            }
+            ParseHtmlError::CDataInHtmlContext => "cdata-in-html-content",
+            ParseHtmlError::CharacterReferenceOutsideUnicodeRange => {
+                "character-reference-outside-unicode-range"
+            }
+            ParseHtmlError::ControlCharacterInInputStream => "control-character-in-input-stream",
+            ParseHtmlError::ControlCharacterReference => "control-character-reference",
+            ParseHtmlError::EndTagWithAttributes => "end-tag-with-attributes",
+            ParseHtmlError::DuplicateAttribute => "duplicate-attribute",
+            ParseHtmlError::EndTagWithTrailingSolidus => "end-tag-with-trailing-solidus",
+            ParseHtmlError::EofBeforeTagName => "eof-before-tag-name",
+            ParseHtmlError::EofInCData => "eof-in-cdata",
+            ParseHtmlError::EofInComment => "eof-in-comment",
+            ParseHtmlError::EofInDoctype => "eof-in-doctype",
+            ParseHtmlError::EofInScriptHtmlCommentLikeText => {
+                "eof-in-script-html-comment-like-text"
+            }
+            ParseHtmlError::EofInTag => "eof-in-tag",
+            ParseHtmlError::IncorrectlyClosedComment => "incorrectly-closed-comment",
+            ParseHtmlError::IncorrectlyOpenedComment => "incorrectly-opened-comment",
+            ParseHtmlError::InvalidCharacterSequenceAfterDoctypeName => {
+                "invalid-character-sequence-after-doctype-name"
+            }
+            ParseHtmlError::InvalidFirstCharacterOfTagName => "invalid-first-character-of-tag-name",
+            ParseHtmlError::MissingAttributeValue => "missing-attribute-value",
+            ParseHtmlError::MissingDoctypeName => "missing-doctype-name",
+            ParseHtmlError::MissingDoctypePublicIdentifier => "missing-doctype-public-identifier",
+            ParseHtmlError::MissingDoctypeSystemIdentifier => "missing-doctype-system-identifier",
+            ParseHtmlError::MissingEndTagName => "missing-end-tag-name",
+            ParseHtmlError::MissingQuoteBeforeDoctypePublicIdentifier => {
+                "missing-quote-before-doctype-public-identifier"
+            }
+            ParseHtmlError::MissingQuoteBeforeDoctypeSystemIdentifier => {
+                "missing-quote-before-doctype-system-identifier"
+            }
+            ParseHtmlError::MissingSemicolonAfterCharacterReference => {
+                "missing-semicolon-after-character-reference"
+            }
+            ParseHtmlError::MissingWhitespaceAfterDoctypePublicKeyword => {
+                "missing-whitespace-after-doctype-public-keyword"
+            }
+            ParseHtmlError::MissingWhitespaceAfterDoctypeSystemKeyword => {
+                "missing-whitespace-after-doctype-system-keyword"
+            }
+            ParseHtmlError::MissingWhitespaceBeforeDoctypeName => {
+                "missing-whitespace-before-doctype-name"
+            }
+            ParseHtmlError::MissingWhitespaceBetweenAttributes => {
+                "missing-whitespace-between-attributes"
+            }
+            ParseHtmlError::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers => {
+                "missing-whitespace-between-doctype-public-and-system-identifiers"
+            }
+            ParseHtmlError::NestedComment => "nested-comment",
+            ParseHtmlError::NoncharacterCharacterReference => "noncharacter-character-reference",
+            ParseHtmlError::NoncharacterInInputStream => "noncharacter-in-input-stream",
+            ParseHtmlError::NonVoidHtmlElementStartTagWithTrailingSolidus => {
+                "non-void-html-element-start-tag-with-trailing-solidus"
+            }
+            ParseHtmlError::NullCharacterReference => "null-character-reference",
+            ParseHtmlError::SurrogateCharacterReference => "surrogate-character-reference",
+            ParseHtmlError::SurrogateCharacterInInputStream => {
+                "surrogate-character-in-input-stream"
+            }
+            ParseHtmlError::UnexpectedCharacterAfterDoctypeSystemIdentifier => {
+                "unexpected-character-after-doctype-system-identifier"
+            }
+            ParseHtmlError::UnexpectedCharacterInAttributeName => {
+                "unexpected-character-in-attribute-name"
+            }
+            ParseHtmlError::UnexpectedCharacterInUnquotedAttributeValue => {
+                "unexpected-character-in-unquoted-attribute-value"
+            }
+            ParseHtmlError::UnexpectedEqualsSignBeforeAttributeName => {
+                "unexpected-equals-sign-before-attribute-name"
+            }
+            ParseHtmlError::UnexpectedNullCharacter => "unexpected-null-character",
+            ParseHtmlError::UnexpectedQuestionMarkInsteadOfTagName => {
+                "unexpected-question-mark-instead-of-tag-name"
+            }
+            ParseHtmlError::UnexpectedSolidusInTag => "unexpected-solidus-in-tag",
+            ParseHtmlError::UnknownNamedCharacterReference => "unknown-named-character-reference",
+            ParseHtmlError::ExpectedDoctype => "expected-doctype",
+            ParseHtmlError::MisnestedTag => "misnested-tag",
+            ParseHtmlError::NonConformingDoctype => "non-conforming-doctype",
+            ParseHtmlError::UnclosedElements => "unclosed-elements",
+            ParseHtmlError::UnexpectedCharacter => "unexpected-character",
+            ParseHtmlError::UnexpectedDoctype => "unexpected-doctype",
+            ParseHtmlError::UnexpectedEndTag => "unexpected-end-tag",
+            ParseHtmlError::UnexpectedEof => "unexpected-eof",
+            ParseHtmlError::UnexpectedStartTag => "unexpected-start-tag",
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -241,6 +360,141 @@ impl ElementDetail {
             _ => false,
         }
     }
+
+    /// Gets the properly cased name of an SVG element given its lowercase
+    ///   name, if it has one
+    // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign
+    pub fn svg_tag_name(name: &str) -> Option<&'static str> {
+        match name {
+            "altglyph" => Some("altGlyph"),
+            "altglyphdef" => Some("altGlyphDef"),
+            "altglyphitem" => Some("altGlyphItem"),
+            "animatecolor" => Some("animateColor"),
+            "animatemotion" => Some("animateMotion"),
+            "animatetransform" => Some("animateTransform"),
+            "clippath" => Some("clipPath"),
+            "feblend" => Some("feBlend"),
+            "fecolormatrix" => Some("feColorMatrix"),
+            "fecomponenttransfer" => Some("feComponentTransfer"),
+            "fecomposite" => Some("feComposite"),
+            "feconvolvematrix" => Some("feConvolveMatrix"),
+            "fediffuselighting" => Some("feDiffuseLighting"),
+            "fedisplacementmap" => Some("feDisplacementMap"),
+            "fedistantlight" => Some("feDistantLight"),
+            "fedropshadow" => Some("feDropShadow"),
+            "feflood" => Some("feFlood"),
+            "fefunca" => Some("feFuncA"),
+            "fefuncb" => Some("feFuncB"),
+            "fefuncg" => Some("feFuncG"),
+            "fefuncr" => Some("feFuncR"),
+            "fegaussianblur" => Some("feGaussianBlur"),
+            "feimage" => Some("feImage"),
+            "femerge" => Some("feMerge"),
+            "femergenode" => Some("feMergeNode"),
+            "femorphology" => Some("feMorphology"),
+            "feoffset" => Some("feOffset"),
+            "fepointlight" => Some("fePointLight"),
+            "fespecularlighting" => Some("feSpecularLighting"),
+            "fespotlight" => Some("feSpotLight"),
+            "fetile" => Some("feTile"),
+            "feturbulence" => Some("feTurbulence"),
+            "foreignobject" => Some("foreignObject"),
+            "glyphref" => Some("glyphRef"),
+            "lineargradient" => Some("linearGradient"),
+            "radialgradient" => Some("radialGradient"),
+            "textpath" => Some("textPath"),
+            _ => None,
+        }
+    }
+
+    /// Gets the properly cased name of an SVG attribute given its lowercase
+    ///   name, if it has one
+    // https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+    pub fn svg_attribute_name(name: &str) -> Option<&'static str> {
+        match name {
+            "attributename" => Some("attributeName"),
+            "attributetype" => Some("attributeType"),
+            "basefrequency" => Some("baseFrequency"),
+            "baseprofile" => Some("baseProfile"),
+            "calcmode" => Some("calcMode"),
+            "clippathunits" => Some("clipPathUnits"),
+            "diffuseconstant" => Some("diffuseConstant"),
+            "edgemode" => Some("edgeMode"),
+            "filterunits" => Some("filterUnits"),
+            "glyphref" => Some("glyphRef"),
+            "gradienttransform" => Some("gradientTransform"),
+            "gradientunits" => Some("gradientUnits"),
+            "kernelmatrix" => Some("kernelMatrix"),
+            "kernelunitlength" => Some("kernelUnitLength"),
+            "keypoints" => Some("keyPoints"),
+            "keysplines" => Some("keySplines"),
+            "keytimes" => Some("keyTimes"),
+            "lengthadjust" => Some("lengthAdjust"),
+            "limitingconeangle" => Some("limitingConeAngle"),
+            "markerheight" => Some("markerHeight"),
+            "markerunits" => Some("markerUnits"),
+            "markerwidth" => Some("markerWidth"),
+            "maskcontentunits" => Some("maskContentUnits"),
+            "maskunits" => Some("maskUnits"),
+            "numoctaves" => Some("numOctaves"),
+            "pathlength" => Some("pathLength"),
+            "patterncontentunits" => Some("patternContentUnits"),
+            "patterntransform" => Some("patternTransform"),
+            "patternunits" => Some("patternUnits"),
+            "pointsatx" => Some("pointsAtX"),
+            "pointsaty" => Some("pointsAtY"),
+            "pointsatz" => Some("pointsAtZ"),
+            "preservealpha" => Some("preserveAlpha"),
+            "preserveaspectratio" => Some("preserveAspectRatio"),
+            "primitiveunits" => Some("primitiveUnits"),
+            "refx" => Some("refX"),
+            "refy" => Some("refY"),
+            "repeatcount" => Some("repeatCount"),
+            "repeatdur" => Some("repeatDur"),
+            "requiredextensions" => Some("requiredExtensions"),
+            "requiredfeatures" => Some("requiredFeatures"),
+            "specularconstant" => Some("specularConstant"),
+            "specularexponent" => Some("specularExponent"),
+            "spreadmethod" => Some("spreadMethod"),
+            "startoffset" => Some("startOffset"),
+            "stddeviation" => Some("stdDeviation"),
+            "stitchtiles" => Some("stitchTiles"),
+            "surfacescale" => Some("surfaceScale"),
+            "systemlanguage" => Some("systemLanguage"),
+            "tablevalues" => Some("tableValues"),
+            "targetx" => Some("targetX"),
+            "targety" => Some("targetY"),
+            "textlength" => Some("textLength"),
+            "viewbox" => Some("viewBox"),
+            "viewtarget" => Some("viewTarget"),
+            "xchannelselector" => Some("xChannelSelector"),
+            "ychannelselector" => Some("yChannelSelector"),
+            "zoomandpan" => Some("zoomAndPan"),
+            _ => None,
+        }
+    }
+
+    /// Gets the prefix, namespace and local name of a namespaced attribute
+    ///   on a foreign element
+    // https://html.spec.whatwg.org/multipage/parsing.html#adjust-foreign-attributes
+    pub fn foreign_attribute(
+        name: &str,
+    ) -> Option<(Option<&'static str>, Namespace, &'static str)> {
+        match name {
+            "xlink:actuate" => Some((Some("xlink"), Namespace::XLink, "actuate")),
+            "xlink:arcrole" => Some((Some("xlink"), Namespace::XLink, "arcrole")),
+            "xlink:href" => Some((Some("xlink"), Namespace::XLink, "href")),
+            "xlink:role" => Some((Some("xlink"), Namespace::XLink, "role")),
+            "xlink:show" => Some((Some("xlink"), Namespace::XLink, "show")),
+            "xlink:title" => Some((Some("xlink"), Namespace::XLink, "title")),
+            "xlink:type" => Some((Some("xlink"), Namespace::XLink, "type")),
+            "xml:lang" => Some((Some("xml"), Namespace::Xml, "lang")),
+            "xml:space" => Some((Some("xml"), Namespace::Xml, "space")),
+            "xmlns" => Some((None, Namespace::Xmlns, "xmlns")),
+            "xmlns:xlink" => Some((Some("xmlns"), Namespace::Xmlns, "xlink")),
+            _ => None,
+        }
+    }
 }
 
 //static Tags: &'static [&'static str] = &[""];
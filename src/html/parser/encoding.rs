@@ -0,0 +1,297 @@
+/* ============================================================================
+ * File:   encoding.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Implements <https://html.spec.whatwg.org/multipage/parsing.html#determining-the-character-encoding>
+use crate::html::parser::detail::EncodingCertainty;
+use crate::html::tokenizer::detail::Tag;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+// how much of the input the prescan looks at
+const PRESCAN_LENGTH: usize = 1024;
+
+/// Determines the encoding of a document from its first bytes
+/// `transport_hint` is the `charset` given by the transport layer, such as
+///   in a `Content-Type` header.
+/// Without a BOM, a hint, or a `<meta>` in the first 1024 bytes, the
+///   result is a tentative `windows-1252`.
+// https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm
+pub fn sniff_encoding(
+    bytes: &[u8],
+    transport_hint: Option<&str>,
+) -> (&'static Encoding, EncodingCertainty) {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return (encoding, EncodingCertainty::Certain(encoding.name().into()));
+    }
+    if let Some(encoding) = transport_hint.and_then(|hint| Encoding::for_label(hint.as_bytes())) {
+        return (encoding, EncodingCertainty::Certain(encoding.name().into()));
+    }
+    let encoding = prescan(&bytes[..bytes.len().min(PRESCAN_LENGTH)]).unwrap_or(WINDOWS_1252);
+    (
+        encoding,
+        EncodingCertainty::Tentative(encoding.name().into()),
+    )
+}
+
+/// Gets the encoding a `<meta>` start tag declares, if any
+/// This is used for `<meta>` elements found while parsing, which can
+///   change a tentative encoding.
+pub fn meta_encoding(tag: &Tag) -> Option<&'static Encoding> {
+    let attr = |name: &str| {
+        tag.attributes
+            .iter()
+            .flatten()
+            .find(|attr| attr.name == name)
+            .map(|attr| attr.value.as_bytes())
+    };
+    if let Some(charset) = attr("charset") {
+        return Encoding::for_label(charset);
+    }
+    match (attr("http-equiv"), attr("content")) {
+        (Some(http_equiv), Some(content)) if http_equiv.eq_ignore_ascii_case(b"content-type") => {
+            encoding_from_meta_content(content)
+        }
+        _ => None,
+    }
+}
+
+/// Gets the encoding to switch to from a declared one
+/// UTF-16 can't be declared from within the document, since the
+///   declaration itself would have to be decoded first.
+pub fn adjust_declared_encoding(encoding: &'static Encoding) -> &'static Encoding {
+    if encoding == UTF_16BE || encoding == UTF_16LE {
+        UTF_8
+    } else if encoding == X_USER_DEFINED {
+        WINDOWS_1252
+    } else {
+        encoding
+    }
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+fn encoding_from_meta_content(content: &[u8]) -> Option<&'static Encoding> {
+    let mut pos = 0;
+    loop {
+        let found = content[pos..]
+            .windows(7)
+            .position(|window| window.eq_ignore_ascii_case(b"charset"))?;
+        pos += found + 7;
+        while pos < content.len() && is_whitespace(content[pos]) {
+            pos += 1;
+        }
+        if pos < content.len() && content[pos] == b'=' {
+            pos += 1;
+            break;
+        }
+        // not followed by `=`; look for another "charset"
+    }
+    while pos < content.len() && is_whitespace(content[pos]) {
+        pos += 1;
+    }
+
+    let value = match content.get(pos) {
+        None => return None,
+        Some(&quote) if quote == b'"' || quote == b'\'' => {
+            let rest = &content[pos + 1..];
+            // an unmatched quote means there's no encoding
+            let end = rest.iter().position(|&b| b == quote)?;
+            &rest[..end]
+        }
+        Some(_) => {
+            let rest = &content[pos..];
+            let end = rest
+                .iter()
+                .position(|&b| is_whitespace(b) || b == b';')
+                .unwrap_or(rest.len());
+            &rest[..end]
+        }
+    };
+    Encoding::for_label(value)
+}
+
+// an attribute found by the prescan
+struct PrescanAttribute {
+    name: Vec<u8>,
+    value: Vec<u8>,
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+// returns `None` if no encoding was found, including when the input ends in
+//   the middle of a tag
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+        if rest.starts_with(b"<!--") {
+            // the dashes of `<!--` can be the start of `-->`
+            let end = rest[2..].windows(3).position(|window| window == b"-->")?;
+            pos += 2 + end + 3;
+        } else if starts_with_ignore_case(rest, b"<meta")
+            && rest.get(5).is_some_and(|&b| is_whitespace(b) || b == b'/')
+        {
+            pos += 6;
+            if let Some(encoding) = prescan_meta(bytes, &mut pos)? {
+                return Some(encoding);
+            }
+        } else if rest.len() >= 2
+            && (rest[0] == b'<' && rest[1].is_ascii_alphabetic()
+                || rest[0] == b'<'
+                    && rest[1] == b'/'
+                    && rest.get(2).is_some_and(|b| b.is_ascii_alphabetic()))
+        {
+            // skip the tag name, then its attributes
+            while pos < bytes.len() && !is_whitespace(bytes[pos]) && bytes[pos] != b'>' {
+                pos += 1;
+            }
+            while get_attribute(bytes, &mut pos)?.is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            let end = rest.iter().position(|&b| b == b'>')?;
+            pos += end + 1;
+        } else {
+            pos += 1;
+        }
+    }
+    None
+}
+
+// handles the attributes of a `<meta>` tag
+// returns `None` if the input ended, or `Some(None)` if the tag doesn't
+//   declare an encoding
+fn prescan_meta(bytes: &[u8], pos: &mut usize) -> Option<Option<&'static Encoding>> {
+    let mut attribute_names: Vec<Vec<u8>> = vec![];
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+
+    while let Some(attr) = get_attribute(bytes, pos)? {
+        if attribute_names.contains(&attr.name) {
+            continue;
+        }
+        match &attr.name[..] {
+            b"http-equiv" if attr.value == b"content-type" => got_pragma = true,
+            b"content" if charset.is_none() => {
+                if let Some(encoding) = encoding_from_meta_content(&attr.value) {
+                    charset = Some(encoding);
+                    need_pragma = Some(true);
+                }
+            }
+            b"charset" => {
+                charset = Encoding::for_label(&attr.value);
+                need_pragma = Some(false);
+            }
+            _ => (),
+        }
+        attribute_names.push(attr.name);
+    }
+
+    let declared = match (need_pragma, charset) {
+        (None, _) | (_, None) => None,
+        (Some(true), _) if !got_pragma => None,
+        (_, Some(charset)) => Some(adjust_declared_encoding(charset)),
+    };
+    Some(declared)
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing
+// returns `None` if the input ended, or `Some(None)` at the end of the tag
+fn get_attribute(bytes: &[u8], pos: &mut usize) -> Option<Option<PrescanAttribute>> {
+    let next = |pos: &mut usize| -> Option<u8> {
+        let b = *bytes.get(*pos)?;
+        *pos += 1;
+        Some(b)
+    };
+
+    let mut b = next(pos)?;
+    while is_whitespace(b) || b == b'/' {
+        b = next(pos)?;
+    }
+    if b == b'>' {
+        return Some(None);
+    }
+
+    let mut attr = PrescanAttribute {
+        name: vec![],
+        value: vec![],
+    };
+    // the name
+    loop {
+        match b {
+            b'=' if !attr.name.is_empty() => break,
+            _ if is_whitespace(b) => {
+                while is_whitespace(b) {
+                    b = next(pos)?;
+                }
+                if b != b'=' {
+                    // an attribute without a value; `b` belongs to the next
+                    //   one
+                    *pos -= 1;
+                    return Some(Some(attr));
+                }
+                break;
+            }
+            b'/' | b'>' => {
+                *pos -= 1;
+                return Some(Some(attr));
+            }
+            b => attr.name.push(b.to_ascii_lowercase()),
+        }
+        b = next(pos)?;
+    }
+
+    // the value
+    b = next(pos)?;
+    while is_whitespace(b) {
+        b = next(pos)?;
+    }
+    match b {
+        b'"' | b'\'' => {
+            let quote = b;
+            loop {
+                b = next(pos)?;
+                if b == quote {
+                    return Some(Some(attr));
+                }
+                attr.value.push(b.to_ascii_lowercase());
+            }
+        }
+        b'>' => {
+            *pos -= 1;
+            return Some(Some(attr));
+        }
+        b => attr.value.push(b.to_ascii_lowercase()),
+    }
+    loop {
+        b = next(pos)?;
+        if is_whitespace(b) || b == b'>' {
+            *pos -= 1;
+            return Some(Some(attr));
+        }
+        attr.value.push(b.to_ascii_lowercase());
+    }
+}
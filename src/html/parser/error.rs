@@ -0,0 +1,106 @@
+/* ============================================================================
+ * File:   error.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+use crate::html::parser::detail::ParseHtmlError;
+use crate::html::tokenizer::detail::TokenMetadata;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// What the tokenizer or parser should do after reporting a parse error
+#[derive(Copy, Clone, PartialEq)]
+pub enum ErrorAction {
+    Continue,
+    Abort,
+}
+
+/// Implemented by types that receive parse errors from `HtmlTokenizer` and
+///   `HtmlParser`
+///
+/// `location` is where in the source the error was found.
+/// Returning `ErrorAction::Abort` stops tokenization and tree construction.
+pub trait ParseErrorHandler {
+    fn parse_error(&mut self, error: ParseHtmlError, location: &TokenMetadata) -> ErrorAction;
+}
+
+/// Allows a handler to be shared between the tokenizer, the parser, and the
+///   caller that inspects it afterwards
+impl<T: ParseErrorHandler> ParseErrorHandler for Rc<RefCell<T>> {
+    fn parse_error(&mut self, error: ParseHtmlError, location: &TokenMetadata) -> ErrorAction {
+        self.borrow_mut().parse_error(error, location)
+    }
+}
+
+/// Keeps every parse error along with its location
+#[derive(Default)]
+pub struct CollectErrors {
+    pub errors: Vec<(ParseHtmlError, TokenMetadata)>,
+}
+
+impl CollectErrors {
+    pub fn new() -> CollectErrors {
+        CollectErrors { errors: vec![] }
+    }
+}
+
+impl ParseErrorHandler for CollectErrors {
+    fn parse_error(&mut self, error: ParseHtmlError, location: &TokenMetadata) -> ErrorAction {
+        self.errors.push((error, location.clone()));
+        ErrorAction::Continue
+    }
+}
+
+/// Prints every parse error to standard error
+pub struct LogErrors;
+
+impl ParseErrorHandler for LogErrors {
+    fn parse_error(&mut self, error: ParseHtmlError, location: &TokenMetadata) -> ErrorAction {
+        eprintln!(
+            "{}:{}: parse error: {}",
+            location.line + 1,
+            location.line_offset + 1,
+            error.as_str()
+        );
+        ErrorAction::Continue
+    }
+}
+
+/// Aborts on the first parse error and keeps it
+/// Used for strict validation where any error is fatal
+#[derive(Default)]
+pub struct StrictErrors {
+    pub error: Option<(ParseHtmlError, TokenMetadata)>,
+}
+
+impl StrictErrors {
+    pub fn new() -> StrictErrors {
+        StrictErrors { error: None }
+    }
+}
+
+impl ParseErrorHandler for StrictErrors {
+    fn parse_error(&mut self, error: ParseHtmlError, location: &TokenMetadata) -> ErrorAction {
+        if self.error.is_none() {
+            self.error = Some((error, location.clone()));
+        }
+        ErrorAction::Abort
+    }
+}
@@ -21,44 +21,176 @@
  * ============================================================================
  */
 pub mod detail;
+pub mod encoding;
+pub mod error;
+pub mod preload;
 pub mod quirks;
+pub mod script;
+pub mod sink;
 
+use crate::html::element::Attribute;
 use crate::html::element::*;
 use crate::html::parser::detail::*;
+use crate::html::parser::error::{ErrorAction, ParseErrorHandler};
+use crate::html::parser::preload::{PreloadHandler, PreloadScanner};
+use crate::html::parser::quirks::QuirksMode;
+use crate::html::parser::script::{ScriptAction, ScriptHandler};
+use crate::html::parser::sink::TreeSink;
 use crate::html::tokenizer::detail::*;
+use crate::html::tokenizer::HtmlTokenizer;
 use crate::infra::namespace::Namespace;
+use encoding_rs::Encoding;
+
+/// Builds a tree from HTML as described in section 12.2.6 of the spec
+/// The tree is `RcDom` unless another `TreeSink` is given to `with_sink`.
+pub struct HtmlParser<Sink: TreeSink = RcDom> {
+    tokenizer: HtmlTokenizer,
 
-pub struct HtmlParser {
     character_encoding: Option<EncodingCertainty>,
+    // set when a `<meta>` names a different encoding than the tentative one;
+    //   parsing stops so the input can be decoded again
+    encoding_change: Option<&'static Encoding>,
 
-    document: RcDom,
+    sink: Sink,
+    // the document's mode, which is also given to the sink
+    quirks_mode: QuirksMode,
 
     insertion_mode: InsertionMode,
     orig_insertion_mode: Option<InsertionMode>,
 
-    open_elements_stack: Vec<Handle>,
-    active_formatting_elements: Vec<Handle>,
-    head_elem: Option<Handle>,
-    form_elem: Option<Handle>,
+    open_elements_stack: Vec<Sink::Handle>,
+    active_formatting_elements: Vec<FormatEntry<Sink::Handle>>,
+    head_elem: Option<Sink::Handle>,
+    form_elem: Option<Sink::Handle>,
 
     scripting: bool,
     frameset_ok: bool,
+    // https://html.spec.whatwg.org/multipage/iframe-embed-object.html#an-iframe-srcdoc-document
+    iframe_srcdoc: bool,
 
     template_insertion_modes: Vec<InsertionMode>,
 
     foster_parenting: bool,
+    // https://html.spec.whatwg.org/multipage/parsing.html#concept-pending-table-char-tokens
+    pending_table_text: String,
 
     script_nesting_level: u32,
     parser_pause_flag: bool,
+    // https://html.spec.whatwg.org/multipage/scripting.html#pending-parsing-blocking-script
+    pending_parsing_blocking_script: Option<Sink::Handle>,
+    script_handler: Option<Box<dyn ScriptHandler<Sink::Handle>>>,
+
+    // the `href` of the first `<base>` outside a `<template>` that has one
+    base_href: Option<String>,
+    // scans the input ahead of the tokenizer while parsing is paused
+    preload_scanner: Option<PreloadScanner>,
+    // the offset in the tokenizer's input the preload scanner has scanned to
+    preload_scanned_to: usize,
+
+    context_elem: Option<Sink::Handle>,
+
+    // location of the token being processed; used for errors
+    cur_location: TokenMetadata,
+    // set when the current token's self-closing flag is acknowledged
+    self_closing_acknowledged: bool,
+    // set after `<pre>`, `<listing>` and `<textarea>`, where a newline right
+    //   after the start tag is ignored
+    ignore_lf: bool,
+
+    error_handler: Option<Box<dyn ParseErrorHandler>>,
+    aborted: bool,
+}
+
+// where to insert a node
+// https://html.spec.whatwg.org/multipage/parsing.html#appropriate-place-for-inserting-a-node
+enum InsertionPoint<H> {
+    LastChild(H),
+    BeforeSibling(H),
+}
 
-    context_elem: Option<Handle>,
+// an entry in the list of active formatting elements
+// https://html.spec.whatwg.org/multipage/parsing.html#list-of-active-formatting-elements
+enum FormatEntry<H> {
+    // the element and the token it was created for
+    Element(H, Tag),
+    Marker,
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-the-specific-scope
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
 }
 
 impl HtmlParser {
+    /// Creates a parser that is given its input in chunks with `feed`
+    /// Call `finish` after the last chunk, then take the tree with
+    ///   `into_document`.
     pub fn new() -> HtmlParser {
+        HtmlParser::with_sink(RcDom::new())
+    }
+
+    /// Parses a whole document from its bytes, determining their encoding
+    /// `transport_hint` is the `charset` given by the transport layer, if
+    ///   any. If a `<meta>` contradicts a tentative encoding, the input is
+    ///   decoded again with that encoding and parsed from the start.
+    pub fn parse_bytes(bytes: &[u8], transport_hint: Option<&str>) -> RcDom {
+        let (encoding, certainty) = encoding::sniff_encoding(bytes, transport_hint);
+        let mut parser = HtmlParser::new();
+        parser.set_character_encoding(certainty);
+        let (html, _) = encoding.decode_with_bom_removal(bytes);
+        parser.feed(&html);
+        if let Some(encoding) = parser.encoding_change_requested() {
+            // start over; the new encoding is certain, so this happens once
+            parser = HtmlParser::new();
+            parser.set_character_encoding(EncodingCertainty::Certain(encoding.name().into()));
+            let (html, _) = encoding.decode_with_bom_removal(bytes);
+            parser.feed(&html);
+        }
+        parser.finish();
+        parser.into_document()
+    }
+
+    /// Parses a whole document
+    pub fn parse(html: &str) -> RcDom {
+        let mut parser = HtmlParser::new();
+        parser.feed(html);
+        parser.finish();
+        parser.into_document()
+    }
+
+    /// Creates a parser for a fragment of markup, as if it were set as the
+    ///   `innerHTML` of `context`
+    /// `context` should be an element. If it's in a tree, an ancestor
+    ///   `<form>` is used as the form element pointer.
+    pub fn new_fragment(context: &Handle) -> HtmlParser {
+        HtmlParser::fragment_with_sink(RcDom::new(), context)
+    }
+
+    /// Parses a fragment of markup in the context of an element, returning
+    ///   the nodes it creates
+    pub fn parse_fragment(context: &Handle, html: &str) -> Vec<Handle> {
+        let mut parser = HtmlParser::new_fragment(context);
+        parser.feed(html);
+        parser.finish();
+        parser.into_fragment()
+    }
+}
+
+impl<Sink: TreeSink> HtmlParser<Sink> {
+    /// Creates a parser that builds its tree in `sink`
+    pub fn with_sink(sink: Sink) -> HtmlParser<Sink> {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.set_coalesce_characters(true);
         HtmlParser {
+            tokenizer,
             character_encoding: None,
-            document: RcDom::new(),
+            encoding_change: None,
+            sink,
+            quirks_mode: QuirksMode::None,
             insertion_mode: InsertionMode::Initial,
             orig_insertion_mode: None,
             open_elements_stack: vec![],
@@ -67,63 +199,397 @@ impl HtmlParser {
             form_elem: None,
             scripting: true,
             frameset_ok: true,
+            iframe_srcdoc: false,
             template_insertion_modes: vec![],
             foster_parenting: false,
+            pending_table_text: String::new(),
             script_nesting_level: 0,
             parser_pause_flag: false,
+            pending_parsing_blocking_script: None,
+            script_handler: None,
+            base_href: None,
+            preload_scanner: None,
+            preload_scanned_to: 0,
             context_elem: None,
+            cur_location: TokenMetadata::new(0, 0, 0),
+            self_closing_acknowledged: false,
+            ignore_lf: false,
+            error_handler: None,
+            aborted: false,
         }
     }
 
-    fn start_tag_with_name(tag: &Tag, tag_name: &str) -> bool {
-        &tag.name[..] == tag_name && !tag.is_end_tag
+    /// Creates a parser for a fragment of markup that builds its tree in
+    ///   `sink`; see `new_fragment`
+    // https://html.spec.whatwg.org/multipage/parsing.html#parsing-html-fragments
+    pub fn fragment_with_sink(sink: Sink, context: &Sink::Handle) -> HtmlParser<Sink> {
+        let mut parser = HtmlParser::with_sink(sink);
+        let name = parser.elem_name(context);
+
+        if name.ns == Namespace::Html {
+            let state = State::for_start_tag(&name.local, parser.scripting);
+            parser.tokenizer.switch_to(state.unwrap_or(State::Data));
+        }
+        parser.tokenizer.set_last_start_tag(&name.local);
+
+        let root = parser
+            .sink
+            .create_element(QualName::new(None, Namespace::Html, "html".into()), vec![]);
+        let document = parser.sink.document();
+        parser
+            .sink
+            .append(&document, NodeOrText::AppendNode(root.clone()));
+        parser.open_elements_stack.push(root);
+
+        if parser.html_elem_named(context, "template") {
+            parser
+                .template_insertion_modes
+                .push(InsertionMode::InTemplate);
+        }
+        parser.context_elem = Some(context.clone());
+        parser.insertion_mode = parser.reset_insertion_mode();
+
+        // the nearest `<form>`, starting with the context element itself
+        let mut node = Some(context.clone());
+        while let Some(elem) = node {
+            if parser.html_elem_named(&elem, "form") {
+                parser.form_elem = Some(elem);
+                break;
+            }
+            // the root of the context's tree is a document, not an element
+            node = parser
+                .sink
+                .parent(&elem)
+                .filter(|parent| parser.sink.parent(parent).is_some());
+        }
+
+        let foreign = parser.adjusted_current_node_foreign();
+        parser.tokenizer.set_adjusted_current_node_foreign(foreign);
+        parser
     }
 
-    fn start_tag_with_names(tag: &Tag, tag_names: Vec<&str>) -> bool {
-        if tag.is_end_tag {
-            return false;
+    /// Sets the handler that receives tree construction errors.
+    /// To get tokenizer errors in the same place, pass a shared
+    ///   `Rc<RefCell<...>>` handler to both.
+    pub fn set_error_handler(&mut self, handler: Box<dyn ParseErrorHandler>) {
+        self.error_handler = Some(handler);
+    }
+
+    /// Sets the handler that runs scripts as their end tags are processed
+    /// Without one, scripts are inserted into the tree but never run.
+    pub fn set_script_handler(&mut self, handler: Box<dyn ScriptHandler<Sink::Handle>>) {
+        self.script_handler = Some(handler);
+    }
+
+    /// Sets the handler that receives the resources found by scanning ahead
+    ///   of the parser while it's paused for a script
+    /// Each time parsing is paused, the scanner picks up where it left off,
+    ///   or where the parser is if it has caught up, so each resource is
+    ///   reported at most once.
+    pub fn set_preload_handler(&mut self, handler: Box<dyn PreloadHandler>) {
+        self.preload_scanner = Some(PreloadScanner::new(handler));
+    }
+
+    /// Sets the scripting flag, which is on by default
+    /// With scripting on, the contents of `<noscript>` are raw text;
+    ///   with it off, they're parsed as markup. Call this before giving the
+    ///   parser any input.
+    // https://html.spec.whatwg.org/multipage/parsing.html#scripting-flag
+    pub fn set_scripting(&mut self, scripting: bool) {
+        self.scripting = scripting;
+        // a fragment parser's initial tokenizer state depends on the flag
+        let noscript_context = self
+            .context_elem
+            .as_ref()
+            .is_some_and(|context| self.html_elem_named(context, "noscript"));
+        if noscript_context {
+            let state = if scripting {
+                State::Rawtext
+            } else {
+                State::Data
+            };
+            self.tokenizer.switch_to(state);
         }
+    }
 
-        let tag = &tag.name[..];
-        for name in tag_names {
-            if tag == name {
-                return true;
+    pub fn scripting(&self) -> bool {
+        self.scripting
+    }
+
+    /// Checks if parsing is paused for a script
+    /// Input given to `feed` is kept until `resume` is called.
+    pub fn paused(&self) -> bool {
+        self.parser_pause_flag || self.pending_parsing_blocking_script.is_some()
+    }
+
+    /// Gets the script that parsing is waiting on, if any
+    pub fn pending_parsing_blocking_script(&self) -> Option<&Sink::Handle> {
+        self.pending_parsing_blocking_script.as_ref()
+    }
+
+    /// Runs the pending parsing-blocking script and continues parsing
+    /// Call this once the script that blocked is ready to run, such as
+    ///   when its source has loaded.
+    // https://html.spec.whatwg.org/multipage/parsing.html#scriptEndTag
+    pub fn resume(&mut self) {
+        if let Some(script) = self.pending_parsing_blocking_script.take() {
+            self.script_nesting_level += 1;
+            if let Some(handler) = self.script_handler.as_mut() {
+                handler.execute_script(&script, self.script_nesting_level);
+            }
+            self.script_nesting_level -= 1;
+            if self.script_nesting_level == 0 {
+                self.parser_pause_flag = false;
             }
         }
-        false
+        self.run();
     }
 
-    fn end_tag_with_name(tag: &Tag, tag_name: &str) -> bool {
-        &tag.name[..] == tag_name && tag.is_end_tag
+    /// Marks the document as the `srcdoc` of an `<iframe>`, which is never
+    ///   in quirks mode
+    pub fn set_iframe_srcdoc(&mut self, iframe_srcdoc: bool) {
+        self.iframe_srcdoc = iframe_srcdoc;
     }
 
-    fn end_tag_with_names(tag: &Tag, tag_names: Vec<&str>) -> bool {
-        if !tag.is_end_tag {
-            return false;
+    /// Sets the encoding the input was decoded with, and how confident the
+    ///   caller is in it
+    /// `<meta>` elements can only change a `Tentative` encoding.
+    pub fn set_character_encoding(&mut self, certainty: EncodingCertainty) {
+        self.character_encoding = Some(certainty);
+    }
+
+    pub fn character_encoding(&self) -> Option<&EncodingCertainty> {
+        self.character_encoding.as_ref()
+    }
+
+    /// Gets the encoding a `<meta>` asked to restart parsing with
+    /// Once set, the parser stops consuming input. The caller should decode
+    ///   the input again and give it to a new parser.
+    pub fn encoding_change_requested(&self) -> Option<&'static Encoding> {
+        self.encoding_change
+    }
+
+    /// Gets the tokenizer feeding this parser, such as to set its error
+    ///   handler
+    pub fn tokenizer(&mut self) -> &mut HtmlTokenizer {
+        &mut self.tokenizer
+    }
+
+    /// Checks if the error handler aborted parsing
+    pub fn aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Appends a chunk of input and builds the tree as far as it can
+    /// Panics if `finish` was already called.
+    pub fn feed(&mut self, chunk: &str) {
+        self.tokenizer.feed(chunk);
+        self.run();
+    }
+
+    /// Marks the end of the input and finishes the tree
+    pub fn finish(&mut self) {
+        self.tokenizer.finish();
+        self.run();
+    }
+
+    /// Gets the tree built so far
+    pub fn document(&self) -> &Sink {
+        &self.sink
+    }
+
+    pub fn into_document(self) -> Sink {
+        self.sink
+    }
+
+    /// Detaches the nodes parsed by a parser for a fragment and returns them,
+    ///   so they can be inserted into another tree
+    pub fn take_fragment(&mut self) -> Vec<Sink::Handle> {
+        let document = self.sink.document();
+        let root = match self.sink.children(&document).first() {
+            Some(root) => root.clone(),
+            None => return vec![],
+        };
+        let children = self.sink.children(&root);
+        for child in children.iter() {
+            self.sink.remove_from_parent(child);
         }
+        children
+    }
 
-        let tag = &tag.name[..];
-        for name in tag_names {
-            if tag == name {
-                return true;
+    /// Takes the nodes parsed by a parser for a fragment
+    /// If the sink owns its nodes, use `take_fragment` and then
+    ///   `into_document` instead.
+    pub fn into_fragment(mut self) -> Vec<Sink::Handle> {
+        self.take_fragment()
+    }
+
+    // builds the tree from every token the tokenizer can emit
+    fn run(&mut self) {
+        while !self.aborted && self.encoding_change.is_none() && !self.paused() {
+            let tok = match self.tokenizer.next() {
+                Some(tok) => tok,
+                None => break,
+            };
+            self.cur_location = tok.start;
+            self.tree_construction_dispatcher(tok.token);
+
+            let foreign = self.adjusted_current_node_foreign();
+            self.tokenizer.set_adjusted_current_node_foreign(foreign);
+        }
+        if self.paused() {
+            self.scan_ahead();
+        }
+    }
+
+    // runs the preload scanner over the input the parser hasn't reached
+    // https://html.spec.whatwg.org/multipage/parsing.html#active-speculative-html-parser
+    fn scan_ahead(&mut self) {
+        let mut scanner = match self.preload_scanner.take() {
+            Some(scanner) => scanner,
+            None => return,
+        };
+        let offset = self.tokenizer.input_offset();
+        let input = self.tokenizer.remaining_input();
+        if self.preload_scanned_to > offset {
+            // still ahead of the parser, so only the new input is scanned
+            scanner.feed(&input[self.preload_scanned_to - offset..]);
+        } else {
+            // the parser has caught up; start over from where it is
+            scanner = PreloadScanner::new(scanner.into_handler());
+            scanner.set_scripting(self.scripting);
+            scanner.set_base(self.base_href.clone());
+            scanner.feed(input);
+        }
+        self.preload_scanned_to = offset + input.len();
+
+        if self.tokenizer.input_finished() {
+            // there's nothing left to scan
+            scanner.finish();
+        } else {
+            self.preload_scanner = Some(scanner);
+        }
+    }
+
+    // a `<meta>` in the head may declare the encoding
+    // https://html.spec.whatwg.org/multipage/parsing.html#changing-the-encoding-while-parsing
+    fn meta_encoding(&mut self, tag: &Tag) {
+        let current = match &self.character_encoding {
+            Some(EncodingCertainty::Tentative(name)) => Encoding::for_label(name.as_bytes()),
+            _ => return,
+        };
+        let declared = match encoding::meta_encoding(tag) {
+            Some(declared) => encoding::adjust_declared_encoding(declared),
+            None => return,
+        };
+        match current {
+            // input decoded as UTF-16 can't have been read wrong
+            Some(current)
+                if current == encoding_rs::UTF_16BE || current == encoding_rs::UTF_16LE =>
+            {
+                self.character_encoding = Some(EncodingCertainty::Certain(current.name().into()));
+            }
+            Some(current) if current == declared => {
+                self.character_encoding = Some(EncodingCertainty::Certain(current.name().into()));
             }
+            _ => self.encoding_change = Some(declared),
         }
-        false
     }
 
-    fn elem_name(&self, target: &Handle) -> ExpandedName {
-        match target.data {
-            NodeData::Element { ref name, .. } => ExpandedName {
-                ns: name.ns.clone(),
-                local: name.local.clone(),
-            },
-            _ => panic!(),
+    // gives a script whose end tag was processed to the script handler
+    // https://html.spec.whatwg.org/multipage/parsing.html#scriptEndTag
+    fn prepare_script(&mut self, script: Sink::Handle) {
+        let handler = match self.script_handler.as_mut() {
+            Some(handler) => handler,
+            None => return,
+        };
+        self.script_nesting_level += 1;
+        let action = handler.prepare_script(&script, self.script_nesting_level);
+        self.script_nesting_level -= 1;
+        if self.script_nesting_level == 0 {
+            self.parser_pause_flag = false;
+        }
+        if action == ScriptAction::Block {
+            self.pending_parsing_blocking_script = Some(script);
+            if self.script_nesting_level != 0 {
+                self.parser_pause_flag = true;
+            }
+        }
+    }
+
+    fn error(&mut self, err: ParseHtmlError) {
+        let handler = match self.error_handler.as_mut() {
+            Some(handler) => handler,
+            None => return,
+        };
+        if handler.parse_error(err, &self.cur_location) == ErrorAction::Abort {
+            self.aborted = true;
+        }
+    }
+
+    // reports a parse error for a token that isn't expected here
+    fn unexpected(&mut self, tok: &Token) {
+        let err = match tok {
+            Token::Character(_) | Token::Characters(_) => ParseHtmlError::UnexpectedCharacter,
+            Token::Doctype(_) => ParseHtmlError::UnexpectedDoctype,
+            Token::Eof => ParseHtmlError::UnexpectedEof,
+            Token::Tag(tag) if tag.is_end_tag => ParseHtmlError::UnexpectedEndTag,
+            _ => ParseHtmlError::UnexpectedStartTag,
+        };
+        self.error(err);
+    }
+
+    fn start_tag_with_name(tag: &Tag, tag_name: &str) -> bool {
+        &tag.name[..] == tag_name && !tag.is_end_tag
+    }
+
+    fn start_tag_with_names(tag: &Tag, tag_names: &[&str]) -> bool {
+        !tag.is_end_tag && tag_names.contains(&&tag.name[..])
+    }
+
+    fn end_tag_with_name(tag: &Tag, tag_name: &str) -> bool {
+        &tag.name[..] == tag_name && tag.is_end_tag
+    }
+
+    fn end_tag_with_names(tag: &Tag, tag_names: &[&str]) -> bool {
+        tag.is_end_tag && tag_names.contains(&&tag.name[..])
+    }
+
+    fn elem_name(&self, target: &Sink::Handle) -> ExpandedName {
+        self.sink.elem_name(target)
+    }
+
+    fn html_elem_named(&self, elem: &Sink::Handle, name: &str) -> bool {
+        let qual = self.sink.elem_name(elem);
+        qual.ns == Namespace::Html && qual.local == name
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.sink.set_quirks_mode(mode.clone());
+        self.quirks_mode = mode;
+    }
+
+    fn current_node(&self) -> &Sink::Handle {
+        self.open_elements_stack.last().expect("no current node")
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#adjusted-current-node
+    fn adjusted_current_node(&self) -> Option<&Sink::Handle> {
+        if self.open_elements_stack.len() == 1 && self.context_elem.is_some() {
+            return self.context_elem.as_ref();
         }
+        self.open_elements_stack.last()
     }
 
-    fn html_elem_named(&self, elem: &Handle, name: &str) -> bool {
-        let expanded = self.elem_name(elem);
-        expanded.ns == Namespace::Html && &expanded.local[..] == name
+    /// Checks if there is an adjusted current node and it is not an element in
+    ///   the HTML namespace.
+    /// The tokenizer needs this to decide how to handle `<![CDATA[`; see
+    ///   `HtmlTokenizer::set_adjusted_current_node_foreign`.
+    pub fn adjusted_current_node_foreign(&self) -> bool {
+        match self.adjusted_current_node() {
+            Some(node) => self.elem_name(node).ns != Namespace::Html,
+            None => false,
+        }
     }
 
     // https://html.spec.whatwg.org/multipage/parsing.html#reset-the-insertion-mode-appropriately
@@ -184,30 +650,2357 @@ impl HtmlParser {
         InsertionMode::InBody
     }
 
-    // TODO: appropriate place for inserting a node
+    // https://html.spec.whatwg.org/multipage/parsing.html#appropriate-place-for-inserting-a-node
+    fn appropriate_place_for_inserting(
+        &self,
+        override_target: Option<&Sink::Handle>,
+    ) -> InsertionPoint<Sink::Handle> {
+        let target = match override_target {
+            Some(target) => target,
+            None => self.current_node(),
+        };
+
+        if self.foster_parenting
+            && self.elem_in(
+                target,
+                Namespace::Html,
+                &["table", "tbody", "tfoot", "thead", "tr"],
+            )
+        {
+            return self.foster_parenting_place();
+        }
+
+        // a template's children go in its contents instead
+        match self.sink.template_contents(target) {
+            Some(contents) => InsertionPoint::LastChild(contents),
+            None => InsertionPoint::LastChild(target.clone()),
+        }
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#foster-parent
+    fn foster_parenting_place(&self) -> InsertionPoint<Sink::Handle> {
+        let last_template = self
+            .open_elements_stack
+            .iter()
+            .rposition(|elem| self.html_elem_named(elem, "template"));
+        let last_table = self
+            .open_elements_stack
+            .iter()
+            .rposition(|elem| self.html_elem_named(elem, "table"));
+
+        match (last_template, last_table) {
+            (Some(template), table) if table.is_none_or(|table| template > table) => {
+                let template = &self.open_elements_stack[template];
+                match self.sink.template_contents(template) {
+                    Some(contents) => InsertionPoint::LastChild(contents),
+                    None => InsertionPoint::LastChild(template.clone()),
+                }
+            }
+            // fragment case
+            (_, None) => InsertionPoint::LastChild(self.open_elements_stack[0].clone()),
+            (_, Some(table)) => {
+                let table_elem = &self.open_elements_stack[table];
+                if self.sink.parent(table_elem).is_some() {
+                    InsertionPoint::BeforeSibling(table_elem.clone())
+                } else {
+                    InsertionPoint::LastChild(self.open_elements_stack[table - 1].clone())
+                }
+            }
+        }
+    }
+
+    fn insert_at(&mut self, point: InsertionPoint<Sink::Handle>, child: NodeOrText<Sink::Handle>) {
+        match point {
+            InsertionPoint::LastChild(parent) => self.sink.append(&parent, child),
+            InsertionPoint::BeforeSibling(sibling) => {
+                self.sink.append_before_sibling(&sibling, child)
+            }
+        }
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#create-an-element-for-the-token
+    // the tag and attribute names of foreign elements are adjusted here
+    fn create_element_for_token(&mut self, tag: &Tag, ns: Namespace) -> Sink::Handle {
+        let attrs = tag
+            .attributes
+            .iter()
+            .flatten()
+            .map(|attr| Attribute {
+                name: adjusted_attribute_name(&ns, &attr.name),
+                value: attr.value.clone(),
+            })
+            .collect();
+        let local = match ElementDetail::svg_tag_name(&tag.name) {
+            Some(local) if ns == Namespace::Svg => local.into(),
+            _ => tag.name.clone(),
+        };
+        let name = QualName::new(None, ns, local);
+        self.sink.create_element(name, attrs)
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#insert-a-foreign-element
+    fn insert_foreign_element(&mut self, tag: &Tag, ns: Namespace) -> Sink::Handle {
+        let point = self.appropriate_place_for_inserting(None);
+        let elem = self.create_element_for_token(tag, ns);
+        self.insert_at(point, NodeOrText::AppendNode(elem.clone()));
+        self.open_elements_stack.push(elem.clone());
+        elem
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#insert-an-html-element
+    fn insert_html_element(&mut self, tag: &Tag) -> Sink::Handle {
+        self.insert_foreign_element(tag, Namespace::Html)
+    }
+
+    // inserts an HTML element for a start tag the parser makes up, such as
+    //   for a missing `<head>`
+    fn insert_phantom_element(&mut self, name: &str) -> Sink::Handle {
+        self.insert_html_element(&Tag::new_named(false, name))
+    }
 
-    // TODO: create element for token
+    // https://html.spec.whatwg.org/multipage/parsing.html#insert-a-character
+    fn insert_characters(&mut self, text: &str) {
+        let point = self.appropriate_place_for_inserting(None);
+        // text can't be inserted directly into the document
+        if let InsertionPoint::LastChild(ref parent) = point {
+            if self.sink.same_node(parent, &self.sink.document()) {
+                return;
+            }
+        }
+        self.insert_at(point, NodeOrText::AppendText(text.into()));
+    }
 
-    // TODO: insert a foreign element
+    // the frameset modes insert whitespace and ignore any other characters
+    fn insert_frameset_whitespace(&mut self, text: &str) {
+        let whitespace: String = text
+            .chars()
+            .filter(|c| matches!(c, '\t' | '\n' | '\u{C}' | '\r' | ' '))
+            .collect();
+        if whitespace.len() != text.len() {
+            self.error(ParseHtmlError::UnexpectedCharacter);
+        }
+        if !whitespace.is_empty() {
+            self.insert_characters(&whitespace);
+        }
+    }
 
-    // TODO: adjust MathML attributes
+    // https://html.spec.whatwg.org/multipage/parsing.html#insert-a-comment
+    fn insert_comment(&mut self, comment: Comment) {
+        let point = self.appropriate_place_for_inserting(None);
+        let node = self.sink.create_comment(comment.value);
+        self.insert_at(point, NodeOrText::AppendNode(node));
+    }
 
-    // TODO: adjust SVG attributes
+    // inserts a comment as the last child of the document
+    fn append_comment_to_document(&mut self, comment: Comment) {
+        let node = self.sink.create_comment(comment.value);
+        let document = self.sink.document();
+        self.sink.append(&document, NodeOrText::AppendNode(node));
+    }
 
-    // TODO: adjust foreign attributes
+    // https://html.spec.whatwg.org/multipage/parsing.html#generic-raw-text-element-parsing-algorithm
+    // https://html.spec.whatwg.org/multipage/parsing.html#generic-rcdata-element-parsing-algorithm
+    fn parse_generic_text_element(&mut self, tag: &Tag, state: State) {
+        self.insert_html_element(tag);
+        self.tokenizer.switch_to(state);
+        self.orig_insertion_mode = Some(self.insertion_mode);
+        self.insertion_mode = InsertionMode::Text;
+    }
 
-    // TODO: insert a character
+    fn pop(&mut self) -> Sink::Handle {
+        self.open_elements_stack
+            .pop()
+            .expect("no current node to pop")
+    }
 
-    // TODO: insert a comment
+    // https://html.spec.whatwg.org/multipage/parsing.html#stop-parsing
+    fn stop_parsing(&mut self) {
+        self.open_elements_stack.clear();
+    }
 
-    // TODO: more
-}
+    // a self-closing start tag is only allowed for elements that are known
+    //   to be empty
+    fn acknowledge_self_closing_flag(&mut self) {
+        self.self_closing_acknowledged = true;
+    }
 
-impl HtmlParser {
-    fn tree_construction_dispatcher(&mut self, _tok: Token) {
-        let mut _foreign_content = true;
-        if self.open_elements_stack.is_empty() {
-            _foreign_content = true;
+    fn elem_in(&self, elem: &Sink::Handle, ns: Namespace, names: &[&str]) -> bool {
+        let name = self.sink.elem_name(elem);
+        name.ns == ns && names.contains(&&name.local[..])
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#mathml-text-integration-point
+    fn mathml_text_integration_point(&self, elem: &Sink::Handle) -> bool {
+        self.elem_in(elem, Namespace::MathML, &["mi", "mo", "mn", "ms", "mtext"])
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#html-integration-point
+    fn html_integration_point(&self, elem: &Sink::Handle) -> bool {
+        self.sink.is_mathml_annotation_xml_integration_point(elem)
+            || self.elem_in(elem, Namespace::Svg, &["foreignObject", "desc", "title"])
+    }
+
+    // adds the attributes of a tag that an element doesn't already have
+    fn add_missing_attributes(&mut self, elem: &Sink::Handle, tag: &Tag) {
+        let attrs = tag
+            .attributes
+            .iter()
+            .flatten()
+            .map(|attr| Attribute {
+                name: QualName::new(None, Namespace::Html, attr.name.clone()),
+                value: attr.value.clone(),
+            })
+            .collect();
+        self.sink.add_attrs_if_missing(elem, attrs);
+    }
+
+    fn special(&self, elem: &Sink::Handle) -> bool {
+        let name = self.elem_name(elem);
+        ElementDetail::special_element(name.ns, &name.local)
+    }
+
+    fn in_open_elements(&self, elem: &Sink::Handle) -> bool {
+        self.open_elements_stack
+            .iter()
+            .any(|open| self.sink.same_node(open, elem))
+    }
+
+    fn remove_from_open_elements(&mut self, elem: &Sink::Handle) {
+        let sink = &self.sink;
+        self.open_elements_stack
+            .retain(|open| !sink.same_node(open, elem));
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-the-specific-scope
+    fn in_scope<F>(&self, scope: Scope, target: F) -> bool
+    where
+        F: Fn(&Sink::Handle) -> bool,
+    {
+        for node in self.open_elements_stack.iter().rev() {
+            if target(node) {
+                return true;
+            }
+            let bounds = match scope {
+                Scope::Default | Scope::ListItem | Scope::Button => {
+                    self.elem_in(
+                        node,
+                        Namespace::Html,
+                        &[
+                            "applet", "caption", "html", "table", "td", "th", "marquee", "object",
+                            "template",
+                        ],
+                    ) || self.elem_in(
+                        node,
+                        Namespace::MathML,
+                        &["mi", "mo", "mn", "ms", "mtext", "annotation-xml"],
+                    ) || self.elem_in(node, Namespace::Svg, &["foreignObject", "desc", "title"])
+                        || match scope {
+                            Scope::ListItem => self.elem_in(node, Namespace::Html, &["ol", "ul"]),
+                            Scope::Button => self.html_elem_named(node, "button"),
+                            _ => false,
+                        }
+                }
+                Scope::Table => self.elem_in(node, Namespace::Html, &["html", "table", "template"]),
+                Scope::Select => !self.elem_in(node, Namespace::Html, &["optgroup", "option"]),
+            };
+            if bounds {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn in_scope_named(&self, scope: Scope, name: &str) -> bool {
+        self.in_scope(scope, |node| self.html_elem_named(node, name))
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        loop {
+            let implied = {
+                let node = self.current_node();
+                self.elem_in(
+                    node,
+                    Namespace::Html,
+                    &[
+                        "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+                    ],
+                ) && !except.is_some_and(|name| self.html_elem_named(node, name))
+            };
+            if !implied {
+                return;
+            }
+            self.pop();
+        }
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#generate-all-implied-end-tags-thoroughly
+    fn generate_all_implied_end_tags_thoroughly(&mut self) {
+        while self.elem_in(
+            self.current_node(),
+            Namespace::Html,
+            &[
+                "caption", "colgroup", "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp",
+                "rt", "rtc", "tbody", "td", "tfoot", "th", "thead", "tr",
+            ],
+        ) {
+            self.pop();
+        }
+    }
+
+    // pops elements until one of the given HTML elements has been popped
+    fn pop_until_named(&mut self, names: &[&str]) {
+        while let Some(elem) = self.open_elements_stack.pop() {
+            if self.elem_in(&elem, Namespace::Html, names) {
+                return;
+            }
+        }
+    }
+
+    // pops elements until `target` has been popped
+    fn pop_until(&mut self, target: &Sink::Handle) {
+        while let Some(elem) = self.open_elements_stack.pop() {
+            if self.sink.same_node(&elem, target) {
+                return;
+            }
+        }
+    }
+
+    fn template_on_stack(&self) -> bool {
+        self.open_elements_stack
+            .iter()
+            .any(|elem| self.html_elem_named(elem, "template"))
+    }
+
+    // pops elements until the HTML element with the given name has been
+    //   popped, which is an error if it wasn't the current node
+    fn close_element_named(&mut self, name: &str) {
+        if !self.html_elem_named(self.current_node(), name) {
+            self.error(ParseHtmlError::UnclosedElements);
+        }
+        self.pop_until_named(&[name]);
+    }
+
+    // reports an error if an element that needs an end tag is still open
+    //   when the body ends
+    fn check_unclosed_elements(&mut self) {
+        let unclosed = self.open_elements_stack.iter().any(|elem| {
+            !self.elem_in(
+                elem,
+                Namespace::Html,
+                &[
+                    "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc", "tbody",
+                    "td", "tfoot", "th", "thead", "tr", "body", "html",
+                ],
+            )
+        });
+        if unclosed {
+            self.error(ParseHtmlError::UnclosedElements);
+        }
+    }
+
+    // pops elements until the current node is one of the given HTML
+    //   elements
+    // https://html.spec.whatwg.org/multipage/parsing.html#clear-the-stack-back-to-a-table-context
+    fn clear_stack_back_to(&mut self, names: &[&str]) {
+        while !self.elem_in(self.current_node(), Namespace::Html, names) {
+            self.pop();
+        }
+    }
+
+    // closes the caption for a token that implies its end; returns false
+    //   if there's no caption to close, and the token should be ignored
+    fn close_caption(&mut self) -> bool {
+        if !self.in_scope_named(Scope::Table, "caption") {
+            self.error(ParseHtmlError::UnexpectedEndTag);
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        self.close_element_named("caption");
+        self.clear_active_formatting_to_last_marker();
+        self.insertion_mode = InsertionMode::InTable;
+        true
+    }
+
+    // closes the current row; returns false if there's no row to close, and
+    //   the token should be ignored
+    fn close_row(&mut self) -> bool {
+        if !self.in_scope_named(Scope::Table, "tr") {
+            self.error(ParseHtmlError::UnexpectedEndTag);
+            return false;
+        }
+        self.clear_stack_back_to(TABLE_ROW_CONTEXT);
+        self.pop();
+        self.insertion_mode = InsertionMode::InTableBody;
+        true
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#close-the-cell
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        if !self.elem_in(self.current_node(), Namespace::Html, &["td", "th"]) {
+            self.error(ParseHtmlError::UnclosedElements);
+        }
+        self.pop_until_named(&["td", "th"]);
+        self.clear_active_formatting_to_last_marker();
+        self.insertion_mode = InsertionMode::InRow;
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#close-a-p-element
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some("p"));
+        if !self.html_elem_named(self.current_node(), "p") {
+            self.error(ParseHtmlError::UnclosedElements);
+        }
+        self.pop_until_named(&["p"]);
+    }
+
+    fn close_p_element_in_button_scope(&mut self) {
+        if self.in_scope_named(Scope::Button, "p") {
+            self.close_p_element();
+        }
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#push-onto-the-list-of-active-formatting-elements
+    fn push_active_formatting_element(&mut self, elem: Sink::Handle, tag: Tag) {
+        // the "Noah's Ark" clause: only three identical elements after the
+        //   last marker
+        let mut identical = vec![];
+        for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            match entry {
+                FormatEntry::Marker => break,
+                FormatEntry::Element(_, ref other) => {
+                    if other.name == tag.name && same_attributes(other, &tag) {
+                        identical.push(i);
+                    }
+                }
+            }
+        }
+        if identical.len() >= 3 {
+            self.active_formatting_elements
+                .remove(*identical.last().unwrap());
+        }
+        self.active_formatting_elements
+            .push(FormatEntry::Element(elem, tag));
+    }
+
+    fn position_in_active_formatting(&self, elem: &Sink::Handle) -> Option<usize> {
+        self.active_formatting_elements
+            .iter()
+            .position(|entry| match entry {
+                FormatEntry::Element(other, _) => self.sink.same_node(other, elem),
+                FormatEntry::Marker => false,
+            })
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#reconstruct-the-active-formatting-elements
+    fn reconstruct_active_formatting_elements(&mut self) {
+        // nothing to do if the last entry is a marker or is open
+        let last = match self.active_formatting_elements.last() {
+            None | Some(FormatEntry::Marker) => return,
+            Some(FormatEntry::Element(elem, _)) => elem.clone(),
+        };
+        if self.in_open_elements(&last) {
+            return;
+        }
+
+        // rewind to the entry after the last marker or open element
+        let mut i = self.active_formatting_elements.len() - 1;
+        while i > 0 {
+            match self.active_formatting_elements[i - 1] {
+                FormatEntry::Marker => break,
+                FormatEntry::Element(ref elem, _) if self.in_open_elements(elem) => break,
+                _ => i -= 1,
+            }
+        }
+
+        // advance, creating each element again
+        while i < self.active_formatting_elements.len() {
+            let tag = match self.active_formatting_elements[i] {
+                FormatEntry::Element(_, ref tag) => tag.clone(),
+                FormatEntry::Marker => panic!("found a marker while reconstructing"),
+            };
+            let elem = self.insert_html_element(&tag);
+            self.active_formatting_elements[i] = FormatEntry::Element(elem, tag);
+            i += 1;
+        }
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#clear-the-list-of-active-formatting-elements-up-to-the-last-marker
+    fn clear_active_formatting_to_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting_elements.pop() {
+            if let FormatEntry::Marker = entry {
+                return;
+            }
+        }
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#adoption-agency-algorithm
+    // returns false if the end tag should be handled like any other end tag
+    fn adoption_agency(&mut self, subject: &str) -> bool {
+        // step 2
+        let current = self.current_node().clone();
+        if self.html_elem_named(&current, subject)
+            && self.position_in_active_formatting(&current).is_none()
+        {
+            self.pop();
+            return true;
+        }
+
+        // steps 3-4
+        for _ in 0..8 {
+            // step 4.3
+            let mut fe_index = None;
+            for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+                match entry {
+                    FormatEntry::Marker => break,
+                    FormatEntry::Element(ref elem, _) if self.html_elem_named(elem, subject) => {
+                        fe_index = Some(i);
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+            let fe_index = match fe_index {
+                Some(i) => i,
+                None => return false,
+            };
+            let (fe, fe_tag) = match self.active_formatting_elements[fe_index] {
+                FormatEntry::Element(ref elem, ref tag) => (elem.clone(), tag.clone()),
+                FormatEntry::Marker => unreachable!(),
+            };
+
+            // step 4.4
+            let fe_stack_index = match self
+                .open_elements_stack
+                .iter()
+                .position(|elem| self.sink.same_node(elem, &fe))
+            {
+                Some(i) => i,
+                None => {
+                    self.error(ParseHtmlError::MisnestedTag);
+                    self.active_formatting_elements.remove(fe_index);
+                    return true;
+                }
+            };
+
+            // step 4.5
+            if !self.in_scope(Scope::Default, |node| self.sink.same_node(node, &fe)) {
+                self.error(ParseHtmlError::MisnestedTag);
+                return true;
+            }
+
+            // step 4.6
+            if !self.sink.same_node(self.current_node(), &fe) {
+                self.error(ParseHtmlError::MisnestedTag);
+            }
+
+            // step 4.7
+            let furthest_block_index = match self.open_elements_stack[fe_stack_index + 1..]
+                .iter()
+                .position(|elem| self.special(elem))
+            {
+                Some(i) => fe_stack_index + 1 + i,
+                None => {
+                    // step 4.8
+                    self.open_elements_stack.truncate(fe_stack_index);
+                    self.active_formatting_elements.remove(fe_index);
+                    return true;
+                }
+            };
+            let furthest_block = self.open_elements_stack[furthest_block_index].clone();
+
+            // step 4.9
+            let common_ancestor = self.open_elements_stack[fe_stack_index - 1].clone();
+
+            // step 4.10
+            let mut bookmark = fe_index;
+
+            // steps 4.11-4.13
+            let mut node_index = furthest_block_index;
+            let mut last_node = furthest_block.clone();
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let node = self.open_elements_stack[node_index].clone();
+                if self.sink.same_node(&node, &fe) {
+                    break;
+                }
+
+                let mut node_afe_index = self.position_in_active_formatting(&node);
+                if inner_loop_counter > 3 {
+                    if let Some(i) = node_afe_index {
+                        self.active_formatting_elements.remove(i);
+                        if i < bookmark {
+                            bookmark -= 1;
+                        }
+                        node_afe_index = None;
+                    }
+                }
+                let node_afe_index = match node_afe_index {
+                    Some(i) => i,
+                    None => {
+                        self.open_elements_stack.remove(node_index);
+                        continue;
+                    }
+                };
+
+                let tag = match self.active_formatting_elements[node_afe_index] {
+                    FormatEntry::Element(_, ref tag) => tag.clone(),
+                    FormatEntry::Marker => unreachable!(),
+                };
+                let new_elem = self.create_element_for_token(&tag, Namespace::Html);
+                self.active_formatting_elements[node_afe_index] =
+                    FormatEntry::Element(new_elem.clone(), tag);
+                self.open_elements_stack[node_index] = new_elem.clone();
+
+                if self.sink.same_node(&last_node, &furthest_block) {
+                    bookmark = node_afe_index + 1;
+                }
+
+                self.sink.remove_from_parent(&last_node);
+                self.sink
+                    .append(&new_elem, NodeOrText::AppendNode(last_node.clone()));
+                last_node = new_elem;
+            }
+
+            // step 4.14
+            self.sink.remove_from_parent(&last_node);
+            let point = self.appropriate_place_for_inserting(Some(&common_ancestor));
+            self.insert_at(point, NodeOrText::AppendNode(last_node));
+
+            // steps 4.15-4.17
+            let new_elem = self.create_element_for_token(&fe_tag, Namespace::Html);
+            self.sink.reparent_children(&furthest_block, &new_elem);
+            self.sink
+                .append(&furthest_block, NodeOrText::AppendNode(new_elem.clone()));
+
+            // step 4.18
+            let fe_index = self.position_in_active_formatting(&fe).unwrap();
+            self.active_formatting_elements.remove(fe_index);
+            if fe_index < bookmark {
+                bookmark -= 1;
+            }
+            self.active_formatting_elements
+                .insert(bookmark, FormatEntry::Element(new_elem.clone(), fe_tag));
+
+            // step 4.19
+            self.remove_from_open_elements(&fe);
+            let furthest_block_index = self
+                .open_elements_stack
+                .iter()
+                .position(|elem| self.sink.same_node(elem, &furthest_block))
+                .unwrap();
+            self.open_elements_stack
+                .insert(furthest_block_index + 1, new_elem);
+        }
+        true
+    }
+}
+
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+// the elements that the stack is cleared back to in the table modes
+const TABLE_CONTEXT: &[&str] = &["table", "template", "html"];
+const TABLE_BODY_CONTEXT: &[&str] = &["tbody", "tfoot", "thead", "template", "html"];
+const TABLE_ROW_CONTEXT: &[&str] = &["tr", "template", "html"];
+
+// gets the name of an attribute on an element in the given namespace
+// attributes without a namespace are given `Namespace::Html`
+// https://html.spec.whatwg.org/multipage/parsing.html#adjust-mathml-attributes
+// https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+// https://html.spec.whatwg.org/multipage/parsing.html#adjust-foreign-attributes
+fn adjusted_attribute_name(ns: &Namespace, name: &str) -> QualName {
+    let local = match ns {
+        Namespace::Html => return QualName::new(None, Namespace::Html, name.into()),
+        Namespace::MathML if name == "definitionurl" => "definitionURL",
+        Namespace::Svg => ElementDetail::svg_attribute_name(name).unwrap_or(name),
+        _ => name,
+    };
+    match ElementDetail::foreign_attribute(name) {
+        Some((prefix, ns, local)) => QualName::new(prefix.map(String::from), ns, local.into()),
+        None => QualName::new(None, Namespace::Html, local.into()),
+    }
+}
+
+// start and end tags that close foreign content
+fn breaks_out_of_foreign_content(tag: &Tag) -> bool {
+    if tag.is_end_tag {
+        return tag.name == "br" || tag.name == "p";
+    }
+    match &tag.name[..] {
+        "b" | "big" | "blockquote" | "body" | "br" | "center" | "code" | "dd" | "div" | "dl"
+        | "dt" | "em" | "embed" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "head" | "hr" | "i"
+        | "img" | "li" | "listing" | "menu" | "meta" | "nobr" | "ol" | "p" | "pre" | "ruby"
+        | "s" | "small" | "span" | "strong" | "strike" | "sub" | "sup" | "table" | "tt" | "u"
+        | "ul" | "var" => true,
+        "font" => tag
+            .attributes
+            .iter()
+            .flatten()
+            .any(|attr| matches!(&attr.name[..], "color" | "face" | "size")),
+        _ => false,
+    }
+}
+
+// checks if two tags have the same attributes, in any order
+fn same_attributes(a: &Tag, b: &Tag) -> bool {
+    let a: Vec<_> = a.attributes.iter().flatten().collect();
+    let b: Vec<_> = b.attributes.iter().flatten().collect();
+    a.len() == b.len()
+        && a.iter()
+            .all(|x| b.iter().any(|y| x.name == y.name && x.value == y.value))
+}
+
+// splits text into its leading ASCII whitespace and the rest
+fn split_whitespace(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|c| !matches!(c, '\t' | '\n' | '\u{C}' | '\r' | ' '))
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+impl<Sink: TreeSink> HtmlParser<Sink> {
+    // https://html.spec.whatwg.org/multipage/parsing.html#tree-construction-dispatcher
+    fn tree_construction_dispatcher(&mut self, tok: Token) {
+        // the tree builder handles runs of text
+        let tok = match tok {
+            Token::Character(c) => Token::Characters(c.to_string()),
+            tok => tok,
+        };
+
+        // a newline right after `<pre>`, `<listing>` or `<textarea>` is
+        //   ignored
+        let tok = match tok {
+            Token::Characters(text) if self.ignore_lf && text.starts_with('\n') => {
+                self.ignore_lf = false;
+                if text.len() == 1 {
+                    return;
+                }
+                Token::Characters(text[1..].into())
+            }
+            tok => tok,
+        };
+        self.ignore_lf = false;
+
+        let self_closing_start_tag = match tok {
+            Token::Tag(ref tag) => tag.self_closing && !tag.is_end_tag,
+            _ => false,
+        };
+        self.self_closing_acknowledged = false;
+
+        if self.use_foreign_content_rules(&tok) {
+            self.in_foreign_content(tok);
+        } else {
+            self.process_using(self.insertion_mode, tok);
+        }
+
+        if self_closing_start_tag && !self.self_closing_acknowledged {
+            self.error(ParseHtmlError::NonVoidHtmlElementStartTagWithTrailingSolidus);
+        }
+    }
+
+    // checks if the token is processed using the rules for parsing tokens in
+    //   foreign content instead of the current insertion mode
+    fn use_foreign_content_rules(&self, tok: &Token) -> bool {
+        let node = match self.adjusted_current_node() {
+            Some(node) => node,
+            None => return false,
+        };
+        if self.elem_name(node).ns == Namespace::Html {
+            return false;
+        }
+        match tok {
+            Token::Characters(_) => {
+                !self.mathml_text_integration_point(node) && !self.html_integration_point(node)
+            }
+            Token::Tag(tag) if !tag.is_end_tag => {
+                let text_start_tag = tag.name != "mglyph" && tag.name != "malignmark";
+                !(self.mathml_text_integration_point(node) && text_start_tag
+                    || self.elem_in(node, Namespace::MathML, &["annotation-xml"])
+                        && tag.name == "svg"
+                    || self.html_integration_point(node))
+            }
+            Token::Eof => false,
+            _ => true,
+        }
+    }
+
+    fn process_using(&mut self, mode: InsertionMode, tok: Token) {
+        match mode {
+            InsertionMode::Initial => self.initial(tok),
+            InsertionMode::BeforeHtml => self.before_html(tok),
+            InsertionMode::BeforeHead => self.before_head(tok),
+            InsertionMode::InHead => self.in_head(tok),
+            InsertionMode::InHeadNoscript => self.in_head_noscript(tok),
+            InsertionMode::AfterHead => self.after_head(tok),
+            InsertionMode::InBody => self.in_body(tok),
+            InsertionMode::Text => self.text(tok),
+            InsertionMode::InTable => self.in_table(tok),
+            InsertionMode::InTableText => self.in_table_text(tok),
+            InsertionMode::InCaption => self.in_caption(tok),
+            InsertionMode::InColumnGroup => self.in_column_group(tok),
+            InsertionMode::InTableBody => self.in_table_body(tok),
+            InsertionMode::InRow => self.in_row(tok),
+            InsertionMode::InCell => self.in_cell(tok),
+            InsertionMode::InSelect => self.in_select(tok),
+            InsertionMode::InSelectInTable => self.in_select_in_table(tok),
+            InsertionMode::InTemplate => self.in_template(tok),
+            InsertionMode::AfterBody => self.after_body(tok),
+            InsertionMode::InFrameset => self.in_frameset(tok),
+            InsertionMode::AfterFrameset => self.after_frameset(tok),
+            InsertionMode::AfterAfterBody => self.after_after_body(tok),
+            InsertionMode::AfterAfterFrameset => self.after_after_frameset(tok),
+        }
+    }
+
+    fn initial(&mut self, tok: Token) {
+        // section 12.2.6.4.1
+        match tok {
+            Token::Characters(text) => {
+                let (_, rest) = split_whitespace(&text);
+                if !rest.is_empty() {
+                    self.initial_anything_else(Token::Characters(rest.into()));
+                }
+            }
+            Token::Comment(comment) => self.append_comment_to_document(comment),
+            Token::Doctype(doctype) => {
+                if !QuirksMode::doctype_is_conforming(&doctype) {
+                    self.error(ParseHtmlError::NonConformingDoctype);
+                }
+                self.set_quirks_mode(QuirksMode::from_doctype(&doctype, self.iframe_srcdoc));
+                self.sink.append_doctype_to_document(
+                    doctype.name.unwrap_or_default(),
+                    doctype.public_id.unwrap_or_default(),
+                    doctype.system_id.unwrap_or_default(),
+                );
+                self.insertion_mode = InsertionMode::BeforeHtml;
+            }
+            _ => self.initial_anything_else(tok),
+        }
+    }
+
+    fn initial_anything_else(&mut self, tok: Token) {
+        if !self.iframe_srcdoc {
+            self.error(ParseHtmlError::ExpectedDoctype);
+            self.set_quirks_mode(QuirksMode::Full);
+        }
+        self.insertion_mode = InsertionMode::BeforeHtml;
+        self.before_html(tok);
+    }
+
+    fn before_html(&mut self, tok: Token) {
+        // section 12.2.6.4.2
+        match tok {
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Comment(comment) => self.append_comment_to_document(comment),
+            Token::Characters(text) => {
+                let (_, rest) = split_whitespace(&text);
+                if !rest.is_empty() {
+                    self.before_html_anything_else(Token::Characters(rest.into()));
+                }
+            }
+            Token::Tag(tag) if Self::start_tag_with_name(&tag, "html") => {
+                let elem = self.create_element_for_token(&tag, Namespace::Html);
+                let document = self.sink.document();
+                self.sink
+                    .append(&document, NodeOrText::AppendNode(elem.clone()));
+                self.open_elements_stack.push(elem);
+                self.insertion_mode = InsertionMode::BeforeHead;
+            }
+            Token::Tag(ref tag)
+                if tag.is_end_tag
+                    && !Self::end_tag_with_names(tag, &["head", "body", "html", "br"]) =>
+            {
+                self.error(ParseHtmlError::UnexpectedEndTag);
+            }
+            _ => self.before_html_anything_else(tok),
+        }
+    }
+
+    fn before_html_anything_else(&mut self, tok: Token) {
+        let elem = self
+            .sink
+            .create_element(QualName::new(None, Namespace::Html, "html".into()), vec![]);
+        let document = self.sink.document();
+        self.sink
+            .append(&document, NodeOrText::AppendNode(elem.clone()));
+        self.open_elements_stack.push(elem);
+        self.insertion_mode = InsertionMode::BeforeHead;
+        self.before_head(tok);
+    }
+
+    fn before_head(&mut self, tok: Token) {
+        // section 12.2.6.4.3
+        match tok {
+            Token::Characters(text) => {
+                let (_, rest) = split_whitespace(&text);
+                if !rest.is_empty() {
+                    self.before_head_anything_else(Token::Characters(rest.into()));
+                }
+            }
+            Token::Comment(comment) => self.insert_comment(comment),
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "html") => {
+                self.in_body(tok);
+            }
+            Token::Tag(tag) if Self::start_tag_with_name(&tag, "head") => {
+                let head = self.insert_html_element(&tag);
+                self.head_elem = Some(head);
+                self.insertion_mode = InsertionMode::InHead;
+            }
+            Token::Tag(ref tag)
+                if tag.is_end_tag
+                    && !Self::end_tag_with_names(tag, &["head", "body", "html", "br"]) =>
+            {
+                self.error(ParseHtmlError::UnexpectedEndTag);
+            }
+            _ => self.before_head_anything_else(tok),
+        }
+    }
+
+    fn before_head_anything_else(&mut self, tok: Token) {
+        let head = self.insert_phantom_element("head");
+        self.head_elem = Some(head);
+        self.insertion_mode = InsertionMode::InHead;
+        self.in_head(tok);
+    }
+
+    fn in_head(&mut self, tok: Token) {
+        // section 12.2.6.4.4
+        match tok {
+            Token::Characters(text) => {
+                let (whitespace, rest) = split_whitespace(&text);
+                if !whitespace.is_empty() {
+                    self.insert_characters(whitespace);
+                }
+                if !rest.is_empty() {
+                    self.in_head_anything_else(Token::Characters(rest.into()));
+                }
+            }
+            Token::Comment(comment) => self.insert_comment(comment),
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "html") => {
+                self.in_body(tok);
+            }
+            Token::Tag(tag)
+                if Self::start_tag_with_names(
+                    &tag,
+                    &["base", "basefont", "bgsound", "link", "meta"],
+                ) =>
+            {
+                self.insert_html_element(&tag);
+                self.pop();
+                self.acknowledge_self_closing_flag();
+                if tag.name == "meta" {
+                    self.meta_encoding(&tag);
+                }
+                // a `<base>` in a `<template>` is inert
+                if tag.name == "base"
+                    && self.base_href.is_none()
+                    && self.template_insertion_modes.is_empty()
+                {
+                    self.base_href = tag
+                        .attributes
+                        .iter()
+                        .flatten()
+                        .find(|attr| attr.name == "href")
+                        .map(|attr| attr.value.clone());
+                }
+            }
+            Token::Tag(tag) if Self::start_tag_with_name(&tag, "title") => {
+                self.parse_generic_text_element(&tag, State::Rcdata);
+            }
+            Token::Tag(tag)
+                if (Self::start_tag_with_name(&tag, "noscript") && self.scripting)
+                    || Self::start_tag_with_names(&tag, &["noframes", "style"]) =>
+            {
+                self.parse_generic_text_element(&tag, State::Rawtext);
+            }
+            Token::Tag(tag) if Self::start_tag_with_name(&tag, "noscript") => {
+                self.insert_html_element(&tag);
+                self.insertion_mode = InsertionMode::InHeadNoscript;
+            }
+            Token::Tag(tag) if Self::start_tag_with_name(&tag, "script") => {
+                let point = self.appropriate_place_for_inserting(None);
+                let elem = self.create_element_for_token(&tag, Namespace::Html);
+                self.insert_at(point, NodeOrText::AppendNode(elem.clone()));
+                self.open_elements_stack.push(elem);
+                self.tokenizer.switch_to(State::ScriptData);
+                self.orig_insertion_mode = Some(self.insertion_mode);
+                self.insertion_mode = InsertionMode::Text;
+            }
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "template") => {
+                self.insert_html_element(tag);
+                self.active_formatting_elements.push(FormatEntry::Marker);
+                self.frameset_ok = false;
+                self.insertion_mode = InsertionMode::InTemplate;
+                self.template_insertion_modes
+                    .push(InsertionMode::InTemplate);
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "template") => {
+                if !self.template_on_stack() {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_all_implied_end_tags_thoroughly();
+                self.close_element_named("template");
+                self.clear_active_formatting_to_last_marker();
+                self.template_insertion_modes.pop();
+                self.insertion_mode = self.reset_insertion_mode();
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "head") => {
+                self.pop();
+                self.insertion_mode = InsertionMode::AfterHead;
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_names(tag, &["body", "html", "br"]) => {
+                self.in_head_anything_else(tok);
+            }
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "head") || tag.is_end_tag => {
+                self.unexpected(&tok);
+            }
+            _ => self.in_head_anything_else(tok),
+        }
+    }
+
+    fn in_head_anything_else(&mut self, tok: Token) {
+        self.pop();
+        self.insertion_mode = InsertionMode::AfterHead;
+        self.after_head(tok);
+    }
+
+    fn in_head_noscript(&mut self, tok: Token) {
+        // section 12.2.6.4.5
+        match tok {
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "html") => {
+                self.in_body(tok);
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "noscript") => {
+                self.pop();
+                self.insertion_mode = InsertionMode::InHead;
+            }
+            Token::Characters(text) => {
+                let (whitespace, rest) = split_whitespace(&text);
+                if !whitespace.is_empty() {
+                    self.in_head(Token::Characters(whitespace.into()));
+                }
+                if !rest.is_empty() {
+                    self.in_head_noscript_anything_else(Token::Characters(rest.into()));
+                }
+            }
+            Token::Comment(_) => self.in_head(tok),
+            Token::Tag(ref tag)
+                if Self::start_tag_with_names(
+                    tag,
+                    &["basefont", "bgsound", "link", "meta", "noframes", "style"],
+                ) =>
+            {
+                self.in_head(tok);
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "br") => {
+                self.in_head_noscript_anything_else(tok);
+            }
+            Token::Tag(ref tag)
+                if Self::start_tag_with_names(tag, &["head", "noscript"]) || tag.is_end_tag =>
+            {
+                self.unexpected(&tok);
+            }
+            _ => self.in_head_noscript_anything_else(tok),
+        }
+    }
+
+    fn in_head_noscript_anything_else(&mut self, tok: Token) {
+        self.unexpected(&tok);
+        self.pop();
+        self.insertion_mode = InsertionMode::InHead;
+        self.in_head(tok);
+    }
+
+    fn after_head(&mut self, tok: Token) {
+        // section 12.2.6.4.6
+        match tok {
+            Token::Characters(text) => {
+                let (whitespace, rest) = split_whitespace(&text);
+                if !whitespace.is_empty() {
+                    self.insert_characters(whitespace);
+                }
+                if !rest.is_empty() {
+                    self.after_head_anything_else(Token::Characters(rest.into()));
+                }
+            }
+            Token::Comment(comment) => self.insert_comment(comment),
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "html") => {
+                self.in_body(tok);
+            }
+            Token::Tag(tag) if Self::start_tag_with_name(&tag, "body") => {
+                self.insert_html_element(&tag);
+                self.frameset_ok = false;
+                self.insertion_mode = InsertionMode::InBody;
+            }
+            Token::Tag(tag) if Self::start_tag_with_name(&tag, "frameset") => {
+                self.insert_html_element(&tag);
+                self.insertion_mode = InsertionMode::InFrameset;
+            }
+            Token::Tag(ref tag)
+                if Self::start_tag_with_names(
+                    tag,
+                    &[
+                        "base", "basefont", "bgsound", "link", "meta", "noframes", "script",
+                        "style", "template", "title",
+                    ],
+                ) =>
+            {
+                self.error(ParseHtmlError::UnexpectedStartTag);
+                let head = self.head_elem.clone().expect("no head element");
+                self.open_elements_stack.push(head.clone());
+                self.in_head(tok);
+                self.remove_from_open_elements(&head);
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_names(tag, &["body", "html", "br"]) => {
+                self.after_head_anything_else(tok);
+            }
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "head") || tag.is_end_tag => {
+                self.unexpected(&tok);
+            }
+            _ => self.after_head_anything_else(tok),
+        }
+    }
+
+    fn after_head_anything_else(&mut self, tok: Token) {
+        self.insert_phantom_element("body");
+        self.insertion_mode = InsertionMode::InBody;
+        self.in_body(tok);
+    }
+
+    fn in_body(&mut self, tok: Token) {
+        // section 12.2.6.4.7
+        match tok {
+            Token::Characters(text) => {
+                let mut text = text;
+                if text.contains('\0') {
+                    for _ in text.matches('\0') {
+                        self.error(ParseHtmlError::UnexpectedNullCharacter);
+                    }
+                    text = text.replace('\0', "");
+                    if text.is_empty() {
+                        return;
+                    }
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_characters(&text);
+                if !split_whitespace(&text).1.is_empty() {
+                    self.frameset_ok = false;
+                }
+            }
+            Token::Comment(comment) => self.insert_comment(comment),
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Eof => {
+                if !self.template_insertion_modes.is_empty() {
+                    self.in_template(Token::Eof);
+                    return;
+                }
+                self.check_unclosed_elements();
+                self.stop_parsing();
+            }
+            Token::Tag(tag) => {
+                if tag.is_end_tag {
+                    self.in_body_end_tag(tag);
+                } else {
+                    self.in_body_start_tag(tag);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn in_body_start_tag(&mut self, mut tag: Tag) {
+        match &tag.name[..] {
+            "html" => {
+                self.error(ParseHtmlError::UnexpectedStartTag);
+                if self.template_on_stack() {
+                    return;
+                }
+                let html = self.open_elements_stack[0].clone();
+                self.add_missing_attributes(&html, &tag);
+            }
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style"
+            | "template" | "title" => self.in_head(Token::Tag(tag)),
+            "body" => {
+                self.error(ParseHtmlError::UnexpectedStartTag);
+                let body = match self.open_elements_stack.get(1) {
+                    Some(body) if self.html_elem_named(body, "body") => body.clone(),
+                    _ => return,
+                };
+                if self.template_on_stack() {
+                    return;
+                }
+                self.frameset_ok = false;
+                self.add_missing_attributes(&body, &tag);
+            }
+            "frameset" => {
+                self.error(ParseHtmlError::UnexpectedStartTag);
+                let body = match self.open_elements_stack.get(1) {
+                    Some(body) if self.html_elem_named(body, "body") => body.clone(),
+                    _ => return,
+                };
+                if !self.frameset_ok {
+                    return;
+                }
+                self.sink.remove_from_parent(&body);
+                self.open_elements_stack.truncate(1);
+                self.insert_html_element(&tag);
+                self.insertion_mode = InsertionMode::InFrameset;
+            }
+            "address" | "article" | "aside" | "blockquote" | "center" | "details" | "dialog"
+            | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer" | "header"
+            | "hgroup" | "main" | "menu" | "nav" | "ol" | "p" | "section" | "summary" | "ul" => {
+                self.close_p_element_in_button_scope();
+                self.insert_html_element(&tag);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.close_p_element_in_button_scope();
+                if self.elem_in(self.current_node(), Namespace::Html, HEADINGS) {
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    self.pop();
+                }
+                self.insert_html_element(&tag);
+            }
+            "pre" | "listing" => {
+                self.close_p_element_in_button_scope();
+                self.insert_html_element(&tag);
+                self.ignore_lf = true;
+                self.frameset_ok = false;
+            }
+            "form" => {
+                let template_on_stack = self.template_on_stack();
+                if self.form_elem.is_some() && !template_on_stack {
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    return;
+                }
+                self.close_p_element_in_button_scope();
+                let form = self.insert_html_element(&tag);
+                if !template_on_stack {
+                    self.form_elem = Some(form);
+                }
+            }
+            "li" | "dd" | "dt" => {
+                self.frameset_ok = false;
+                let closes: &[&str] = match &tag.name[..] {
+                    "li" => &["li"],
+                    _ => &["dd", "dt"],
+                };
+                for node in self.open_elements_stack.clone().iter().rev() {
+                    if self.elem_in(node, Namespace::Html, closes) {
+                        let name = self.elem_name(node).local;
+                        self.generate_implied_end_tags(Some(&name));
+                        if !self.html_elem_named(self.current_node(), &name) {
+                            self.error(ParseHtmlError::UnclosedElements);
+                        }
+                        self.pop_until_named(&[&name]);
+                        break;
+                    }
+                    if self.special(node)
+                        && !self.elem_in(node, Namespace::Html, &["address", "div", "p"])
+                    {
+                        break;
+                    }
+                }
+                self.close_p_element_in_button_scope();
+                self.insert_html_element(&tag);
+            }
+            "plaintext" => {
+                self.close_p_element_in_button_scope();
+                self.insert_html_element(&tag);
+                self.tokenizer.switch_to(State::Plaintext);
+            }
+            "button" => {
+                if self.in_scope_named(Scope::Default, "button") {
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    self.generate_implied_end_tags(None);
+                    self.pop_until_named(&["button"]);
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&tag);
+                self.frameset_ok = false;
+            }
+            "a" => {
+                let existing = self
+                    .active_formatting_elements
+                    .iter()
+                    .rev()
+                    .take_while(|entry| !matches!(entry, FormatEntry::Marker))
+                    .find_map(|entry| match entry {
+                        FormatEntry::Element(elem, _) if self.html_elem_named(elem, "a") => {
+                            Some(elem.clone())
+                        }
+                        _ => None,
+                    });
+                if let Some(existing) = existing {
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    self.adoption_agency("a");
+                    if let Some(i) = self.position_in_active_formatting(&existing) {
+                        self.active_formatting_elements.remove(i);
+                    }
+                    self.remove_from_open_elements(&existing);
+                }
+                self.reconstruct_active_formatting_elements();
+                let elem = self.insert_html_element(&tag);
+                self.push_active_formatting_element(elem, tag);
+            }
+            "b" | "big" | "code" | "em" | "font" | "i" | "s" | "small" | "strike" | "strong"
+            | "tt" | "u" => {
+                self.reconstruct_active_formatting_elements();
+                let elem = self.insert_html_element(&tag);
+                self.push_active_formatting_element(elem, tag);
+            }
+            "nobr" => {
+                self.reconstruct_active_formatting_elements();
+                if self.in_scope_named(Scope::Default, "nobr") {
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    self.adoption_agency("nobr");
+                    self.reconstruct_active_formatting_elements();
+                }
+                let elem = self.insert_html_element(&tag);
+                self.push_active_formatting_element(elem, tag);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&tag);
+                self.active_formatting_elements.push(FormatEntry::Marker);
+                self.frameset_ok = false;
+            }
+            "table" => {
+                if !matches!(self.quirks_mode, QuirksMode::Full) {
+                    self.close_p_element_in_button_scope();
+                }
+                self.insert_html_element(&tag);
+                self.frameset_ok = false;
+                self.insertion_mode = InsertionMode::InTable;
+            }
+            "area" | "br" | "embed" | "img" | "keygen" | "wbr" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&tag);
+                self.pop();
+                self.acknowledge_self_closing_flag();
+                self.frameset_ok = false;
+            }
+            "input" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&tag);
+                self.pop();
+                self.acknowledge_self_closing_flag();
+                let hidden =
+                    tag.attributes.iter().flatten().any(|attr| {
+                        attr.name == "type" && attr.value.eq_ignore_ascii_case("hidden")
+                    });
+                if !hidden {
+                    self.frameset_ok = false;
+                }
+            }
+            "param" | "source" | "track" => {
+                self.insert_html_element(&tag);
+                self.pop();
+                self.acknowledge_self_closing_flag();
+            }
+            "hr" => {
+                self.close_p_element_in_button_scope();
+                self.insert_html_element(&tag);
+                self.pop();
+                self.acknowledge_self_closing_flag();
+                self.frameset_ok = false;
+            }
+            "image" => {
+                // don't ask
+                self.error(ParseHtmlError::UnexpectedStartTag);
+                tag.name = "img".into();
+                self.in_body(Token::Tag(tag));
+            }
+            "textarea" => {
+                self.insert_html_element(&tag);
+                self.ignore_lf = true;
+                self.tokenizer.switch_to(State::Rcdata);
+                self.orig_insertion_mode = Some(self.insertion_mode);
+                self.frameset_ok = false;
+                self.insertion_mode = InsertionMode::Text;
+            }
+            "xmp" => {
+                self.close_p_element_in_button_scope();
+                self.reconstruct_active_formatting_elements();
+                self.frameset_ok = false;
+                self.parse_generic_text_element(&tag, State::Rawtext);
+            }
+            "iframe" => {
+                self.frameset_ok = false;
+                self.parse_generic_text_element(&tag, State::Rawtext);
+            }
+            "noembed" => self.parse_generic_text_element(&tag, State::Rawtext),
+            "noscript" if self.scripting => self.parse_generic_text_element(&tag, State::Rawtext),
+            "select" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&tag);
+                self.frameset_ok = false;
+                self.insertion_mode = match self.insertion_mode {
+                    InsertionMode::InTable
+                    | InsertionMode::InCaption
+                    | InsertionMode::InTableBody
+                    | InsertionMode::InRow
+                    | InsertionMode::InCell => InsertionMode::InSelectInTable,
+                    _ => InsertionMode::InSelect,
+                };
+            }
+            "optgroup" | "option" => {
+                if self.html_elem_named(self.current_node(), "option") {
+                    self.pop();
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&tag);
+            }
+            "rb" | "rtc" => {
+                if self.in_scope_named(Scope::Default, "ruby") {
+                    self.generate_implied_end_tags(None);
+                    if !self.html_elem_named(self.current_node(), "ruby") {
+                        self.error(ParseHtmlError::UnexpectedStartTag);
+                    }
+                }
+                self.insert_html_element(&tag);
+            }
+            "rp" | "rt" => {
+                if self.in_scope_named(Scope::Default, "ruby") {
+                    self.generate_implied_end_tags(Some("rtc"));
+                    if !self.elem_in(self.current_node(), Namespace::Html, &["rtc", "ruby"]) {
+                        self.error(ParseHtmlError::UnexpectedStartTag);
+                    }
+                }
+                self.insert_html_element(&tag);
+            }
+            "math" | "svg" => {
+                self.reconstruct_active_formatting_elements();
+                let ns = match &tag.name[..] {
+                    "math" => Namespace::MathML,
+                    _ => Namespace::Svg,
+                };
+                self.insert_foreign_element(&tag, ns);
+                if tag.self_closing {
+                    self.pop();
+                    self.acknowledge_self_closing_flag();
+                }
+            }
+            "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot" | "th"
+            | "thead" | "tr" => self.error(ParseHtmlError::UnexpectedStartTag),
+            _ => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&tag);
+            }
+        }
+    }
+
+    fn in_body_end_tag(&mut self, tag: Tag) {
+        match &tag.name[..] {
+            "template" => self.in_head(Token::Tag(tag)),
+            "body" | "html" => {
+                if !self.in_scope_named(Scope::Default, "body") {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.check_unclosed_elements();
+                self.insertion_mode = InsertionMode::AfterBody;
+                if tag.name == "html" {
+                    self.process_using(InsertionMode::AfterBody, Token::Tag(tag));
+                }
+            }
+            "address" | "article" | "aside" | "blockquote" | "button" | "center" | "details"
+            | "dialog" | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer"
+            | "header" | "hgroup" | "listing" | "main" | "menu" | "nav" | "ol" | "pre"
+            | "section" | "summary" | "ul" => {
+                if !self.in_scope_named(Scope::Default, &tag.name) {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.close_element_named(&tag.name);
+            }
+            "form" => {
+                if self.template_on_stack() {
+                    if !self.in_scope_named(Scope::Default, "form") {
+                        self.error(ParseHtmlError::UnexpectedEndTag);
+                        return;
+                    }
+                    self.generate_implied_end_tags(None);
+                    self.close_element_named("form");
+                    return;
+                }
+                let node = match self.form_elem.take() {
+                    Some(node) => node,
+                    None => {
+                        self.error(ParseHtmlError::UnexpectedEndTag);
+                        return;
+                    }
+                };
+                if !self.in_scope(Scope::Default, |elem| self.sink.same_node(elem, &node)) {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.sink.same_node(self.current_node(), &node) {
+                    self.error(ParseHtmlError::UnclosedElements);
+                }
+                self.remove_from_open_elements(&node);
+            }
+            "p" => {
+                if !self.in_scope_named(Scope::Button, "p") {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    self.insert_phantom_element("p");
+                }
+                self.close_p_element();
+            }
+            "li" => {
+                if !self.in_scope_named(Scope::ListItem, "li") {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(Some("li"));
+                self.close_element_named("li");
+            }
+            "dd" | "dt" => {
+                if !self.in_scope_named(Scope::Default, &tag.name) {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(Some(&tag.name));
+                self.close_element_named(&tag.name);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if !self.in_scope(Scope::Default, |node| {
+                    self.elem_in(node, Namespace::Html, HEADINGS)
+                }) {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.html_elem_named(self.current_node(), &tag.name) {
+                    self.error(ParseHtmlError::UnclosedElements);
+                }
+                self.pop_until_named(HEADINGS);
+            }
+            "a" | "b" | "big" | "code" | "em" | "font" | "i" | "nobr" | "s" | "small"
+            | "strike" | "strong" | "tt" | "u" => {
+                if !self.adoption_agency(&tag.name) {
+                    self.in_body_any_other_end_tag(&tag);
+                }
+            }
+            "applet" | "marquee" | "object" => {
+                if !self.in_scope_named(Scope::Default, &tag.name) {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.close_element_named(&tag.name);
+                self.clear_active_formatting_to_last_marker();
+            }
+            "br" => {
+                // treated as `<br>`
+                self.error(ParseHtmlError::UnexpectedEndTag);
+                self.in_body_start_tag(Tag::new_named(false, "br"));
+            }
+            _ => self.in_body_any_other_end_tag(&tag),
+        }
+    }
+
+    fn in_body_any_other_end_tag(&mut self, tag: &Tag) {
+        for i in (0..self.open_elements_stack.len()).rev() {
+            let node = self.open_elements_stack[i].clone();
+            if self.html_elem_named(&node, &tag.name) {
+                self.generate_implied_end_tags(Some(&tag.name));
+                if !self.sink.same_node(self.current_node(), &node) {
+                    self.error(ParseHtmlError::UnclosedElements);
+                }
+                self.open_elements_stack.truncate(i);
+                return;
+            }
+            if self.special(&node) {
+                self.error(ParseHtmlError::UnexpectedEndTag);
+                return;
+            }
+        }
+    }
+
+    fn text(&mut self, tok: Token) {
+        // section 12.2.6.4.8
+        match tok {
+            Token::Characters(text) => self.insert_characters(&text),
+            Token::Eof => {
+                self.error(ParseHtmlError::UnexpectedEof);
+                self.pop();
+                self.insertion_mode = self.orig_insertion_mode.take().unwrap();
+                self.process_using(self.insertion_mode, tok);
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "script") => {
+                let script = self.current_node().clone();
+                self.pop();
+                self.insertion_mode = self.orig_insertion_mode.take().unwrap();
+                self.prepare_script(script);
+            }
+            Token::Tag(ref tag) if tag.is_end_tag => {
+                self.pop();
+                self.insertion_mode = self.orig_insertion_mode.take().unwrap();
+            }
+            _ => (),
+        }
+    }
+
+    fn in_table(&mut self, tok: Token) {
+        // section 12.2.6.4.9
+        match tok {
+            Token::Characters(_)
+                if self.elem_in(
+                    self.current_node(),
+                    Namespace::Html,
+                    &["table", "tbody", "tfoot", "thead", "tr"],
+                ) =>
+            {
+                self.pending_table_text.clear();
+                self.orig_insertion_mode = Some(self.insertion_mode);
+                self.insertion_mode = InsertionMode::InTableText;
+                self.in_table_text(tok);
+            }
+            Token::Comment(comment) => self.insert_comment(comment),
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if !tag.is_end_tag => match &tag.name[..] {
+                "caption" => {
+                    self.clear_stack_back_to(TABLE_CONTEXT);
+                    self.active_formatting_elements.push(FormatEntry::Marker);
+                    self.insert_html_element(tag);
+                    self.insertion_mode = InsertionMode::InCaption;
+                }
+                "colgroup" => {
+                    self.clear_stack_back_to(TABLE_CONTEXT);
+                    self.insert_html_element(tag);
+                    self.insertion_mode = InsertionMode::InColumnGroup;
+                }
+                "col" => {
+                    self.clear_stack_back_to(TABLE_CONTEXT);
+                    self.insert_phantom_element("colgroup");
+                    self.insertion_mode = InsertionMode::InColumnGroup;
+                    self.in_column_group(tok);
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    self.clear_stack_back_to(TABLE_CONTEXT);
+                    self.insert_html_element(tag);
+                    self.insertion_mode = InsertionMode::InTableBody;
+                }
+                "td" | "th" | "tr" => {
+                    self.clear_stack_back_to(TABLE_CONTEXT);
+                    self.insert_phantom_element("tbody");
+                    self.insertion_mode = InsertionMode::InTableBody;
+                    self.in_table_body(tok);
+                }
+                "table" => {
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    if !self.in_scope_named(Scope::Table, "table") {
+                        return;
+                    }
+                    self.pop_until_named(&["table"]);
+                    self.insertion_mode = self.reset_insertion_mode();
+                    self.process_using(self.insertion_mode, tok);
+                }
+                "style" | "script" | "template" => self.in_head(tok),
+                "input" => {
+                    let hidden = tag.attributes.iter().flatten().any(|attr| {
+                        attr.name == "type" && attr.value.eq_ignore_ascii_case("hidden")
+                    });
+                    if !hidden {
+                        self.in_table_anything_else(tok);
+                        return;
+                    }
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    self.insert_html_element(tag);
+                    self.pop();
+                    self.acknowledge_self_closing_flag();
+                }
+                "form" => {
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    if self.form_elem.is_some() || self.template_on_stack() {
+                        return;
+                    }
+                    let form = self.insert_html_element(tag);
+                    self.form_elem = Some(form);
+                    self.pop();
+                }
+                _ => self.in_table_anything_else(tok),
+            },
+            Token::Tag(ref tag) => match &tag.name[..] {
+                "table" => {
+                    if !self.in_scope_named(Scope::Table, "table") {
+                        self.error(ParseHtmlError::UnexpectedEndTag);
+                        return;
+                    }
+                    self.pop_until_named(&["table"]);
+                    self.insertion_mode = self.reset_insertion_mode();
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot"
+                | "th" | "thead" | "tr" => self.error(ParseHtmlError::UnexpectedEndTag),
+                "template" => self.in_head(tok),
+                _ => self.in_table_anything_else(tok),
+            },
+            Token::Eof => self.in_body(tok),
+            _ => self.in_table_anything_else(tok),
+        }
+    }
+
+    fn in_table_anything_else(&mut self, tok: Token) {
+        self.unexpected(&tok);
+        self.foster_parenting = true;
+        self.in_body(tok);
+        self.foster_parenting = false;
+    }
+
+    fn in_table_text(&mut self, tok: Token) {
+        // section 12.2.6.4.10
+        match tok {
+            Token::Characters(text) => {
+                for c in text.chars() {
+                    if c == '\0' {
+                        self.error(ParseHtmlError::UnexpectedNullCharacter);
+                    } else {
+                        self.pending_table_text.push(c);
+                    }
+                }
+            }
+            _ => {
+                let text = std::mem::take(&mut self.pending_table_text);
+                if !split_whitespace(&text).1.is_empty() {
+                    // foster parented like any other stray content
+                    self.in_table_anything_else(Token::Characters(text));
+                } else if !text.is_empty() {
+                    self.insert_characters(&text);
+                }
+                self.insertion_mode = self.orig_insertion_mode.take().unwrap();
+                self.process_using(self.insertion_mode, tok);
+            }
+        }
+    }
+
+    fn in_caption(&mut self, tok: Token) {
+        // section 12.2.6.4.11
+        match tok {
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "caption") => {
+                self.close_caption();
+            }
+            Token::Tag(ref tag)
+                if Self::start_tag_with_names(
+                    tag,
+                    &[
+                        "caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr",
+                    ],
+                ) || Self::end_tag_with_name(tag, "table") =>
+            {
+                if self.close_caption() {
+                    self.in_table(tok);
+                }
+            }
+            Token::Tag(ref tag)
+                if Self::end_tag_with_names(
+                    tag,
+                    &[
+                        "body", "col", "colgroup", "html", "tbody", "td", "tfoot", "th", "thead",
+                        "tr",
+                    ],
+                ) =>
+            {
+                self.error(ParseHtmlError::UnexpectedEndTag);
+            }
+            _ => self.in_body(tok),
+        }
+    }
+
+    fn in_column_group(&mut self, tok: Token) {
+        // section 12.2.6.4.12
+        match tok {
+            Token::Characters(text) => {
+                let (whitespace, rest) = split_whitespace(&text);
+                if !whitespace.is_empty() {
+                    self.insert_characters(whitespace);
+                }
+                if !rest.is_empty() {
+                    self.in_column_group_anything_else(Token::Characters(rest.into()));
+                }
+            }
+            Token::Comment(comment) => self.insert_comment(comment),
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "html") => self.in_body(tok),
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "col") => {
+                self.insert_html_element(tag);
+                self.pop();
+                self.acknowledge_self_closing_flag();
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "colgroup") => {
+                if !self.html_elem_named(self.current_node(), "colgroup") {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.pop();
+                self.insertion_mode = InsertionMode::InTable;
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "col") => {
+                self.error(ParseHtmlError::UnexpectedEndTag);
+            }
+            Token::Tag(ref tag) if tag.name == "template" => self.in_head(tok),
+            Token::Eof => self.in_body(tok),
+            _ => self.in_column_group_anything_else(tok),
+        }
+    }
+
+    fn in_column_group_anything_else(&mut self, tok: Token) {
+        if !self.html_elem_named(self.current_node(), "colgroup") {
+            self.unexpected(&tok);
+            return;
+        }
+        self.pop();
+        self.insertion_mode = InsertionMode::InTable;
+        self.in_table(tok);
+    }
+
+    fn in_table_body(&mut self, tok: Token) {
+        // section 12.2.6.4.13
+        match tok {
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "tr") => {
+                self.clear_stack_back_to(TABLE_BODY_CONTEXT);
+                self.insert_html_element(tag);
+                self.insertion_mode = InsertionMode::InRow;
+            }
+            Token::Tag(ref tag) if Self::start_tag_with_names(tag, &["td", "th"]) => {
+                self.error(ParseHtmlError::UnexpectedStartTag);
+                self.clear_stack_back_to(TABLE_BODY_CONTEXT);
+                self.insert_phantom_element("tr");
+                self.insertion_mode = InsertionMode::InRow;
+                self.in_row(tok);
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_names(tag, &["tbody", "tfoot", "thead"]) => {
+                if !self.in_scope_named(Scope::Table, &tag.name) {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.clear_stack_back_to(TABLE_BODY_CONTEXT);
+                self.pop();
+                self.insertion_mode = InsertionMode::InTable;
+            }
+            Token::Tag(ref tag)
+                if Self::start_tag_with_names(
+                    tag,
+                    &["caption", "col", "colgroup", "tbody", "tfoot", "thead"],
+                ) || Self::end_tag_with_name(tag, "table") =>
+            {
+                let in_scope = self.in_scope(Scope::Table, |node| {
+                    self.elem_in(node, Namespace::Html, &["tbody", "tfoot", "thead"])
+                });
+                if !in_scope {
+                    self.unexpected(&tok);
+                    return;
+                }
+                self.clear_stack_back_to(TABLE_BODY_CONTEXT);
+                self.pop();
+                self.insertion_mode = InsertionMode::InTable;
+                self.in_table(tok);
+            }
+            Token::Tag(ref tag)
+                if Self::end_tag_with_names(
+                    tag,
+                    &[
+                        "body", "caption", "col", "colgroup", "html", "td", "th", "tr",
+                    ],
+                ) =>
+            {
+                self.error(ParseHtmlError::UnexpectedEndTag);
+            }
+            _ => self.in_table(tok),
+        }
+    }
+
+    fn in_row(&mut self, tok: Token) {
+        // section 12.2.6.4.14
+        match tok {
+            Token::Tag(ref tag) if Self::start_tag_with_names(tag, &["td", "th"]) => {
+                self.clear_stack_back_to(TABLE_ROW_CONTEXT);
+                self.insert_html_element(tag);
+                self.insertion_mode = InsertionMode::InCell;
+                self.active_formatting_elements.push(FormatEntry::Marker);
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "tr") => {
+                self.close_row();
+            }
+            Token::Tag(ref tag)
+                if Self::start_tag_with_names(
+                    tag,
+                    &[
+                        "caption", "col", "colgroup", "tbody", "tfoot", "thead", "tr",
+                    ],
+                ) || Self::end_tag_with_name(tag, "table") =>
+            {
+                if self.close_row() {
+                    self.in_table_body(tok);
+                }
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_names(tag, &["tbody", "tfoot", "thead"]) => {
+                if !self.in_scope_named(Scope::Table, &tag.name) {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                if self.close_row() {
+                    self.in_table_body(tok);
+                }
+            }
+            Token::Tag(ref tag)
+                if Self::end_tag_with_names(
+                    tag,
+                    &["body", "caption", "col", "colgroup", "html", "td", "th"],
+                ) =>
+            {
+                self.error(ParseHtmlError::UnexpectedEndTag);
+            }
+            _ => self.in_table(tok),
+        }
+    }
+
+    fn in_cell(&mut self, tok: Token) {
+        // section 12.2.6.4.15
+        match tok {
+            Token::Tag(ref tag) if Self::end_tag_with_names(tag, &["td", "th"]) => {
+                if !self.in_scope_named(Scope::Table, &tag.name) {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.close_element_named(&tag.name);
+                self.clear_active_formatting_to_last_marker();
+                self.insertion_mode = InsertionMode::InRow;
+            }
+            Token::Tag(ref tag)
+                if Self::start_tag_with_names(
+                    tag,
+                    &[
+                        "caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr",
+                    ],
+                ) =>
+            {
+                let in_scope = self.in_scope(Scope::Table, |node| {
+                    self.elem_in(node, Namespace::Html, &["td", "th"])
+                });
+                if !in_scope {
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    return;
+                }
+                self.close_cell();
+                self.in_row(tok);
+            }
+            Token::Tag(ref tag)
+                if Self::end_tag_with_names(
+                    tag,
+                    &["body", "caption", "col", "colgroup", "html"],
+                ) =>
+            {
+                self.error(ParseHtmlError::UnexpectedEndTag);
+            }
+            Token::Tag(ref tag)
+                if Self::end_tag_with_names(tag, &["table", "tbody", "tfoot", "thead", "tr"]) =>
+            {
+                if !self.in_scope_named(Scope::Table, &tag.name) {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.close_cell();
+                self.in_row(tok);
+            }
+            _ => self.in_body(tok),
+        }
+    }
+
+    fn in_select(&mut self, tok: Token) {
+        // section 12.2.6.4.16
+        match tok {
+            Token::Characters(text) => {
+                let mut text = text;
+                if text.contains('\0') {
+                    for _ in text.matches('\0') {
+                        self.error(ParseHtmlError::UnexpectedNullCharacter);
+                    }
+                    text = text.replace('\0', "");
+                }
+                if !text.is_empty() {
+                    self.insert_characters(&text);
+                }
+            }
+            Token::Comment(comment) => self.insert_comment(comment),
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if !tag.is_end_tag => match &tag.name[..] {
+                "html" => self.in_body(tok),
+                "option" => {
+                    if self.html_elem_named(self.current_node(), "option") {
+                        self.pop();
+                    }
+                    self.insert_html_element(tag);
+                }
+                "optgroup" => {
+                    if self.html_elem_named(self.current_node(), "option") {
+                        self.pop();
+                    }
+                    if self.html_elem_named(self.current_node(), "optgroup") {
+                        self.pop();
+                    }
+                    self.insert_html_element(tag);
+                }
+                "select" => {
+                    // treated as `</select>`
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    if self.in_scope_named(Scope::Select, "select") {
+                        self.pop_until_named(&["select"]);
+                        self.insertion_mode = self.reset_insertion_mode();
+                    }
+                }
+                "input" | "keygen" | "textarea" => {
+                    self.error(ParseHtmlError::UnexpectedStartTag);
+                    if !self.in_scope_named(Scope::Select, "select") {
+                        return;
+                    }
+                    self.pop_until_named(&["select"]);
+                    self.insertion_mode = self.reset_insertion_mode();
+                    self.process_using(self.insertion_mode, tok);
+                }
+                "script" | "template" => self.in_head(tok),
+                _ => self.error(ParseHtmlError::UnexpectedStartTag),
+            },
+            Token::Tag(ref tag) => match &tag.name[..] {
+                "optgroup" => {
+                    let len = self.open_elements_stack.len();
+                    if self.html_elem_named(self.current_node(), "option")
+                        && len > 1
+                        && self.html_elem_named(&self.open_elements_stack[len - 2], "optgroup")
+                    {
+                        self.pop();
+                    }
+                    if self.html_elem_named(self.current_node(), "optgroup") {
+                        self.pop();
+                    } else {
+                        self.error(ParseHtmlError::UnexpectedEndTag);
+                    }
+                }
+                "option" => {
+                    if self.html_elem_named(self.current_node(), "option") {
+                        self.pop();
+                    } else {
+                        self.error(ParseHtmlError::UnexpectedEndTag);
+                    }
+                }
+                "select" => {
+                    if !self.in_scope_named(Scope::Select, "select") {
+                        self.error(ParseHtmlError::UnexpectedEndTag);
+                        return;
+                    }
+                    self.pop_until_named(&["select"]);
+                    self.insertion_mode = self.reset_insertion_mode();
+                }
+                "template" => self.in_head(tok),
+                _ => self.error(ParseHtmlError::UnexpectedEndTag),
+            },
+            Token::Eof => self.in_body(tok),
+            _ => self.unexpected(&tok),
+        }
+    }
+
+    fn in_select_in_table(&mut self, tok: Token) {
+        // section 12.2.6.4.17
+        const TABLE_ELEMENTS: &[&str] = &[
+            "caption", "table", "tbody", "tfoot", "thead", "tr", "td", "th",
+        ];
+        match tok {
+            Token::Tag(ref tag) if Self::start_tag_with_names(tag, TABLE_ELEMENTS) => {
+                self.error(ParseHtmlError::UnexpectedStartTag);
+                self.pop_until_named(&["select"]);
+                self.insertion_mode = self.reset_insertion_mode();
+                self.process_using(self.insertion_mode, tok);
+            }
+            Token::Tag(ref tag) if Self::end_tag_with_names(tag, TABLE_ELEMENTS) => {
+                self.error(ParseHtmlError::UnexpectedEndTag);
+                if !self.in_scope_named(Scope::Table, &tag.name) {
+                    return;
+                }
+                self.pop_until_named(&["select"]);
+                self.insertion_mode = self.reset_insertion_mode();
+                self.process_using(self.insertion_mode, tok);
+            }
+            _ => self.in_select(tok),
+        }
+    }
+
+    fn in_template(&mut self, tok: Token) {
+        // section 12.2.6.4.18
+        match tok {
+            Token::Characters(_) | Token::Comment(_) | Token::Doctype(_) => self.in_body(tok),
+            Token::Tag(ref tag)
+                if Self::start_tag_with_names(
+                    tag,
+                    &[
+                        "base", "basefont", "bgsound", "link", "meta", "noframes", "script",
+                        "style", "template", "title",
+                    ],
+                ) || Self::end_tag_with_name(tag, "template") =>
+            {
+                self.in_head(tok);
+            }
+            Token::Tag(ref tag) if !tag.is_end_tag => {
+                let mode = match &tag.name[..] {
+                    "caption" | "colgroup" | "tbody" | "tfoot" | "thead" => InsertionMode::InTable,
+                    "col" => InsertionMode::InColumnGroup,
+                    "tr" => InsertionMode::InTableBody,
+                    "td" | "th" => InsertionMode::InRow,
+                    _ => InsertionMode::InBody,
+                };
+                self.template_insertion_modes.pop();
+                self.template_insertion_modes.push(mode);
+                self.insertion_mode = mode;
+                self.process_using(mode, tok);
+            }
+            Token::Tag(_) => self.error(ParseHtmlError::UnexpectedEndTag),
+            Token::Eof => {
+                if !self.template_on_stack() {
+                    self.stop_parsing();
+                    return;
+                }
+                self.error(ParseHtmlError::UnexpectedEof);
+                self.pop_until_named(&["template"]);
+                self.clear_active_formatting_to_last_marker();
+                self.template_insertion_modes.pop();
+                self.insertion_mode = self.reset_insertion_mode();
+                self.process_using(self.insertion_mode, tok);
+            }
+            _ => (),
+        }
+    }
+
+    fn after_body(&mut self, tok: Token) {
+        // section 12.2.6.4.19
+        match tok {
+            Token::Characters(text) => {
+                let (whitespace, rest) = split_whitespace(&text);
+                if !whitespace.is_empty() {
+                    self.in_body(Token::Characters(whitespace.into()));
+                }
+                if !rest.is_empty() {
+                    self.after_body_anything_else(Token::Characters(rest.into()));
+                }
+            }
+            Token::Comment(comment) => {
+                // inserted as the last child of the `<html>` element
+                let html = self.open_elements_stack[0].clone();
+                let node = self.sink.create_comment(comment.value);
+                self.sink.append(&html, NodeOrText::AppendNode(node));
+            }
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "html") => self.in_body(tok),
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "html") => {
+                if self.context_elem.is_some() {
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.insertion_mode = InsertionMode::AfterAfterBody;
+            }
+            Token::Eof => self.stop_parsing(),
+            _ => self.after_body_anything_else(tok),
+        }
+    }
+
+    fn after_body_anything_else(&mut self, tok: Token) {
+        self.unexpected(&tok);
+        self.insertion_mode = InsertionMode::InBody;
+        self.in_body(tok);
+    }
+
+    fn in_frameset(&mut self, tok: Token) {
+        // section 12.2.6.4.20
+        match tok {
+            Token::Characters(text) => self.insert_frameset_whitespace(&text),
+            Token::Comment(comment) => self.insert_comment(comment),
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if !tag.is_end_tag => match &tag.name[..] {
+                "html" => self.in_body(tok),
+                "frameset" => {
+                    self.insert_html_element(tag);
+                }
+                "frame" => {
+                    self.insert_html_element(tag);
+                    self.pop();
+                    self.acknowledge_self_closing_flag();
+                }
+                "noframes" => self.in_head(tok),
+                _ => self.error(ParseHtmlError::UnexpectedStartTag),
+            },
+            Token::Tag(ref tag) if tag.name == "frameset" => {
+                if self.open_elements_stack.len() == 1 {
+                    // the current node is the root `<html>` element
+                    self.error(ParseHtmlError::UnexpectedEndTag);
+                    return;
+                }
+                self.pop();
+                if self.context_elem.is_none()
+                    && !self.html_elem_named(self.current_node(), "frameset")
+                {
+                    self.insertion_mode = InsertionMode::AfterFrameset;
+                }
+            }
+            Token::Tag(_) => self.error(ParseHtmlError::UnexpectedEndTag),
+            Token::Eof => {
+                if self.open_elements_stack.len() != 1 {
+                    self.error(ParseHtmlError::UnexpectedEof);
+                }
+                self.stop_parsing();
+            }
+            _ => (),
+        }
+    }
+
+    fn after_frameset(&mut self, tok: Token) {
+        // section 12.2.6.4.21
+        match tok {
+            Token::Characters(text) => self.insert_frameset_whitespace(&text),
+            Token::Comment(comment) => self.insert_comment(comment),
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "html") => self.in_body(tok),
+            Token::Tag(ref tag) if Self::end_tag_with_name(tag, "html") => {
+                self.insertion_mode = InsertionMode::AfterAfterFrameset;
+            }
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "noframes") => {
+                self.in_head(tok);
+            }
+            Token::Eof => self.stop_parsing(),
+            _ => self.unexpected(&tok),
+        }
+    }
+
+    fn after_after_body(&mut self, tok: Token) {
+        // section 12.2.6.4.22
+        match tok {
+            Token::Comment(comment) => self.append_comment_to_document(comment),
+            Token::Doctype(_) => self.in_body(tok),
+            Token::Characters(text) => {
+                let (whitespace, rest) = split_whitespace(&text);
+                if !whitespace.is_empty() {
+                    self.in_body(Token::Characters(whitespace.into()));
+                }
+                if !rest.is_empty() {
+                    self.after_body_anything_else(Token::Characters(rest.into()));
+                }
+            }
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "html") => self.in_body(tok),
+            Token::Eof => self.stop_parsing(),
+            _ => self.after_body_anything_else(tok),
+        }
+    }
+
+    fn after_after_frameset(&mut self, tok: Token) {
+        // section 12.2.6.4.23
+        match tok {
+            Token::Comment(comment) => self.append_comment_to_document(comment),
+            Token::Doctype(_) => self.in_body(tok),
+            Token::Characters(text) => {
+                let whitespace: String = text
+                    .chars()
+                    .filter(|c| matches!(c, '\t' | '\n' | '\u{C}' | '\r' | ' '))
+                    .collect();
+                if whitespace.len() != text.len() {
+                    self.error(ParseHtmlError::UnexpectedCharacter);
+                }
+                if !whitespace.is_empty() {
+                    self.in_body(Token::Characters(whitespace));
+                }
+            }
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "html") => self.in_body(tok),
+            Token::Tag(ref tag) if Self::start_tag_with_name(tag, "noframes") => {
+                self.in_head(tok);
+            }
+            Token::Eof => self.stop_parsing(),
+            _ => self.unexpected(&tok),
+        }
+    }
+
+    fn in_foreign_content(&mut self, tok: Token) {
+        // section 12.2.6.5
+        match tok {
+            Token::Characters(text) => {
+                if text
+                    .chars()
+                    .any(|c| !matches!(c, '\0' | '\t' | '\n' | '\u{C}' | '\r' | ' '))
+                {
+                    self.frameset_ok = false;
+                }
+                let mut text = text;
+                if text.contains('\0') {
+                    for _ in text.matches('\0') {
+                        self.error(ParseHtmlError::UnexpectedNullCharacter);
+                    }
+                    text = text.replace('\0', "\u{FFFD}");
+                }
+                self.insert_characters(&text);
+            }
+            Token::Comment(comment) => self.insert_comment(comment),
+            Token::Doctype(_) => self.error(ParseHtmlError::UnexpectedDoctype),
+            Token::Tag(ref tag) if breaks_out_of_foreign_content(tag) => {
+                self.unexpected(&tok);
+                while !self.mathml_text_integration_point(self.current_node())
+                    && !self.html_integration_point(self.current_node())
+                    && self.elem_name(self.current_node()).ns != Namespace::Html
+                {
+                    self.pop();
+                }
+                self.process_using(self.insertion_mode, tok);
+            }
+            Token::Tag(ref tag) if !tag.is_end_tag => {
+                let ns = match self.adjusted_current_node() {
+                    Some(node) => self.elem_name(node).ns,
+                    None => Namespace::Html,
+                };
+                self.insert_foreign_element(tag, ns);
+                if tag.self_closing {
+                    let script = self.current_node().clone();
+                    self.pop();
+                    self.acknowledge_self_closing_flag();
+                    if self.elem_in(&script, Namespace::Svg, &["script"]) {
+                        self.prepare_script(script);
+                    }
+                }
+            }
+            Token::Tag(ref tag)
+                if tag.name == "script"
+                    && self.elem_in(self.current_node(), Namespace::Svg, &["script"]) =>
+            {
+                let script = self.current_node().clone();
+                self.pop();
+                self.prepare_script(script);
+            }
+            Token::Tag(tag) => self.in_foreign_content_end_tag(tag),
+            _ => (),
+        }
+    }
+
+    fn in_foreign_content_end_tag(&mut self, tag: Tag) {
+        let mut i = self.open_elements_stack.len() - 1;
+        let current_name = self.elem_name(self.current_node()).local;
+        if !current_name.eq_ignore_ascii_case(&tag.name) {
+            self.error(ParseHtmlError::UnexpectedEndTag);
+        }
+        // the topmost element is never closed here (fragment case)
+        while i > 0 {
+            let node = self.open_elements_stack[i].clone();
+            if self.elem_name(&node).local.eq_ignore_ascii_case(&tag.name) {
+                self.pop_until(&node);
+                return;
+            }
+            i -= 1;
+            if self.elem_name(&self.open_elements_stack[i]).ns == Namespace::Html {
+                self.process_using(self.insertion_mode, Token::Tag(tag));
+                return;
+            }
         }
     }
 }
@@ -0,0 +1,302 @@
+/* ============================================================================
+ * File:   preload.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Implements a lightweight form of <https://html.spec.whatwg.org/multipage/parsing.html#speculative-html-parsing>
+use crate::html::tokenizer::detail::{State, Tag, Token};
+use crate::html::tokenizer::HtmlTokenizer;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The kind of resource a preload request is for
+#[derive(Copy, Clone, PartialEq)]
+pub enum ResourceKind {
+    /// A `<script src>`
+    Script,
+    /// A `<link rel=stylesheet>`
+    Style,
+    /// An `<img src>` or a candidate in an `<img srcset>`
+    Image,
+    /// A `<link rel=preload>`; its `as` attribute is in `destination`
+    Preload,
+}
+
+/// A resource found ahead of the parser
+#[derive(Clone)]
+pub struct PreloadRequest {
+    pub kind: ResourceKind,
+    /// The URL as written in the markup
+    pub url: String,
+    /// The `href` of the first `<base>` seen, which `url` is relative to
+    pub base: Option<String>,
+    /// The `as` attribute of a `<link rel=preload>`
+    pub destination: Option<String>,
+}
+
+/// Implemented by types that receive the resources `PreloadScanner` finds,
+///   such as a loader that fetches them early
+pub trait PreloadHandler {
+    fn preload(&mut self, request: PreloadRequest);
+}
+
+/// Allows a handler to be shared between the scanner and the caller that
+///   inspects it afterwards
+impl<T: PreloadHandler> PreloadHandler for Rc<RefCell<T>> {
+    fn preload(&mut self, request: PreloadRequest) {
+        self.borrow_mut().preload(request)
+    }
+}
+
+/// Keeps every preload request
+#[derive(Default)]
+pub struct CollectPreloads {
+    pub requests: Vec<PreloadRequest>,
+}
+
+impl CollectPreloads {
+    pub fn new() -> CollectPreloads {
+        CollectPreloads { requests: vec![] }
+    }
+}
+
+impl PreloadHandler for CollectPreloads {
+    fn preload(&mut self, request: PreloadRequest) {
+        self.requests.push(request);
+    }
+}
+
+/// Finds the resources a document refers to without building a tree
+/// Only start tags are looked at. The tokenizer is switched into the raw
+///   text states after elements like `<script>` and `<style>`, but there is
+///   no tree construction, so misnested markup may be scanned differently
+///   than `HtmlParser` would parse it.
+/// `HtmlParser` runs one ahead of itself while it's blocked on a script;
+///   see `HtmlParser::set_preload_handler`.
+pub struct PreloadScanner {
+    tokenizer: HtmlTokenizer,
+    handler: Box<dyn PreloadHandler>,
+    base: Option<String>,
+    scripting: bool,
+    // how many `<template>` elements the scanner is in; their contents are
+    //   inert, so nothing in them is loaded
+    template_depth: usize,
+    // how many `<svg>` and `<math>` elements the scanner is in
+    foreign_depth: usize,
+}
+
+impl PreloadScanner {
+    pub fn new(handler: Box<dyn PreloadHandler>) -> PreloadScanner {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.set_coalesce_characters(true);
+        PreloadScanner {
+            tokenizer,
+            handler,
+            base: None,
+            scripting: true,
+            template_depth: 0,
+            foreign_depth: 0,
+        }
+    }
+
+    /// Finds the resources in a whole document
+    pub fn scan(html: &str) -> Vec<PreloadRequest> {
+        let preloads = Rc::new(RefCell::new(CollectPreloads::new()));
+        let mut scanner = PreloadScanner::new(Box::new(preloads.clone()));
+        scanner.feed(html);
+        scanner.finish();
+        let requests = preloads.borrow().requests.clone();
+        requests
+    }
+
+    /// Sets the scripting flag, which is on by default
+    /// With scripting off, scripts aren't requested and the contents of
+    ///   `<noscript>` are scanned.
+    pub fn set_scripting(&mut self, scripting: bool) {
+        self.scripting = scripting;
+    }
+
+    /// Sets the base URL for when scanning starts after a `<base>`
+    pub fn set_base(&mut self, base: Option<String>) {
+        self.base = base;
+    }
+
+    /// Gives the handler back, such as to reuse it for another scanner
+    pub fn into_handler(self) -> Box<dyn PreloadHandler> {
+        self.handler
+    }
+
+    /// Appends a chunk of input and scans as far as it can
+    pub fn feed(&mut self, chunk: &str) {
+        self.tokenizer.feed(chunk);
+        self.run();
+    }
+
+    /// Marks the end of the input and scans the rest of it
+    pub fn finish(&mut self) {
+        self.tokenizer.finish();
+        self.run();
+    }
+
+    fn run(&mut self) {
+        while let Some(tok) = self.tokenizer.next() {
+            match tok.token {
+                Token::Tag(tag) if tag.is_end_tag => self.end_tag(&tag),
+                Token::Tag(tag) => self.start_tag(&tag),
+                Token::Eof => break,
+                _ => (),
+            }
+            self.tokenizer
+                .set_adjusted_current_node_foreign(self.foreign_depth != 0);
+        }
+    }
+
+    fn start_tag(&mut self, tag: &Tag) {
+        if self.foreign_depth != 0 {
+            // only HTML elements are preloaded, and foreign elements don't
+            //   have raw text
+            if !tag.self_closing && (tag.name == "svg" || tag.name == "math") {
+                self.foreign_depth += 1;
+            }
+            return;
+        }
+
+        match &tag.name[..] {
+            "base" if self.base.is_none() && self.template_depth == 0 => {
+                self.base = attribute(tag, "href").map(String::from);
+            }
+            "img" => {
+                if let Some(src) = attribute(tag, "src") {
+                    self.request(ResourceKind::Image, src, None);
+                }
+                if let Some(srcset) = attribute(tag, "srcset") {
+                    for url in srcset_urls(srcset) {
+                        self.request(ResourceKind::Image, url, None);
+                    }
+                }
+            }
+            "link" => {
+                let rel = attribute(tag, "rel").unwrap_or("").to_ascii_lowercase();
+                let href = match attribute(tag, "href") {
+                    Some(href) => href,
+                    None => return,
+                };
+                for keyword in rel.split_ascii_whitespace() {
+                    match keyword {
+                        "stylesheet" => self.request(ResourceKind::Style, href, None),
+                        "preload" => {
+                            let destination = attribute(tag, "as").map(String::from);
+                            self.request(ResourceKind::Preload, href, destination);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            "script" if self.scripting => {
+                if let Some(src) = attribute(tag, "src") {
+                    self.request(ResourceKind::Script, src, None);
+                }
+            }
+            "template" => self.template_depth += 1,
+            "svg" | "math" if !tag.self_closing => self.foreign_depth += 1,
+            _ => (),
+        }
+
+        // the same text states the tree builder switches to
+        if let Some(state) = State::for_start_tag(&tag.name, self.scripting) {
+            self.tokenizer.switch_to(state);
+        }
+    }
+
+    fn end_tag(&mut self, tag: &Tag) {
+        match &tag.name[..] {
+            "svg" | "math" if self.foreign_depth != 0 => self.foreign_depth -= 1,
+            "template" if self.foreign_depth == 0 && self.template_depth != 0 => {
+                self.template_depth -= 1
+            }
+            _ => (),
+        }
+    }
+
+    fn request(&mut self, kind: ResourceKind, url: &str, destination: Option<String>) {
+        let url = url.trim_matches(is_whitespace);
+        if self.template_depth != 0 || url.is_empty() {
+            return;
+        }
+        self.handler.preload(PreloadRequest {
+            kind,
+            url: url.into(),
+            base: self.base.clone(),
+            destination,
+        });
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+// the value of the first attribute named `name`
+fn attribute<'a>(tag: &'a Tag, name: &str) -> Option<&'a str> {
+    tag.attributes
+        .iter()
+        .flatten()
+        .find(|attr| attr.name == name)
+        .map(|attr| &attr.value[..])
+}
+
+// the URLs of the image candidates in a `srcset`, without their descriptors
+// https://html.spec.whatwg.org/multipage/images.html#parse-a-srcset-attribute
+fn srcset_urls(srcset: &str) -> Vec<&str> {
+    let mut urls = vec![];
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c| is_whitespace(c) || c == ',');
+        if rest.is_empty() {
+            return urls;
+        }
+        let end = rest.find(is_whitespace).unwrap_or(rest.len());
+        let (url, after) = rest.split_at(end);
+        rest = after;
+
+        // a comma at the end of the URL ends the candidate
+        if url.ends_with(',') {
+            urls.push(url.trim_end_matches(','));
+            continue;
+        }
+        urls.push(url);
+
+        // skip the descriptors; commas in parentheses don't end them
+        let mut in_parens = false;
+        let mut descriptors_end = rest.len();
+        for (i, c) in rest.char_indices() {
+            match c {
+                '(' => in_parens = true,
+                ')' => in_parens = false,
+                ',' if !in_parens => {
+                    descriptors_end = i + 1;
+                    break;
+                }
+                _ => (),
+            }
+        }
+        rest = &rest[descriptors_end..];
+    }
+}
@@ -20,12 +20,69 @@
  *   Iron. If not, see <http://www.gnu.org/licenses/>.
  * ============================================================================
  */
+use crate::html::tokenizer::detail::Doctype;
+
 pub enum QuirksMode {
     None,
     Limited,
     Full,
 }
 
+impl QuirksMode {
+    /// Determines a document's mode from its DOCTYPE
+    /// An iframe srcdoc document is never in quirks mode.
+    // https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+    pub fn from_doctype(doctype: &Doctype, iframe_srcdoc: bool) -> QuirksMode {
+        if iframe_srcdoc {
+            return QuirksMode::None;
+        }
+
+        let name = doctype.name.as_deref().unwrap_or("");
+        let public_id = doctype
+            .public_id
+            .as_deref()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let system_id = doctype.system_id.as_ref().map(|id| id.to_ascii_lowercase());
+        let public_id_starts_with =
+            |prefixes: &[&str]| prefixes.iter().any(|prefix| public_id.starts_with(prefix));
+
+        let (quirks_prefixes, limited_quirks_prefixes) = match system_id {
+            Some(_) => (
+                QUIRKS_MODE_PUBLIC_ID_PREFIXES,
+                LIMITED_QUIRKS_WITH_SYSTEM_ID_PUBLIC_ID_PREFIXES,
+            ),
+            None => (
+                QUIRKS_MODE_NO_SYSTEM_ID_PUBLIC_ID_PREFIXES,
+                LIMITED_QUIRKS_PUBLIC_ID_PREFIXES,
+            ),
+        };
+
+        if doctype.force_quirks
+            || !name.eq_ignore_ascii_case(VALID_DOCTYPE_NAME)
+            || QUIRKS_MODE_PUBLIC_IDS.contains(&&public_id[..])
+            || system_id.as_deref() == Some(QUIRKS_MODE_SYSTEM_ID)
+            || public_id_starts_with(quirks_prefixes)
+        {
+            QuirksMode::Full
+        } else if public_id_starts_with(limited_quirks_prefixes) {
+            QuirksMode::Limited
+        } else {
+            QuirksMode::None
+        }
+    }
+
+    /// Checks if a DOCTYPE is one that conforming documents may use
+    pub fn doctype_is_conforming(doctype: &Doctype) -> bool {
+        doctype.name.as_deref() == Some(VALID_DOCTYPE_NAME)
+            && doctype.public_id.is_none()
+            && doctype
+                .system_id
+                .as_deref()
+                .is_none_or(|id| id == VALID_SYSTEM_ID)
+    }
+}
+
 impl Clone for QuirksMode {
     fn clone(&self) -> QuirksMode {
         match self {
@@ -0,0 +1,65 @@
+/* ============================================================================
+ * File:   script.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Implements the hooks for <https://html.spec.whatwg.org/multipage/parsing.html#scripts-that-modify-the-page-as-it-is-being-parsed>
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// What the parser should do after a script is prepared
+#[derive(Copy, Clone, PartialEq)]
+pub enum ScriptAction {
+    /// The script ran, or will run without blocking the parser
+    Continue,
+    /// The script is the pending parsing-blocking script, such as an
+    ///   external script that hasn't loaded yet
+    /// Parsing pauses until `HtmlParser::resume` is called.
+    Block,
+}
+
+/// Implemented by types that run the scripts `HtmlParser` finds
+///
+/// `H` is the handle type of the parser's `TreeSink`.
+/// `nesting_level` is the parser's script nesting level while the script is
+///   handled; it's 1 unless scripts are run re-entrantly.
+pub trait ScriptHandler<H> {
+    /// Prepares a script element after its end tag is processed
+    /// This is also called for SVG `<script>` elements, which should be
+    ///   processed according to the SVG rules; they can't block.
+    // https://html.spec.whatwg.org/multipage/scripting.html#prepare-the-script-element
+    fn prepare_script(&mut self, script: &H, nesting_level: u32) -> ScriptAction;
+
+    /// Runs the pending parsing-blocking script once it's ready
+    // https://html.spec.whatwg.org/multipage/scripting.html#execute-the-script-element
+    fn execute_script(&mut self, script: &H, nesting_level: u32);
+}
+
+/// Allows a handler to be shared between the parser and the caller that
+///   tells it when a blocking script is ready
+impl<H, T: ScriptHandler<H>> ScriptHandler<H> for Rc<RefCell<T>> {
+    fn prepare_script(&mut self, script: &H, nesting_level: u32) -> ScriptAction {
+        self.borrow_mut().prepare_script(script, nesting_level)
+    }
+
+    fn execute_script(&mut self, script: &H, nesting_level: u32) {
+        self.borrow_mut().execute_script(script, nesting_level)
+    }
+}
@@ -0,0 +1,89 @@
+/* ============================================================================
+ * File:   sink.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// The interface between `HtmlParser` and the tree it builds
+use crate::html::element::{Attribute, ExpandedName, NodeOrText, QualName};
+use crate::html::parser::quirks::QuirksMode;
+
+/// A tree that `HtmlParser` can build
+/// Nodes are referred to by handles, which should be cheap to clone.
+///   `RcDom` is the default implementation.
+pub trait TreeSink {
+    type Handle: Clone;
+
+    /// Gets the document node
+    fn document(&self) -> Self::Handle;
+
+    /// Gets the name of an element
+    /// This is only called for elements.
+    fn elem_name(&self, target: &Self::Handle) -> ExpandedName;
+
+    /// Creates an element that is not in the tree yet
+    /// A `<template>` should be given a node to hold its contents, and a
+    ///   MathML `<annotation-xml>` with an `encoding` of `text/html` or
+    ///   `application/xhtml+xml` should be marked as an HTML integration
+    ///   point.
+    fn create_element(&mut self, name: QualName, attrs: Vec<Attribute>) -> Self::Handle;
+
+    /// Creates a comment that is not in the tree yet
+    fn create_comment(&mut self, text: String) -> Self::Handle;
+
+    /// Appends a DOCTYPE to the document
+    fn append_doctype_to_document(&mut self, name: String, public_id: String, system_id: String);
+
+    /// Appends a node or text as the last child of `parent`
+    /// Text should be merged into the last child if it's a text node.
+    fn append(&mut self, parent: &Self::Handle, child: NodeOrText<Self::Handle>);
+
+    /// Inserts a node or text right before `sibling`
+    /// Text should be merged into the previous sibling if it's a text node.
+    fn append_before_sibling(&mut self, sibling: &Self::Handle, child: NodeOrText<Self::Handle>);
+
+    /// Gets the parent of a node, if it has one
+    fn parent(&self, target: &Self::Handle) -> Option<Self::Handle>;
+
+    /// Gets the children of a node
+    fn children(&self, target: &Self::Handle) -> Vec<Self::Handle>;
+
+    /// Detaches a node from its parent, if it has one
+    fn remove_from_parent(&mut self, target: &Self::Handle);
+
+    /// Moves all the children of `node` to the end of `new_parent`
+    fn reparent_children(&mut self, node: &Self::Handle, new_parent: &Self::Handle);
+
+    /// Gets the node holding a `<template>`'s contents, or `None` for any
+    ///   other node
+    fn template_contents(&self, target: &Self::Handle) -> Option<Self::Handle>;
+
+    /// Checks if two handles refer to the same node
+    fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool;
+
+    /// Checks if an element was marked as an HTML integration point when it
+    ///   was created
+    fn is_mathml_annotation_xml_integration_point(&self, target: &Self::Handle) -> bool;
+
+    /// Adds the attributes an element doesn't already have
+    fn add_attrs_if_missing(&mut self, target: &Self::Handle, attrs: Vec<Attribute>);
+
+    /// Sets the document's mode
+    fn set_quirks_mode(&mut self, mode: QuirksMode);
+}
@@ -0,0 +1,675 @@
+/* ============================================================================
+ * File:   mod.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// A streaming HTML rewriter, in the style of lol-html
+// The input is tokenized without building a DOM. Handlers registered for
+//   start tags, attributes, text, and comments can change or remove them, or
+//   insert content around them. Everything else is written out byte for byte
+//   as it came in.
+use crate::html::serializer::{escape, VOID_ELEMENTS};
+use crate::html::tokenizer::detail::{Attribute, State, Tag, Token, TokenWithLocation};
+use crate::html::tokenizer::HtmlTokenizer;
+use std::collections::HashMap;
+
+/// How content given to a handler is written out
+#[derive(Copy, Clone, PartialEq)]
+pub enum ContentType {
+    /// Written out as is
+    Html,
+    /// Escaped so it shows up as text
+    Text,
+}
+
+// content to write around, or in place of, a token
+struct Mutations {
+    before: String,
+    after: String,
+    replacement: Option<String>,
+    removed: bool,
+}
+
+impl Mutations {
+    fn new() -> Mutations {
+        Mutations {
+            before: String::new(),
+            after: String::new(),
+            replacement: None,
+            removed: false,
+        }
+    }
+}
+
+fn encode(content: &str, content_type: ContentType) -> String {
+    match content_type {
+        ContentType::Html => content.into(),
+        ContentType::Text => escape(content, false),
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Removal {
+    None,
+    Element,
+    TagsOnly,
+}
+
+/// A start tag, as given to element handlers
+pub struct Element {
+    tag: Tag,
+    // set once the attributes change, meaning the tag has to be serialized
+    //   again instead of being written out as it came in
+    tag_modified: bool,
+    mutations: Mutations,
+    removal: Removal,
+}
+
+impl Element {
+    fn new(tag: Tag) -> Element {
+        Element {
+            tag,
+            tag_modified: false,
+            mutations: Mutations::new(),
+            removal: Removal::None,
+        }
+    }
+
+    pub fn tag_name(&self) -> &str {
+        &self.tag.name
+    }
+
+    pub fn is_self_closing(&self) -> bool {
+        self.tag.self_closing
+    }
+
+    pub fn attributes(&self) -> &[Attribute] {
+        match self.tag.attributes.as_ref() {
+            Some(attrs) => attrs,
+            None => &[],
+        }
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes()
+            .iter()
+            .find(|attr| attr.name == name)
+            .map(|attr| &attr.value[..])
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute(name).is_some()
+    }
+
+    /// Sets the value of an attribute, adding it if it doesn't exist
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.tag_modified = true;
+        let attrs = self.tag.attributes.get_or_insert_with(Vec::new);
+        match attrs.iter_mut().find(|attr| attr.name == name) {
+            Some(attr) => attr.value = value.into(),
+            None => {
+                let mut attr = Attribute::new();
+                attr.name = name.into();
+                attr.value = value.into();
+                attrs.push(attr);
+            }
+        }
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) {
+        if let Some(attrs) = self.tag.attributes.as_mut() {
+            let len = attrs.len();
+            attrs.retain(|attr| attr.name != name);
+            self.tag_modified |= attrs.len() != len;
+        }
+    }
+
+    /// Inserts content before the start tag
+    pub fn before(&mut self, content: &str, content_type: ContentType) {
+        self.mutations
+            .before
+            .push_str(&encode(content, content_type));
+    }
+
+    /// Inserts content right after the start tag, i.e. at the start of the
+    ///   element's content
+    pub fn prepend(&mut self, content: &str, content_type: ContentType) {
+        self.mutations
+            .after
+            .push_str(&encode(content, content_type));
+    }
+
+    /// Removes the element along with its content
+    pub fn remove(&mut self) {
+        self.removal = Removal::Element;
+    }
+
+    /// Removes the element's start and end tags, but keeps its content
+    pub fn remove_and_keep_content(&mut self) {
+        self.removal = Removal::TagsOnly;
+    }
+
+    pub fn removed(&self) -> bool {
+        self.removal != Removal::None
+    }
+
+    fn serialize(&self) -> String {
+        let mut html = format!("<{}", self.tag.name);
+        for attr in self.attributes() {
+            html.push(' ');
+            html.push_str(&attr.name);
+            if !attr.value.is_empty() {
+                html.push_str("=\"");
+                html.push_str(&escape(&attr.value, true));
+                html.push('"');
+            }
+        }
+        if self.tag.self_closing {
+            html.push_str(" /");
+        }
+        html.push('>');
+        html
+    }
+}
+
+/// An attribute of a start tag, as given to attribute handlers
+pub struct ElementAttribute {
+    tag_name: String,
+    name: String,
+    value: String,
+    modified: bool,
+    removed: bool,
+}
+
+impl ElementAttribute {
+    /// The name of the tag this attribute belongs to
+    pub fn tag_name(&self) -> &str {
+        &self.tag_name
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn set_value(&mut self, value: &str) {
+        self.value = value.into();
+        self.modified = true;
+    }
+
+    pub fn remove(&mut self) {
+        self.removed = true;
+    }
+}
+
+/// A run of text, as given to text handlers
+/// Character references have been decoded.
+/// One text node can be given in several chunks, such as when it spans
+///   chunks of input.
+pub struct TextChunk {
+    text: String,
+    mutations: Mutations,
+}
+
+impl TextChunk {
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn before(&mut self, content: &str, content_type: ContentType) {
+        self.mutations
+            .before
+            .push_str(&encode(content, content_type));
+    }
+
+    pub fn after(&mut self, content: &str, content_type: ContentType) {
+        self.mutations
+            .after
+            .push_str(&encode(content, content_type));
+    }
+
+    pub fn replace(&mut self, content: &str, content_type: ContentType) {
+        self.mutations.replacement = Some(encode(content, content_type));
+    }
+
+    pub fn remove(&mut self) {
+        self.mutations.removed = true;
+    }
+
+    pub fn removed(&self) -> bool {
+        self.mutations.removed
+    }
+}
+
+/// A comment, as given to comment handlers
+pub struct CommentChunk {
+    text: String,
+    mutations: Mutations,
+}
+
+impl CommentChunk {
+    /// The text between `<!--` and `-->`
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.into();
+        self.mutations.replacement = Some(format!("<!--{}-->", text));
+    }
+
+    pub fn before(&mut self, content: &str, content_type: ContentType) {
+        self.mutations
+            .before
+            .push_str(&encode(content, content_type));
+    }
+
+    pub fn after(&mut self, content: &str, content_type: ContentType) {
+        self.mutations
+            .after
+            .push_str(&encode(content, content_type));
+    }
+
+    pub fn replace(&mut self, content: &str, content_type: ContentType) {
+        self.mutations.replacement = Some(encode(content, content_type));
+    }
+
+    pub fn remove(&mut self) {
+        self.mutations.removed = true;
+    }
+
+    pub fn removed(&self) -> bool {
+        self.mutations.removed
+    }
+}
+
+type ElementHandler<'h> = Box<dyn FnMut(&mut Element) + 'h>;
+type AttributeHandler<'h> = Box<dyn FnMut(&mut ElementAttribute) + 'h>;
+type TextHandler<'h> = Box<dyn FnMut(&mut TextChunk) + 'h>;
+type CommentHandler<'h> = Box<dyn FnMut(&mut CommentChunk) + 'h>;
+
+/// Rewrites HTML as it streams through
+///
+/// Input is given with `write` and the rewritten HTML is passed to the output
+///   function as soon as it's known; call `end` after the last chunk.
+/// Element and attribute handlers are registered for a tag or attribute
+///   name, or `*` for all of them.
+pub struct HtmlRewriter<'h> {
+    tokenizer: HtmlTokenizer,
+    output: Box<dyn FnMut(&str) + 'h>,
+
+    element_handlers: Vec<(String, ElementHandler<'h>)>,
+    attribute_handlers: Vec<(String, AttributeHandler<'h>)>,
+    text_handlers: Vec<TextHandler<'h>>,
+    comment_handlers: Vec<CommentHandler<'h>>,
+
+    // input that hasn't been written out yet; `pending_start` is the offset
+    //   of its first byte in the whole input, and `pending_pos` is how much of
+    //   it has been written out or skipped
+    pending: String,
+    pending_start: usize,
+    pending_pos: usize,
+
+    // how many elements of each name are open, to match up end tags
+    open_elements: HashMap<String, usize>,
+    // the element being removed along with its content, with its nesting
+    //   level in `open_elements`
+    removing: Option<(String, usize)>,
+    // elements whose end tag will be removed, with their nesting level
+    unwrapped: Vec<(String, usize)>,
+    // how many `<svg>` and `<math>` elements are open
+    foreign_depth: usize,
+    scripting: bool,
+}
+
+impl<'h> HtmlRewriter<'h> {
+    pub fn new<F: FnMut(&str) + 'h>(output: F) -> HtmlRewriter<'h> {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.set_coalesce_characters(true);
+        HtmlRewriter {
+            tokenizer,
+            output: Box::new(output),
+            element_handlers: vec![],
+            attribute_handlers: vec![],
+            text_handlers: vec![],
+            comment_handlers: vec![],
+            pending: String::new(),
+            pending_start: 0,
+            pending_pos: 0,
+            open_elements: HashMap::new(),
+            removing: None,
+            unwrapped: vec![],
+            foreign_depth: 0,
+            scripting: true,
+        }
+    }
+
+    /// Sets the scripting flag, which is on by default
+    /// With scripting on, the contents of `<noscript>` are raw text, as they
+    ///   are to a browser running scripts.
+    pub fn set_scripting(&mut self, scripting: bool) {
+        self.scripting = scripting;
+    }
+
+    /// Registers a handler for start tags with the given name (or `*`)
+    pub fn on_element<F: FnMut(&mut Element) + 'h>(&mut self, tag_name: &str, handler: F) {
+        self.element_handlers
+            .push((tag_name.to_ascii_lowercase(), Box::new(handler)));
+    }
+
+    /// Registers a handler for attributes with the given name (or `*`)
+    /// Attribute handlers run after the element handlers.
+    pub fn on_attribute<F: FnMut(&mut ElementAttribute) + 'h>(&mut self, name: &str, handler: F) {
+        self.attribute_handlers
+            .push((name.to_ascii_lowercase(), Box::new(handler)));
+    }
+
+    pub fn on_text<F: FnMut(&mut TextChunk) + 'h>(&mut self, handler: F) {
+        self.text_handlers.push(Box::new(handler));
+    }
+
+    pub fn on_comment<F: FnMut(&mut CommentChunk) + 'h>(&mut self, handler: F) {
+        self.comment_handlers.push(Box::new(handler));
+    }
+
+    /// Rewrites a chunk of input
+    /// Panics if `end` was already called.
+    pub fn write(&mut self, chunk: &str) {
+        self.tokenizer.feed(chunk);
+        self.pending.push_str(chunk);
+        self.process_tokens();
+    }
+
+    /// Marks the end of the input, writing out everything that's left
+    pub fn end(&mut self) {
+        self.tokenizer.finish();
+        self.process_tokens();
+        let end = self.pending_start + self.pending.len();
+        self.pass_through(end);
+    }
+
+    fn process_tokens(&mut self) {
+        while let Some(token) = self.tokenizer.next() {
+            self.process(token);
+        }
+
+        // forget what's been written out
+        self.pending.drain(..self.pending_pos);
+        self.pending_start += self.pending_pos;
+        self.pending_pos = 0;
+    }
+
+    fn process(&mut self, token: TokenWithLocation) {
+        let start = self.tokenizer.source_offset(token.start.file_offset);
+        let end = self.tokenizer.source_offset(token.end.file_offset);
+
+        // anything between tokens (such as `</>`) is left alone
+        self.pass_through(start);
+        match token.token {
+            Token::Tag(tag) => {
+                if tag.is_end_tag {
+                    self.end_tag(tag, end);
+                } else {
+                    self.start_tag(tag, end);
+                }
+            }
+            Token::Characters(text) => self.text(text, end),
+            Token::Character(c) => self.text(c.to_string(), end),
+            Token::Comment(comment) => self.comment(comment.value, end),
+            Token::Attribute(_) | Token::Doctype(_) | Token::Eof => self.pass_through(end),
+        }
+    }
+
+    fn start_tag(&mut self, tag: Tag, end: usize) {
+        self.update_tokenizer_state(&tag);
+        let level = self.open_element(&tag);
+        if self.removing.is_some() {
+            self.skip(end);
+            return;
+        }
+
+        let name = tag.name.clone();
+        let mut element = Element::new(tag);
+        for (tag_name, handler) in self.element_handlers.iter_mut() {
+            if tag_name == "*" || *tag_name == name {
+                handler(&mut element);
+            }
+        }
+        self.run_attribute_handlers(&mut element);
+
+        self.write_out(&element.mutations.before);
+        match element.removal {
+            Removal::None => {
+                if element.tag_modified {
+                    let html = element.serialize();
+                    self.write_out(&html);
+                    self.skip(end);
+                } else {
+                    self.pass_through(end);
+                }
+            }
+            Removal::Element => {
+                self.skip(end);
+                if let Some(level) = level {
+                    self.removing = Some((name, level));
+                }
+            }
+            Removal::TagsOnly => {
+                self.skip(end);
+                if let Some(level) = level {
+                    self.unwrapped.push((name, level));
+                }
+            }
+        }
+        self.write_out(&element.mutations.after);
+    }
+
+    fn run_attribute_handlers(&mut self, element: &mut Element) {
+        if self.attribute_handlers.is_empty() {
+            return;
+        }
+
+        let mut attrs = match element.tag.attributes.take() {
+            Some(attrs) => attrs,
+            None => return,
+        };
+        let mut modified = false;
+        attrs.retain_mut(|attr| {
+            let mut handled = ElementAttribute {
+                tag_name: element.tag.name.clone(),
+                name: attr.name.clone(),
+                value: attr.value.clone(),
+                modified: false,
+                removed: false,
+            };
+            for (name, handler) in self.attribute_handlers.iter_mut() {
+                if name == "*" || *name == attr.name {
+                    handler(&mut handled);
+                }
+            }
+            if handled.modified {
+                attr.value = handled.value;
+            }
+            modified |= handled.modified || handled.removed;
+            !handled.removed
+        });
+        element.tag.attributes = Some(attrs);
+        element.tag_modified |= modified;
+    }
+
+    fn end_tag(&mut self, tag: Tag, end: usize) {
+        self.update_tokenizer_state(&tag);
+        let level = self.close_element(&tag.name);
+
+        if let Some((name, removing_level)) = self.removing.as_ref() {
+            if *name == tag.name && level == Some(*removing_level) {
+                self.removing = None;
+            }
+            self.skip(end);
+            return;
+        }
+
+        let unwrapped = self.unwrapped.iter().rposition(|(name, unwrapped_level)| {
+            *name == tag.name && level == Some(*unwrapped_level)
+        });
+        match unwrapped {
+            Some(index) => {
+                self.unwrapped.remove(index);
+                self.skip(end);
+            }
+            None => self.pass_through(end),
+        }
+    }
+
+    fn text(&mut self, text: String, end: usize) {
+        if self.removing.is_some() {
+            self.skip(end);
+            return;
+        }
+        if self.text_handlers.is_empty() {
+            self.pass_through(end);
+            return;
+        }
+
+        let mut chunk = TextChunk {
+            text,
+            mutations: Mutations::new(),
+        };
+        for handler in self.text_handlers.iter_mut() {
+            handler(&mut chunk);
+        }
+        self.write_mutated(&chunk.mutations, end);
+    }
+
+    fn comment(&mut self, text: String, end: usize) {
+        if self.removing.is_some() {
+            self.skip(end);
+            return;
+        }
+        if self.comment_handlers.is_empty() {
+            self.pass_through(end);
+            return;
+        }
+
+        let mut chunk = CommentChunk {
+            text,
+            mutations: Mutations::new(),
+        };
+        for handler in self.comment_handlers.iter_mut() {
+            handler(&mut chunk);
+        }
+        self.write_mutated(&chunk.mutations, end);
+    }
+
+    // writes out a token ending at `end` with the changes made to it
+    fn write_mutated(&mut self, mutations: &Mutations, end: usize) {
+        self.write_out(&mutations.before);
+        if mutations.removed {
+            self.skip(end);
+        } else if let Some(replacement) = mutations.replacement.as_ref() {
+            self.write_out(replacement);
+            self.skip(end);
+        } else {
+            self.pass_through(end);
+        }
+        self.write_out(&mutations.after);
+    }
+
+    // switches the tokenizer's state the way tree construction would
+    // this is only an approximation since there's no DOM to go by, but it's
+    //   right for all but the most broken markup
+    fn update_tokenizer_state(&mut self, tag: &Tag) {
+        let name = &tag.name[..];
+        if name == "svg" || name == "math" {
+            if tag.is_end_tag {
+                self.foreign_depth = self.foreign_depth.saturating_sub(1);
+            } else if !tag.self_closing {
+                self.foreign_depth += 1;
+            }
+            self.tokenizer
+                .set_adjusted_current_node_foreign(self.foreign_depth != 0);
+        }
+
+        if tag.is_end_tag || self.foreign_depth != 0 {
+            return;
+        }
+        if let Some(state) = State::for_start_tag(name, self.scripting) {
+            self.tokenizer.switch_to(state);
+        }
+    }
+
+    // returns the nesting level of the opened element, or `None` if it won't
+    //   have an end tag
+    fn open_element(&mut self, tag: &Tag) -> Option<usize> {
+        // self-closing tags only mean anything in foreign content
+        let foreign_self_closing = tag.self_closing && self.foreign_depth != 0;
+        if foreign_self_closing || VOID_ELEMENTS.contains(&&tag.name[..]) {
+            return None;
+        }
+        let count = self.open_elements.entry(tag.name.clone()).or_insert(0);
+        *count += 1;
+        Some(*count)
+    }
+
+    // returns the nesting level of the closed element, or `None` if there
+    //   wasn't one open
+    fn close_element(&mut self, name: &str) -> Option<usize> {
+        match self.open_elements.get_mut(name) {
+            Some(count) if *count != 0 => {
+                *count -= 1;
+                Some(*count + 1)
+            }
+            _ => None,
+        }
+    }
+
+    fn write_out(&mut self, html: &str) {
+        if !html.is_empty() {
+            (self.output)(html);
+        }
+    }
+
+    // writes out the input up to `offset` as it came in, unless it's part of
+    //   a removed element
+    fn pass_through(&mut self, offset: usize) {
+        if self.removing.is_some() {
+            self.skip(offset);
+            return;
+        }
+        let end = offset
+            .saturating_sub(self.pending_start)
+            .min(self.pending.len());
+        if end > self.pending_pos {
+            (self.output)(&self.pending[self.pending_pos..end]);
+            self.pending_pos = end;
+        }
+    }
+
+    // drops the input up to `offset`
+    fn skip(&mut self, offset: usize) {
+        let end = offset
+            .saturating_sub(self.pending_start)
+            .min(self.pending.len());
+        self.pending_pos = self.pending_pos.max(end);
+    }
+}
@@ -0,0 +1,132 @@
+/* ============================================================================
+ * File:   dump.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Implements the tree format of <https://github.com/html5lib/html5lib-tests/tree/master/tree-construction>
+use crate::html::element::{Handle, NodeData, QualName, RcDom};
+use crate::infra::namespace::Namespace;
+
+/// Dumps a document in the html5lib-tests tree format
+/// Each node is a line starting with `| `, indented two spaces per
+///   ancestor. The output of the test suite's `#document` section is the
+///   same, with a newline after every line.
+pub fn dump_document(dom: &RcDom) -> String {
+    let mut out = String::new();
+    dump_children(&dom.document, 0, &mut out);
+    out
+}
+
+/// Dumps a list of nodes, such as those returned by
+///   `HtmlParser::parse_fragment`, as if they were children of a document
+pub fn dump_nodes(nodes: &[Handle]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        dump_node(node, 0, &mut out);
+    }
+    out
+}
+
+fn dump_children(node: &Handle, depth: usize, out: &mut String) {
+    for child in node.children.borrow().iter() {
+        dump_node(child, depth, out);
+    }
+}
+
+fn push_line(depth: usize, line: &str, out: &mut String) {
+    out.push_str("| ");
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+    out.push_str(line);
+    out.push('\n');
+}
+
+fn dump_node(node: &Handle, depth: usize, out: &mut String) {
+    match node.data {
+        NodeData::Comment { ref content } => {
+            push_line(depth, &format!("<!-- {} -->", content), out)
+        }
+        NodeData::Doctype {
+            ref name,
+            ref public_id,
+            ref system_id,
+        } => {
+            let line = if public_id.is_empty() && system_id.is_empty() {
+                format!("<!DOCTYPE {}>", name)
+            } else {
+                format!("<!DOCTYPE {} \"{}\" \"{}\">", name, public_id, system_id)
+            };
+            push_line(depth, &line, out);
+        }
+        NodeData::Document => dump_children(node, depth, out),
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ref template_contents,
+            ..
+        } => {
+            push_line(depth, &format!("<{}>", element_name(name)), out);
+
+            // sorted by UTF-16 code unit, as in the DOM
+            let mut attrs: Vec<(String, String)> = attrs
+                .borrow()
+                .iter()
+                .map(|attr| (attribute_name(&attr.name), attr.value.clone()))
+                .collect();
+            attrs.sort_by(|(x, _), (y, _)| x.encode_utf16().cmp(y.encode_utf16()));
+            for (name, value) in attrs {
+                push_line(depth + 1, &format!("{}=\"{}\"", name, value), out);
+            }
+
+            if let Some(contents) = template_contents {
+                push_line(depth + 1, "content", out);
+                dump_children(contents, depth + 2, out);
+            }
+            dump_children(node, depth + 1, out);
+        }
+        NodeData::ProcessingInstruction {
+            ref target,
+            ref content,
+        } => push_line(depth, &format!("<?{} {}>", target, content), out),
+        NodeData::Text { ref content } => {
+            push_line(depth, &format!("\"{}\"", content.borrow()), out)
+        }
+    }
+}
+
+// the local name, with a designator for the SVG and MathML namespaces
+fn element_name(name: &QualName) -> String {
+    match name.ns {
+        Namespace::Svg => format!("svg {}", name.local),
+        Namespace::MathML => format!("math {}", name.local),
+        _ => name.local.clone(),
+    }
+}
+
+// attributes without a namespace have `Namespace::Html`
+fn attribute_name(name: &QualName) -> String {
+    match name.ns {
+        Namespace::XLink => format!("xlink {}", name.local),
+        Namespace::Xml => format!("xml {}", name.local),
+        Namespace::Xmlns => format!("xmlns {}", name.local),
+        _ => name.local.clone(),
+    }
+}
@@ -0,0 +1,195 @@
+/* ============================================================================
+ * File:   mod.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+pub mod dump;
+
+// Implements <https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments>
+use crate::html::element::{Handle, NodeData, QualName, RcDom};
+use crate::infra::namespace::Namespace;
+
+/// The HTML elements that never have children or an end tag, so they're
+///   serialized as just a start tag
+pub const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+// elements whose text is written out as is
+// `<noscript>` is too, but only when scripting is enabled
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "style",
+    "script",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+];
+
+/// Serializes the children of a node as HTML, like `innerHTML`
+/// A `<template>`'s contents are serialized instead of its children.
+/// `scripting` should match the flag the tree was parsed with. With it off,
+///   the text in `<noscript>` is escaped, as the parser would've made it
+///   text and not markup; serializing it as is could create elements when
+///   the output is parsed again.
+pub fn serialize(node: &Handle, scripting: bool) -> String {
+    let mut out = String::new();
+    serialize_children(node, scripting, &mut out);
+    out
+}
+
+/// Serializes a node and its children as HTML, like `outerHTML`
+/// See `serialize` for `scripting`.
+pub fn serialize_outer(node: &Handle, scripting: bool) -> String {
+    let mut out = String::new();
+    serialize_node(node, false, scripting, &mut out);
+    out
+}
+
+/// Serializes a whole document, including its DOCTYPE
+/// See `serialize` for `scripting`.
+pub fn serialize_document(dom: &RcDom, scripting: bool) -> String {
+    serialize(&dom.document, scripting)
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#escapingString
+pub fn escape(text: &str, attribute_mode: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '\u{A0}' => escaped.push_str("&nbsp;"),
+            '"' if attribute_mode => escaped.push_str("&quot;"),
+            '<' if !attribute_mode => escaped.push_str("&lt;"),
+            '>' if !attribute_mode => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn serialize_children(node: &Handle, scripting: bool, out: &mut String) {
+    let node = match node.data {
+        NodeData::Element {
+            template_contents: Some(ref contents),
+            ..
+        } => contents,
+        _ => node,
+    };
+    let raw_text = match node.data {
+        NodeData::Element { ref name, .. } => {
+            name.ns == Namespace::Html
+                && (RAW_TEXT_ELEMENTS.contains(&&name.local[..])
+                    || (scripting && name.local == "noscript"))
+        }
+        _ => false,
+    };
+    for child in node.children.borrow().iter() {
+        serialize_node(child, raw_text, scripting, out);
+    }
+}
+
+// `raw_text` is set if the parent's text isn't escaped
+fn serialize_node(node: &Handle, raw_text: bool, scripting: bool, out: &mut String) {
+    match node.data {
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } => {
+            let tag_name = element_name(name);
+            out.push('<');
+            out.push_str(&tag_name);
+            for attr in attrs.borrow().iter() {
+                out.push(' ');
+                out.push_str(&attribute_name(&attr.name));
+                out.push_str("=\"");
+                out.push_str(&escape(&attr.value, true));
+                out.push('"');
+            }
+            out.push('>');
+
+            if name.ns == Namespace::Html && VOID_ELEMENTS.contains(&&name.local[..]) {
+                return;
+            }
+            serialize_children(node, scripting, out);
+            out.push_str("</");
+            out.push_str(&tag_name);
+            out.push('>');
+        }
+        NodeData::Text { ref content } => {
+            if raw_text {
+                out.push_str(&content.borrow());
+            } else {
+                out.push_str(&escape(&content.borrow(), false));
+            }
+        }
+        NodeData::Comment { ref content } => {
+            out.push_str("<!--");
+            out.push_str(content);
+            out.push_str("-->");
+        }
+        NodeData::ProcessingInstruction {
+            ref target,
+            ref content,
+        } => {
+            out.push_str("<?");
+            out.push_str(target);
+            out.push(' ');
+            out.push_str(content);
+            out.push('>');
+        }
+        NodeData::Doctype { ref name, .. } => {
+            out.push_str("<!DOCTYPE ");
+            out.push_str(name);
+            out.push('>');
+        }
+        NodeData::Document => serialize_children(node, scripting, out),
+    }
+}
+
+// elements in the HTML, MathML and SVG namespaces are written with just
+//   their local name
+fn element_name(name: &QualName) -> String {
+    match (&name.ns, &name.prefix) {
+        (Namespace::Html, _) | (Namespace::MathML, _) | (Namespace::Svg, _) | (_, None) => {
+            name.local.clone()
+        }
+        (_, Some(prefix)) => format!("{}:{}", prefix, name.local),
+    }
+}
+
+// attributes without a namespace have `Namespace::Html`
+// https://html.spec.whatwg.org/multipage/parsing.html#attribute's-serialised-name
+fn attribute_name(name: &QualName) -> String {
+    match name.ns {
+        Namespace::Html => name.local.clone(),
+        Namespace::Xml => format!("xml:{}", name.local),
+        Namespace::Xmlns if name.local == "xmlns" => "xmlns".into(),
+        Namespace::Xmlns => format!("xmlns:{}", name.local),
+        Namespace::XLink => format!("xlink:{}", name.local),
+        _ => match name.prefix {
+            Some(ref prefix) => format!("{}:{}", prefix, name.local),
+            None => name.local.clone(),
+        },
+    }
+}
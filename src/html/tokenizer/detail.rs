@@ -131,6 +131,16 @@ impl Tag {
         }
     }
 
+    /// Creates a tag with a name and no attributes
+    pub fn new_named(end_tag: bool, name: &str) -> Tag {
+        Tag {
+            name: name.into(),
+            self_closing: false,
+            is_end_tag: end_tag,
+            attributes: None,
+        }
+    }
+
     pub fn create_attribute(&mut self) {
         // create the list if needed
         if self.attributes.is_none() {
@@ -139,19 +149,45 @@ impl Tag {
         self.attributes.as_mut().unwrap().push(Attribute::new());
     }
 
+    // the current (last) attribute, created if there isn't one
+    fn cur_attr(&mut self) -> &mut Attribute {
+        let attrs = self.attributes.get_or_insert_with(Vec::new);
+        if attrs.is_empty() {
+            attrs.push(Attribute::new());
+        }
+        let last = attrs.len() - 1;
+        &mut attrs[last]
+    }
+
     pub fn append_to_cur_attr_name(&mut self, c: char) {
-        let last = self.attributes.as_mut().unwrap().last_mut();
-        last.unwrap().name.push(c);
+        self.cur_attr().name.push(c);
     }
 
     pub fn append_to_cur_attr_value(&mut self, c: char) {
-        let last = self.attributes.as_mut().unwrap().last_mut();
-        last.unwrap().value.push(c);
+        self.cur_attr().value.push(c);
     }
 
     pub fn append_to_cur_attr_value_str(&mut self, chars: &str) {
-        let last = self.attributes.as_mut().unwrap().last_mut();
-        last.unwrap().value.push_str(chars);
+        self.cur_attr().value.push_str(chars);
+    }
+
+    /// Checks if the current (last) attribute has the same name as an
+    ///   earlier one
+    pub fn cur_attr_is_duplicate(&self) -> bool {
+        match self
+            .attributes
+            .as_ref()
+            .and_then(|attrs| attrs.split_last())
+        {
+            Some((cur, earlier)) => earlier.iter().any(|attr| attr.name == cur.name),
+            None => false,
+        }
+    }
+
+    pub fn remove_cur_attr(&mut self) {
+        if let Some(attrs) = self.attributes.as_mut() {
+            attrs.pop();
+        }
     }
 
     pub fn set_self_closing_flag(&mut self) {
@@ -170,6 +206,9 @@ impl Clone for Tag {
     }
 }
 
+/// A location in the source text
+/// `file_offset` is in bytes; `line` and `line_offset` (in characters) are
+///   zero-based.
 #[derive(Clone)]
 pub struct TokenMetadata {
     pub file_offset: usize,
@@ -187,10 +226,26 @@ impl TokenMetadata {
     }
 }
 
+/// A token along with where its source text starts and ends
+#[derive(Clone)]
+pub struct TokenWithLocation {
+    pub token: Token,
+    pub start: TokenMetadata,
+    pub end: TokenMetadata,
+}
+
+impl TokenWithLocation {
+    pub fn new(token: Token, start: TokenMetadata, end: TokenMetadata) -> TokenWithLocation {
+        TokenWithLocation { token, start, end }
+    }
+}
+
 #[derive(Clone)]
 pub enum Token {
     Attribute(Attribute),
     Character(char),
+    /// A run of characters; only emitted when `HtmlTokenizer` coalesces them
+    Characters(String),
     Comment(Comment),
     Doctype(Doctype),
     Eof,
@@ -290,3 +345,20 @@ pub enum State {
     DecimalCharacterReference,
     NumericCharacterReferenceEnd,
 }
+
+impl State {
+    /// Gets the state tree construction switches the tokenizer to after a
+    ///   start tag for the HTML element `name`, if it switches at all
+    /// The contents of `<noscript>` are only raw text when scripting is
+    ///   enabled.
+    pub fn for_start_tag(name: &str, scripting: bool) -> Option<State> {
+        match name {
+            "title" | "textarea" => Some(State::Rcdata),
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" => Some(State::Rawtext),
+            "noscript" if scripting => Some(State::Rawtext),
+            "script" => Some(State::ScriptData),
+            "plaintext" => Some(State::Plaintext),
+            _ => None,
+        }
+    }
+}
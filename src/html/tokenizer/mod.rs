@@ -22,21 +22,26 @@
  */
 // Implements <https://html.spec.whatwg.org/multipage/parsing.html#tokenization>
 pub mod detail;
+pub mod entities;
+pub mod preprocess;
 
 use crate::html::parser::detail::ParseHtmlError;
+use crate::html::parser::error::{ErrorAction, ParseErrorHandler};
 use crate::html::tokenizer::detail::*;
+use crate::html::tokenizer::preprocess::InputPreprocessor;
 use crate::infra::code_point::*;
+use crate::io::decode::utf8::Utf8ChunkDecoder;
 use crate::io::iter::LineOffsetIterator;
 use std::char;
 use std::collections::{HashMap, VecDeque};
-use std::io::Cursor;
 
 pub struct HtmlTokenizer {
     html: LineOffsetIterator,
     pub state: State,
 
     return_state: Option<State>,
-    last_emitted_tag: Option<Tag>,
+    // name of the last start tag emitted; decides which end tag is appropriate
+    last_start_tag: Option<String>,
 
     comment: Option<Comment>,
     tag: Option<Tag>,
@@ -45,28 +50,316 @@ pub struct HtmlTokenizer {
     temp_buf: String,
     char_ref_code: u32,
 
-    tokens_to_emit: VecDeque<Token>,
+    tokens_to_emit: VecDeque<TokenWithLocation>,
+
+    /// Set by the tree builder when there is an adjusted current node and it
+    ///   is not an element in the HTML namespace
+    adjusted_current_node_foreign: bool,
+
+    error_handler: Option<Box<dyn ParseErrorHandler>>,
+    aborted: bool,
+    // location of the character currently being tokenized; used for errors
+    cur_char_location: TokenMetadata,
+    // where the source text of the next token to be emitted begins
+    token_start: Option<TokenMetadata>,
+    eof_emitted: bool,
+
+    // holds back partial code points between chunks given to `feed_bytes`
+    decoder: Utf8ChunkDecoder,
+    preprocessor: InputPreprocessor,
+    // input stream errors not yet reported, by byte offset; they're reported
+    //   as the tokenizer reaches them so they come in order with the others
+    stream_errors: VecDeque<(usize, ParseHtmlError)>,
+    // where newlines were CRLFs in the input; see `source_offset`
+    crlf_offsets: Vec<usize>,
+
+    // set when the current attribute's name duplicates an earlier one
+    cur_attr_duplicate: bool,
+
+    coalesce_characters: bool,
+    // characters waiting to be emitted as one token when coalescing
+    text_run: String,
+    text_run_start: TokenMetadata,
+    text_run_end: TokenMetadata,
 }
 
 impl HtmlTokenizer {
     pub fn new(html: &str) -> HtmlTokenizer {
-        let mut cursor = Cursor::new(html);
+        let mut tokenizer = HtmlTokenizer::with_input(LineOffsetIterator::new_streaming());
+        tokenizer.feed(html);
+        tokenizer.finish();
+        tokenizer
+    }
+
+    /// Creates a tokenizer that is given its input in chunks as it arrives
+    ///
+    /// Tokens are emitted as soon as they can be determined.
+    /// When the input runs out (even partway through a token), the iterator
+    ///   returns `None` until more is given with `feed` or `feed_bytes`;
+    ///   `needs_input` tells this apart from the end of tokenization.
+    /// Call `finish` after the last chunk.
+    pub fn new_streaming() -> HtmlTokenizer {
+        HtmlTokenizer::with_input(LineOffsetIterator::new_streaming())
+    }
+
+    fn with_input(html: LineOffsetIterator) -> HtmlTokenizer {
         HtmlTokenizer {
-            html: LineOffsetIterator::new(&mut cursor),
+            html,
             state: State::Data,
             return_state: None,
-            last_emitted_tag: None,
+            last_start_tag: None,
             comment: None,
             tag: None,
             doctype: None,
             temp_buf: "".into(),
             char_ref_code: 0,
             tokens_to_emit: VecDeque::new(),
+            adjusted_current_node_foreign: false,
+            error_handler: None,
+            aborted: false,
+            cur_char_location: TokenMetadata::new(0, 0, 0),
+            token_start: None,
+            eof_emitted: false,
+            decoder: Utf8ChunkDecoder::new(),
+            preprocessor: InputPreprocessor::new(),
+            stream_errors: VecDeque::new(),
+            crlf_offsets: vec![],
+            cur_attr_duplicate: false,
+            coalesce_characters: false,
+            text_run: String::new(),
+            text_run_start: TokenMetadata::new(0, 0, 0),
+            text_run_end: TokenMetadata::new(0, 0, 0),
+        }
+    }
+
+    /// Sets whether runs of text are emitted as one `Token::Characters`
+    ///   instead of a `Token::Character` for each character.
+    /// Plain text is then copied straight from the input, which is much
+    ///   faster on text-heavy documents.
+    pub fn set_coalesce_characters(&mut self, coalesce: bool) {
+        self.coalesce_characters = coalesce;
+    }
+
+    /// Appends a chunk of input to a streaming tokenizer
+    /// Panics if `finish` was already called.
+    pub fn feed(&mut self, chunk: &str) {
+        assert!(!self.html.finished(), "input fed after `finish`");
+        let mut processed = String::new();
+        let found = self.preprocessor.process(chunk, &mut processed);
+        // offsets are relative to the end of the input so far
+        let base = self.html.offset() + self.html.remaining().len();
+        self.stream_errors.extend(
+            found
+                .errors
+                .into_iter()
+                .map(|(offset, err)| (base + offset, err)),
+        );
+        self.crlf_offsets
+            .extend(found.crlf_offsets.into_iter().map(|offset| base + offset));
+        self.html.push_str(&processed);
+    }
+
+    /// Appends a chunk of UTF-8 encoded input to a streaming tokenizer
+    /// Code points split between chunks are held back until the rest arrives.
+    /// Panics if `finish` was already called.
+    pub fn feed_bytes(&mut self, bytes: &[u8]) {
+        let chunk = self.decoder.decode(bytes, false);
+        self.feed(&chunk);
+    }
+
+    /// Marks the end of a streaming tokenizer's input
+    /// Calling it again does nothing.
+    pub fn finish(&mut self) {
+        if self.html.finished() {
+            return;
+        }
+        let rest = self.decoder.decode(&[], true);
+        self.feed(&rest);
+        self.html.finish();
+    }
+
+    /// Checks if tokenization is suspended until more input is given
+    pub fn needs_input(&self) -> bool {
+        !self.html.finished() && !self.enough_input()
+    }
+
+    /// Gets the input that hasn't been tokenized yet
+    /// This is for scanning ahead of the tokenizer, as `PreloadScanner`
+    ///   does.
+    pub fn remaining_input(&self) -> &str {
+        self.html.remaining()
+    }
+
+    /// Gets the byte offset of `remaining_input` from the start of the input
+    pub fn input_offset(&self) -> usize {
+        self.html.offset()
+    }
+
+    /// Checks if `finish` was called
+    pub fn input_finished(&self) -> bool {
+        self.html.finished()
+    }
+
+    // checks if there's enough input to tokenize the next character without
+    //   running out partway through a lookahead
+    fn enough_input(&self) -> bool {
+        let remaining = self.html.remaining();
+        if remaining.is_empty() {
+            return false;
+        }
+
+        match self.state {
+            State::MarkupDeclarationOpen => {
+                !HtmlTokenizer::keyword_undecided(remaining, &["--", "doctype", "[CDATA["])
+            }
+            State::AfterDoctypeName => {
+                !HtmlTokenizer::keyword_undecided(remaining, &["public", "system"])
+            }
+            State::CharacterReference => {
+                // the named character reference state reads ahead until the
+                //   name is no longer the start of any named reference
+                let mut name = String::new();
+                for c in remaining.chars() {
+                    name.push(c);
+                    if !entities::is_prefix(&name) {
+                        return true;
+                    }
+                }
+                false
+            }
+            _ => true,
+        }
+    }
+
+    // checks if `input` is too short to tell if it starts with one of `keywords`
+    fn keyword_undecided(input: &str, keywords: &[&str]) -> bool {
+        keywords.iter().any(|keyword| {
+            input.len() < keyword.len()
+                && keyword.as_bytes()[..input.len()].eq_ignore_ascii_case(input.as_bytes())
+        })
+    }
+
+    /// Sets the handler that receives parse errors along with their location.
+    /// Without one, parse errors are ignored.
+    pub fn set_error_handler(&mut self, handler: Box<dyn ParseErrorHandler>) {
+        self.error_handler = Some(handler);
+    }
+
+    /// Checks if the error handler aborted tokenization.
+    /// Once aborted, the iterator stops returning tokens.
+    pub fn aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Gets the location of the character currently being tokenized
+    pub fn location(&self) -> TokenMetadata {
+        self.cur_char_location.clone()
+    }
+
+    /// Converts a byte offset in the tokenizer's input, as in token
+    ///   locations, into the offset in the input as given to `feed`.
+    /// They differ once newlines are normalized; a CRLF is tokenized as a
+    ///   single LF.
+    pub fn source_offset(&self, offset: usize) -> usize {
+        offset + self.crlf_offsets.partition_point(|&crlf| crlf <= offset)
+    }
+
+    // location of the next character to be read
+    fn input_location(&self) -> TokenMetadata {
+        let (line, line_offset) = self.html.state();
+        TokenMetadata::new(self.html.offset(), line as u32, line_offset as u32)
+    }
+
+    // checks if the tokenizer is between tokens, i.e. if the current state
+    //   does not belong to a token that is still being built
+    fn in_text_state(&self) -> bool {
+        matches!(
+            self.state,
+            State::Data
+                | State::Rcdata
+                | State::Rawtext
+                | State::ScriptData
+                | State::Plaintext
+                | State::CdataSection
+        )
+    }
+
+    /// Tells the tokenizer whether the tree builder's adjusted current node
+    ///   is outside the HTML namespace.
+    /// `<![CDATA[` only starts a CDATA section when it is; otherwise it is
+    ///   tokenized as a bogus comment.
+    pub fn set_adjusted_current_node_foreign(&mut self, foreign: bool) {
+        self.adjusted_current_node_foreign = foreign;
+    }
+
+    /// Switches the tokenizer to another state before the next token.
+    /// The tree builder uses this after elements such as `<title>`,
+    ///   `<textarea>`, `<style>`, `<script>` and `<plaintext>` to switch to
+    ///   `State::Rcdata`, `State::Rawtext`, `State::ScriptData` or
+    ///   `State::Plaintext`.
+    pub fn switch_to(&mut self, state: State) {
+        self.state = state;
+    }
+
+    /// Sets the name of the last start tag emitted, which decides the end tag
+    ///   that leaves RCDATA, RAWTEXT and script data.
+    /// Start tags record themselves as they are emitted; this is for when
+    ///   tokenizing starts inside an element, such as in fragment parsing.
+    pub fn set_last_start_tag(&mut self, name: &str) {
+        self.last_start_tag = Some(name.into());
+    }
+
+    fn error(&mut self, err: ParseHtmlError) {
+        let location = self.location();
+        self.error_at(err, location);
+    }
+
+    fn error_at(&mut self, err: ParseHtmlError, location: TokenMetadata) {
+        let handler = match self.error_handler.as_mut() {
+            Some(handler) => handler,
+            None => return,
+        };
+        if handler.parse_error(err, &location) == ErrorAction::Abort {
+            self.aborted = true;
         }
     }
 
-    fn error(&mut self, _err: ParseHtmlError) {
-        // TODO: call a callback
+    // reports the input stream errors for characters before byte offset `end`
+    fn report_stream_errors(&mut self, end: usize) {
+        while let Some(&(offset, err)) = self.stream_errors.front() {
+            if offset >= end {
+                break;
+            }
+            self.stream_errors.pop_front();
+            let (line, line_offset) = self.html.state_of(offset);
+            self.error_at(
+                err,
+                TokenMetadata::new(offset, line as u32, line_offset as u32),
+            );
+        }
+    }
+
+    // the tag, comment, and DOCTYPE being built
+    // the states using them always create them first, but they're created
+    //   on demand so a state switched to from outside can't panic
+    fn cur_tag(&mut self) -> &mut Tag {
+        self.tag.get_or_insert_with(|| Tag::new(false))
+    }
+
+    fn cur_comment(&mut self) -> &mut Comment {
+        self.comment.get_or_insert_with(Comment::new)
+    }
+
+    fn cur_doctype(&mut self) -> &mut Doctype {
+        self.doctype.get_or_insert_with(Doctype::new)
+    }
+
+    fn take_comment(&mut self) -> Comment {
+        self.comment.take().unwrap_or_else(Comment::new)
+    }
+
+    fn take_doctype(&mut self) -> Doctype {
+        self.doctype.take().unwrap_or_else(Doctype::new)
     }
 
     fn consumed_as_part_of_attribute(&mut self) -> bool {
@@ -82,13 +375,59 @@ impl HtmlTokenizer {
     }
 
     fn end_tag_appropriate(&mut self) -> bool {
-        // NOTE: will intentionally panic if `tag` is `None`
-        match self.last_emitted_tag.as_ref() {
-            None => false,
-            Some(last_tag) => last_tag.name == self.tag.as_ref().unwrap().name,
+        match (self.last_start_tag.as_ref(), self.tag.as_ref()) {
+            (Some(last_tag), Some(tag)) => *last_tag == tag.name,
+            _ => false,
+        }
+    }
+
+    // starts a new attribute on the current tag, first dropping the current
+    //   one if it duplicated an earlier attribute
+    fn start_new_attribute(&mut self) {
+        if self.cur_attr_duplicate {
+            self.cur_tag().remove_cur_attr();
+            self.cur_attr_duplicate = false;
+        }
+        self.cur_tag().create_attribute();
+    }
+
+    // section 12.2.5.33: when leaving the attribute name state, an attribute
+    //   with the same name as an earlier one is a parse error and is removed
+    //   from the token
+    // the attribute is only marked here since its value is still to come
+    fn check_duplicate_attribute(&mut self) {
+        if self.cur_tag().cur_attr_is_duplicate() {
+            self.error(ParseHtmlError::DuplicateAttribute);
+            self.cur_attr_duplicate = true;
         }
     }
 
+    fn emit_current_tag(&mut self) -> Vec<Token> {
+        let mut tag = self.tag.take().unwrap_or_else(|| Tag::new(false));
+        if self.cur_attr_duplicate {
+            tag.remove_cur_attr();
+            self.cur_attr_duplicate = false;
+        }
+
+        if !tag.is_end_tag {
+            self.last_start_tag = Some(tag.name.clone());
+        } else {
+            // section 12.2.5: end tags with attributes or the self-closing
+            //   flag are parse errors
+            if tag
+                .attributes
+                .as_ref()
+                .is_some_and(|attrs| !attrs.is_empty())
+            {
+                self.error(ParseHtmlError::EndTagWithAttributes);
+            }
+            if tag.self_closing {
+                self.error(ParseHtmlError::EndTagWithTrailingSolidus);
+            }
+        }
+        vec![Token::Tag(tag)]
+    }
+
     fn temp_buf_to_tokens(&mut self) -> Vec<Token> {
         let mut buf: Vec<Token> = Vec::with_capacity(32);
         for c in self.temp_buf.chars() {
@@ -97,39 +436,188 @@ impl HtmlTokenizer {
         buf
     }
 
+    // https://html.spec.whatwg.org/multipage/parsing.html#flush-code-points-consumed-as-a-character-reference
+    fn flush_code_points_consumed_as_char_ref(&mut self) -> Option<Vec<Token>> {
+        if self.consumed_as_part_of_attribute() {
+            self.tag
+                .get_or_insert_with(|| Tag::new(false))
+                .append_to_cur_attr_value_str(&self.temp_buf);
+            return None;
+        }
+        Some(self.temp_buf_to_tokens())
+    }
+
+    fn reconsume_in_return_state(
+        &mut self,
+        tokens: Option<Vec<Token>>,
+        c: Option<char>,
+    ) -> Option<Vec<Token>> {
+        self.state = self.return_state.unwrap_or(State::Data);
+        let mut tok = tokens.unwrap_or_default();
+        let mut reconsumed = self.tokenize(self.state, c).unwrap_or_default();
+        tok.append(&mut reconsumed);
+        if tok.is_empty() {
+            return None;
+        }
+        Some(tok)
+    }
+
     // actual tokenization functions place below `impl Iterator for HtmlTokenizer`
 }
 
+/// Tokens are yielded with the location of the source text they came from.
+/// Tokens produced together from one run of input (for example, the
+///   characters of `</` followed by something other than a tag name) share
+///   that run's location; EOF tokens begin and end at the end of the input.
 impl Iterator for HtmlTokenizer {
-    type Item = Token;
+    type Item = TokenWithLocation;
+
+    fn next(&mut self) -> Option<TokenWithLocation> {
+        if self.aborted {
+            return None;
+        }
 
-    fn next(&mut self) -> Option<Token> {
         // spit out a stored token from the vector if it exists
         match self.tokens_to_emit.pop_front() {
             Some(tok) => return Some(tok),
             None => (),
         }
 
+        // nothing comes after the EOF token
+        if self.eof_emitted {
+            return None;
+        }
+
         loop {
+            // suspend if more input is on the way; any text seen so far won't
+            //   change, so it can be emitted now
+            if self.needs_input() {
+                return self.take_text_run();
+            }
+
+            if self.coalesce_characters && self.in_text_state() && self.consume_text_run() {
+                continue;
+            }
+
+            self.cur_char_location = self.input_location();
+            if self.token_start.is_none() {
+                self.token_start = Some(self.cur_char_location.clone());
+            }
+
+            // the character about to be read, then any read ahead
+            self.report_stream_errors(self.html.offset() + 1);
             let c = self.html.read();
-            match self.tokenize(self.state, c) {
+            let tokens = self.tokenize(self.state, c);
+            self.report_stream_errors(self.html.offset());
+            if self.aborted {
+                // the error handler decided this error is fatal
+                return None;
+            }
+            match tokens {
                 Some(tokens) => {
-                    if tokens.len() == 1 {
-                        // if there's only one token, don't store it; just return it
-                        return Some(tokens.get(0).unwrap().clone());
-                    } else {
-                        self.tokens_to_emit = VecDeque::from(tokens);
-                        return Some(self.tokens_to_emit.pop_front().unwrap().clone());
+                    let start = match self.token_start.take() {
+                        Some(start) => start,
+                        None => self.cur_char_location.clone(),
+                    };
+                    let end = self.input_location();
+                    for token in tokens {
+                        let start = match token {
+                            Token::Eof => {
+                                self.eof_emitted = true;
+                                end.clone()
+                            }
+                            _ => start.clone(),
+                        };
+                        self.emit(TokenWithLocation::new(token, start, end.clone()));
+                    }
+                    // when coalescing, characters are held back in the text run
+                    if let Some(tok) = self.tokens_to_emit.pop_front() {
+                        return Some(tok);
                     }
                 }
                 None => {
                     // No tokens returned; consume next character and try again
+                    // Anything consumed without starting a token (such as
+                    //   `</>`) isn't part of the next token's source text
+                    if self.in_text_state() {
+                        self.token_start = None;
+                    }
                 }
             }
         }
     }
 }
 
+// emission
+impl HtmlTokenizer {
+    fn emit(&mut self, token: TokenWithLocation) {
+        if self.coalesce_characters {
+            if let Token::Character(c) = token.token {
+                if self.text_run.is_empty() {
+                    self.text_run_start = token.start;
+                }
+                self.text_run.push(c);
+                self.text_run_end = token.end;
+                return;
+            }
+            if let Some(run) = self.take_text_run() {
+                self.tokens_to_emit.push_back(run);
+            }
+        }
+        self.tokens_to_emit.push_back(token);
+    }
+
+    fn take_text_run(&mut self) -> Option<TokenWithLocation> {
+        if self.text_run.is_empty() {
+            return None;
+        }
+        let text = std::mem::take(&mut self.text_run);
+        Some(TokenWithLocation::new(
+            Token::Characters(text),
+            self.text_run_start.clone(),
+            self.text_run_end.clone(),
+        ))
+    }
+
+    // adds the characters at the start of the input that the current text
+    //   state emits unchanged to the text run, without going through the
+    //   state machine one character at a time
+    // returns `false` if the next character needs the state machine
+    fn consume_text_run(&mut self) -> bool {
+        let start = self.input_location();
+        let remaining = self.html.remaining();
+        // none of these are ever part of a multi-byte code point
+        let special = match self.state {
+            State::Data | State::Rcdata => remaining
+                .bytes()
+                .position(|b| b == b'&' || b == b'<' || b == 0),
+            State::Rawtext | State::ScriptData => {
+                remaining.bytes().position(|b| b == b'<' || b == 0)
+            }
+            State::Plaintext => remaining.bytes().position(|b| b == 0),
+            State::CdataSection => remaining.bytes().position(|b| b == b']'),
+            _ => Some(0),
+        };
+        let mut len = special.unwrap_or(remaining.len());
+        // stop before any character with an input stream error so it gets
+        //   reported
+        if let Some(&(offset, _)) = self.stream_errors.front() {
+            len = len.min(offset.saturating_sub(start.file_offset));
+        }
+        if len == 0 {
+            return false;
+        }
+
+        if self.text_run.is_empty() {
+            self.text_run_start = start;
+        }
+        self.text_run.push_str(&remaining[..len]);
+        self.html.consume_bytes(len);
+        self.text_run_end = self.input_location();
+        true
+    }
+}
+
 // implementation
 impl HtmlTokenizer {
     fn tokenize(&mut self, state: State, c: Option<char>) -> Option<Vec<Token>> {
@@ -157,8 +645,8 @@ impl HtmlTokenizer {
             State::ScriptDataEscapedDash => self.script_data_escaped_dash(c),
             State::ScriptDataEscapedDashDash => self.script_data_escaped_dash_dash(c),
             State::ScriptDataEscapedLessThanSign => self.script_data_escaped_less_than_sign(c),
-            State::ScriptDataEscapedEndTagOpen => self.script_data_end_tag_open(c),
-            State::ScriptDataEscapedEndTagName => self.script_data_end_tag_name(c),
+            State::ScriptDataEscapedEndTagOpen => self.script_data_escaped_end_tag_open(c),
+            State::ScriptDataEscapedEndTagName => self.script_data_escaped_end_tag_name(c),
             State::ScriptDataDoubleEscapeStart => self.script_data_double_escape_start(c),
             State::ScriptDataDoubleEscaped => self.script_data_double_escaped(c),
             State::ScriptDataDoubleEscapedDash => self.script_data_double_escaped_dash(c),
@@ -228,8 +716,7 @@ impl HtmlTokenizer {
             State::HexadecimalCharacterReference => self.hexadecimal_character_reference(c),
             State::DecimalCharacterReference => self.decimal_character_reference(c),
             State::NumericCharacterReferenceEnd => self.numeric_character_reference_end(c),
-        };
-        unreachable!();
+        }
     }
 
     fn data(&mut self, c: Option<char>) -> Option<Vec<Token>> {
@@ -331,17 +818,23 @@ impl HtmlTokenizer {
             }
             Some(c) if ascii_alpha(c as u32) => {
                 self.tag = Some(Tag::new(false));
+                self.state = State::TagName;
                 self.tag_name(Some(c))
             }
             Some('?') => {
                 self.error(ParseHtmlError::UnexpectedQuestionMarkInsteadOfTagName);
                 self.comment = Some(Comment::new());
+                self.state = State::BogusComment;
                 self.bogus_comment(c)
             }
-            None => Some(vec![Token::Character('<'), Token::Eof]),
+            None => {
+                self.error(ParseHtmlError::EofBeforeTagName);
+                Some(vec![Token::Character('<'), Token::Eof])
+            }
             Some(c) => {
                 self.error(ParseHtmlError::InvalidFirstCharacterOfTagName);
                 let mut tok = vec![Token::Character('<')];
+                self.state = State::Data;
                 let mut reconsumed = self.data(Some(c)).unwrap_or_default();
                 tok.append(&mut reconsumed);
                 Some(tok)
@@ -354,9 +847,10 @@ impl HtmlTokenizer {
         match c {
             Some(c) if ascii_alpha(c as u32) => {
                 self.tag = Some(Tag::new(true));
+                self.state = State::TagName;
                 self.tag_name(Some(c))
             }
-            Some('<') => {
+            Some('>') => {
                 self.error(ParseHtmlError::MissingEndTagName);
                 self.state = State::Data;
                 None
@@ -372,6 +866,7 @@ impl HtmlTokenizer {
             Some(c) => {
                 self.error(ParseHtmlError::InvalidFirstCharacterOfTagName);
                 self.comment = Some(Comment::new());
+                self.state = State::BogusComment;
                 self.bogus_comment(Some(c))
             }
         }
@@ -390,18 +885,15 @@ impl HtmlTokenizer {
             }
             Some('>') => {
                 self.state = State::Data;
-                match self.tag.as_ref() {
-                    Some(tag) => Some(vec![Token::Tag(tag.clone())]),
-                    None => panic!(),
-                }
+                Some(self.emit_current_tag())
             }
             Some(c) if ascii_upper_alpha(c as u32) => {
-                self.tag.as_mut().unwrap().name.push(c.to_ascii_lowercase());
+                self.cur_tag().name.push(c.to_ascii_lowercase());
                 None
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.tag.as_mut().unwrap().name.push('\u{FFFD}');
+                self.cur_tag().name.push('\u{FFFD}');
                 None
             }
             None => {
@@ -409,7 +901,7 @@ impl HtmlTokenizer {
                 Some(vec![Token::Eof])
             }
             Some(c) => {
-                self.tag.as_mut().unwrap().name.push(c);
+                self.cur_tag().name.push(c);
                 None
             }
         }
@@ -425,6 +917,7 @@ impl HtmlTokenizer {
             }
             _ => {
                 let mut tok = vec![Token::Character('<')];
+                self.state = State::Rcdata;
                 let mut reconsumed = self.rcdata(c).unwrap_or_default();
                 tok.append(&mut reconsumed);
                 Some(tok)
@@ -437,10 +930,12 @@ impl HtmlTokenizer {
         match c {
             Some(c) if ascii_alpha(c as u32) => {
                 self.tag = Some(Tag::new(true));
+                self.state = State::RcdataEndTagName;
                 self.rcdata_end_tag_name(Some(c))
             }
             _ => {
                 let mut tok = vec![Token::Character('<'), Token::Character('/')];
+                self.state = State::Rcdata;
                 let mut reconsumed = self.rcdata(c).unwrap_or_default();
                 tok.append(&mut reconsumed);
                 Some(tok)
@@ -468,19 +963,17 @@ impl HtmlTokenizer {
             Some('>') => {
                 if self.end_tag_appropriate() {
                     self.state = State::Data;
-                    let tag = self.tag.clone().unwrap();
-                    self.tag = None;
-                    return Some(vec![Token::Tag(tag)]);
+                    return Some(self.emit_current_tag());
                 }
                 // otherwise treat as "anything else"
             }
             Some(c) if ascii_upper_alpha(c as u32) => {
-                self.tag.as_mut().unwrap().name.push(c.to_ascii_lowercase());
+                self.cur_tag().name.push(c.to_ascii_lowercase());
                 self.temp_buf.push(c);
                 return None;
             }
             Some(c) if ascii_lower_alpha(c as u32) => {
-                self.tag.as_mut().unwrap().name.push(c);
+                self.cur_tag().name.push(c);
                 self.temp_buf.push(c);
                 return None;
             }
@@ -488,6 +981,7 @@ impl HtmlTokenizer {
         }
         let mut tok = vec![Token::Character('<'), Token::Character('/')];
         tok.append(&mut self.temp_buf_to_tokens());
+        self.state = State::Rcdata;
         let mut reconsumed = self.rcdata(c).unwrap_or_default();
         tok.append(&mut reconsumed);
         Some(tok)
@@ -503,6 +997,7 @@ impl HtmlTokenizer {
             }
             _ => {
                 let mut tok = vec![Token::Character('<')];
+                self.state = State::Rawtext;
                 let mut reconsumed = self.rawtext(c).unwrap_or_default();
                 tok.append(&mut reconsumed);
                 Some(tok)
@@ -515,10 +1010,12 @@ impl HtmlTokenizer {
         match c {
             Some(c) if ascii_alpha(c as u32) => {
                 self.tag = Some(Tag::new(true));
+                self.state = State::RawtextEndTagName;
                 self.rawtext_end_tag_name(Some(c))
             }
             _ => {
                 let mut tok = vec![Token::Character('<'), Token::Character('/')];
+                self.state = State::Rawtext;
                 let mut reconsumed = self.rawtext(c).unwrap_or_default();
                 tok.append(&mut reconsumed);
                 Some(tok)
@@ -546,17 +1043,17 @@ impl HtmlTokenizer {
             Some('>') => {
                 if self.end_tag_appropriate() {
                     self.state = State::Data;
-                    return None;
+                    return Some(self.emit_current_tag());
                 }
                 // otherwise treat as "anything else"
             }
             Some(c) if ascii_upper_alpha(c as u32) => {
-                self.tag.as_mut().unwrap().name.push(c.to_ascii_lowercase());
+                self.cur_tag().name.push(c.to_ascii_lowercase());
                 self.temp_buf.push(c);
                 return None;
             }
             Some(c) if ascii_lower_alpha(c as u32) => {
-                self.tag.as_mut().unwrap().name.push(c);
+                self.cur_tag().name.push(c);
                 self.temp_buf.push(c);
                 return None;
             }
@@ -564,6 +1061,7 @@ impl HtmlTokenizer {
         }
         let mut tok = vec![Token::Character('<'), Token::Character('/')];
         tok.append(&mut self.temp_buf_to_tokens());
+        self.state = State::Rawtext;
         let mut reconsumed = self.rawtext(c).unwrap_or_default();
         tok.append(&mut reconsumed);
         Some(tok)
@@ -583,6 +1081,7 @@ impl HtmlTokenizer {
             }
             _ => {
                 let mut tok = vec![Token::Character('<')];
+                self.state = State::ScriptData;
                 let mut reconsumed = self.script_data(c).unwrap_or_default();
                 tok.append(&mut reconsumed);
                 Some(tok)
@@ -595,10 +1094,12 @@ impl HtmlTokenizer {
         match c {
             Some(c) if ascii_alpha(c as u32) => {
                 self.tag = Some(Tag::new(true));
+                self.state = State::ScriptDataEndTagName;
                 self.script_data_end_tag_name(Some(c))
             }
             _ => {
                 let mut tok = vec![Token::Character('<'), Token::Character('/')];
+                self.state = State::ScriptData;
                 let mut reconsumed = self.script_data(c).unwrap_or_default();
                 tok.append(&mut reconsumed);
                 Some(tok)
@@ -626,20 +1127,17 @@ impl HtmlTokenizer {
             Some('>') => {
                 if self.end_tag_appropriate() {
                     self.state = State::Data;
-                    match self.tag.as_ref() {
-                        Some(tag) => return Some(vec![Token::Tag(tag.clone())]),
-                        None => panic!(),
-                    }
+                    return Some(self.emit_current_tag());
                 }
                 // otherwise treat as "anything else"
             }
             Some(c) if ascii_upper_alpha(c as u32) => {
-                self.tag.as_mut().unwrap().name.push(c.to_ascii_lowercase());
+                self.cur_tag().name.push(c.to_ascii_lowercase());
                 self.temp_buf.push(c);
                 return None;
             }
             Some(c) if ascii_lower_alpha(c as u32) => {
-                self.tag.as_mut().unwrap().name.push(c);
+                self.cur_tag().name.push(c);
                 self.temp_buf.push(c);
                 return None;
             }
@@ -647,6 +1145,7 @@ impl HtmlTokenizer {
         }
         let mut tok = vec![Token::Character('<'), Token::Character('/')];
         tok.append(&mut self.temp_buf_to_tokens());
+        self.state = State::ScriptData;
         let mut reconsumed = self.script_data(c).unwrap_or_default();
         tok.append(&mut reconsumed);
         Some(tok)
@@ -659,7 +1158,10 @@ impl HtmlTokenizer {
                 self.state = State::ScriptDataEscapeStartDash;
                 Some(vec![Token::Character('-')])
             }
-            _ => self.script_data(c),
+            _ => {
+                self.state = State::ScriptData;
+                self.script_data(c)
+            }
         }
     }
 
@@ -670,7 +1172,10 @@ impl HtmlTokenizer {
                 self.state = State::ScriptDataEscapedDashDash;
                 Some(vec![Token::Character('-')])
             }
-            _ => self.script_data(c),
+            _ => {
+                self.state = State::ScriptData;
+                self.script_data(c)
+            }
         }
     }
 
@@ -763,6 +1268,7 @@ impl HtmlTokenizer {
             Some(c) if ascii_alpha(c as u32) => {
                 self.temp_buf = "".into();
                 let mut tok = vec![Token::Character('<')];
+                self.state = State::ScriptDataDoubleEscapeStart;
                 let mut reconsumed = self
                     .script_data_double_escape_start(Some(c))
                     .unwrap_or_default();
@@ -771,6 +1277,7 @@ impl HtmlTokenizer {
             }
             _ => {
                 let mut tok = vec![Token::Character('<')];
+                self.state = State::ScriptDataEscaped;
                 let mut reconsumed = self.script_data_escaped(c).unwrap_or_default();
                 tok.append(&mut reconsumed);
                 Some(tok)
@@ -783,10 +1290,12 @@ impl HtmlTokenizer {
         match c {
             Some(c) if ascii_alpha(c as u32) => {
                 self.tag = Some(Tag::new(true));
+                self.state = State::ScriptDataEscapedEndTagName;
                 self.script_data_escaped_end_tag_name(Some(c))
             }
             _ => {
                 let mut tok = vec![Token::Character('<'), Token::Character('/')];
+                self.state = State::ScriptDataEscaped;
                 let mut reconsumed = self.script_data_escaped(c).unwrap_or_default();
                 tok.append(&mut reconsumed);
                 Some(tok)
@@ -814,20 +1323,17 @@ impl HtmlTokenizer {
             Some('>') => {
                 if self.end_tag_appropriate() {
                     self.state = State::Data;
-                    match self.tag.as_ref() {
-                        Some(tag) => return Some(vec![Token::Tag(tag.clone())]),
-                        None => panic!(),
-                    }
+                    return Some(self.emit_current_tag());
                 }
                 // otherwise treat as "anything else"
             }
             Some(c) if ascii_upper_alpha(c as u32) => {
-                self.tag.as_mut().unwrap().name.push(c.to_ascii_lowercase());
+                self.cur_tag().name.push(c.to_ascii_lowercase());
                 self.temp_buf.push(c);
                 return None;
             }
             Some(c) if ascii_lower_alpha(c as u32) => {
-                self.tag.as_mut().unwrap().name.push(c);
+                self.cur_tag().name.push(c);
                 self.temp_buf.push(c);
                 return None;
             }
@@ -835,6 +1341,7 @@ impl HtmlTokenizer {
         }
         let mut tok = vec![Token::Character('<'), Token::Character('/')];
         tok.append(&mut self.temp_buf_to_tokens());
+        self.state = State::ScriptDataEscaped;
         let mut reconsumed = self.script_data_escaped(c).unwrap_or_default();
         tok.append(&mut reconsumed);
         Some(tok)
@@ -859,7 +1366,10 @@ impl HtmlTokenizer {
                 self.temp_buf.push(c);
                 Some(vec![Token::Character(c)])
             }
-            _ => self.script_data_escaped(c),
+            _ => {
+                self.state = State::ScriptDataEscaped;
+                self.script_data_escaped(c)
+            }
         }
     }
 
@@ -949,7 +1459,10 @@ impl HtmlTokenizer {
                 self.state = State::ScriptDataDoubleEscapeEnd;
                 Some(vec![Token::Character('/')])
             }
-            _ => self.script_data_double_escaped(c),
+            _ => {
+                self.state = State::ScriptDataDoubleEscaped;
+                self.script_data_double_escaped(c)
+            }
         }
     }
 
@@ -972,7 +1485,10 @@ impl HtmlTokenizer {
                 self.temp_buf.push(c);
                 Some(vec![Token::Character(c)])
             }
-            _ => self.script_data_double_escaped(c),
+            _ => {
+                self.state = State::ScriptDataDoubleEscaped;
+                self.script_data_double_escaped(c)
+            }
         }
     }
 
@@ -980,16 +1496,20 @@ impl HtmlTokenizer {
         // section 12.2.5.32
         match c {
             Some(c) if ascii_whitespace(c as u32) => None,
-            Some('/') | Some('>') | None => self.after_attribute_name(c),
+            Some('/') | Some('>') | None => {
+                self.state = State::AfterAttributeName;
+                self.after_attribute_name(c)
+            }
             Some('=') => {
                 self.error(ParseHtmlError::UnexpectedEqualsSignBeforeAttributeName);
-                self.tag.as_mut().unwrap().create_attribute();
-                self.tag.as_mut().unwrap().append_to_cur_attr_name('=');
+                self.start_new_attribute();
+                self.cur_tag().append_to_cur_attr_name('=');
                 self.state = State::AttributeName;
                 None
             }
             _ => {
-                self.tag.as_mut().unwrap().create_attribute();
+                self.start_new_attribute();
+                self.state = State::AttributeName;
                 self.attribute_name(c)
             }
         }
@@ -997,29 +1517,30 @@ impl HtmlTokenizer {
 
     fn attribute_name(&mut self, c: Option<char>) -> Option<Vec<Token>> {
         // section 12.2.5.33
-        // TODO: When leaving this state, and before emitting the tag token, check for duplicate attributes
         match c {
             Some(c) if ascii_whitespace(c as u32) || c == '/' || c == '>' => {
-                return self.after_attribute_name(Some(c))
+                self.check_duplicate_attribute();
+                self.state = State::AfterAttributeName;
+                return self.after_attribute_name(Some(c));
+            }
+            None => {
+                self.check_duplicate_attribute();
+                self.state = State::AfterAttributeName;
+                return self.after_attribute_name(None);
             }
-            None => return self.after_attribute_name(None),
             Some('=') => {
+                self.check_duplicate_attribute();
                 self.state = State::BeforeAttributeValue;
                 return None;
             }
             Some(c) if ascii_upper_alpha(c as u32) => {
-                self.tag
-                    .as_mut()
-                    .unwrap()
+                self.cur_tag()
                     .append_to_cur_attr_name(c.to_ascii_lowercase());
                 return None;
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.tag
-                    .as_mut()
-                    .unwrap()
-                    .append_to_cur_attr_name('\u{FFFD}');
+                self.cur_tag().append_to_cur_attr_name('\u{FFFD}');
                 return None;
             }
             Some(c) if c == '"' || c == '\'' || c == '<' => {
@@ -1028,10 +1549,9 @@ impl HtmlTokenizer {
             }
             _ => (),
         }
-        self.tag
-            .as_mut()
-            .unwrap()
-            .append_to_cur_attr_name(c.unwrap());
+        if let Some(c) = c {
+            self.cur_tag().append_to_cur_attr_name(c);
+        }
         None
     }
 
@@ -1049,16 +1569,15 @@ impl HtmlTokenizer {
             }
             Some('>') => {
                 self.state = State::Data;
-                let tag = self.tag.clone().unwrap();
-                self.tag = None;
-                Some(vec![Token::Tag(tag)])
+                Some(self.emit_current_tag())
             }
             None => {
                 self.error(ParseHtmlError::EofInTag);
                 Some(vec![Token::Eof])
             }
             Some(c) => {
-                self.tag.as_mut().unwrap().create_attribute();
+                self.start_new_attribute();
+                self.state = State::AttributeName;
                 self.attribute_name(Some(c))
             }
         }
@@ -1079,11 +1598,12 @@ impl HtmlTokenizer {
             Some('>') => {
                 self.error(ParseHtmlError::MissingAttributeValue);
                 self.state = State::Data;
-                let tag = self.tag.clone().unwrap();
-                self.tag = None;
-                Some(vec![Token::Tag(tag)])
+                Some(self.emit_current_tag())
+            }
+            _ => {
+                self.state = State::AttributeValueUnquoted;
+                self.attribute_value_unquoted(c)
             }
-            _ => self.attribute_value_unquoted(c),
         }
     }
 
@@ -1101,10 +1621,7 @@ impl HtmlTokenizer {
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.tag
-                    .as_mut()
-                    .unwrap()
-                    .append_to_cur_attr_value('\u{FFFD}');
+                self.cur_tag().append_to_cur_attr_value('\u{FFFD}');
                 None
             }
             None => {
@@ -1112,7 +1629,7 @@ impl HtmlTokenizer {
                 Some(vec![Token::Eof])
             }
             Some(c) => {
-                self.tag.as_mut().unwrap().append_to_cur_attr_value(c);
+                self.cur_tag().append_to_cur_attr_value(c);
                 None
             }
         }
@@ -1132,10 +1649,7 @@ impl HtmlTokenizer {
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.tag
-                    .as_mut()
-                    .unwrap()
-                    .append_to_cur_attr_value('\u{FFFD}');
+                self.cur_tag().append_to_cur_attr_value('\u{FFFD}');
                 None
             }
             None => {
@@ -1143,7 +1657,7 @@ impl HtmlTokenizer {
                 Some(vec![Token::Eof])
             }
             Some(c) => {
-                self.tag.as_mut().unwrap().append_to_cur_attr_value(c);
+                self.cur_tag().append_to_cur_attr_value(c);
                 None
             }
         }
@@ -1163,16 +1677,11 @@ impl HtmlTokenizer {
             }
             Some('>') => {
                 self.state = State::Data;
-                let tag = self.tag.clone().unwrap();
-                self.tag = None;
-                return Some(vec![Token::Tag(tag)]);
+                return Some(self.emit_current_tag());
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.tag
-                    .as_mut()
-                    .unwrap()
-                    .append_to_cur_attr_value('\u{FFFD}');
+                self.cur_tag().append_to_cur_attr_value('\u{FFFD}');
                 return None;
             }
             Some('"') | Some('\'') | Some('<') | Some('=') | Some('`') => {
@@ -1185,10 +1694,9 @@ impl HtmlTokenizer {
             }
             _ => (),
         }
-        self.tag
-            .as_mut()
-            .unwrap()
-            .append_to_cur_attr_value(c.unwrap());
+        if let Some(c) = c {
+            self.cur_tag().append_to_cur_attr_value(c);
+        }
         None
     }
 
@@ -1205,9 +1713,7 @@ impl HtmlTokenizer {
             }
             Some('>') => {
                 self.state = State::Data;
-                let tag = self.tag.clone().unwrap();
-                self.tag = None;
-                Some(vec![Token::Tag(tag)])
+                Some(self.emit_current_tag())
             }
             None => {
                 self.error(ParseHtmlError::EofInTag);
@@ -1215,6 +1721,7 @@ impl HtmlTokenizer {
             }
             _ => {
                 self.error(ParseHtmlError::MissingWhitespaceBetweenAttributes);
+                self.state = State::BeforeAttributeName;
                 self.before_attribute_name(c)
             }
         }
@@ -1224,11 +1731,9 @@ impl HtmlTokenizer {
         // section 12.2.5.40
         match c {
             Some('>') => {
-                self.tag.as_mut().unwrap().set_self_closing_flag();
+                self.cur_tag().set_self_closing_flag();
                 self.state = State::Data;
-                let tag = self.tag.clone().unwrap();
-                self.tag = None;
-                Some(vec![Token::Tag(tag)])
+                Some(self.emit_current_tag())
             }
             None => {
                 self.error(ParseHtmlError::EofInTag);
@@ -1236,6 +1741,7 @@ impl HtmlTokenizer {
             }
             _ => {
                 self.error(ParseHtmlError::UnexpectedSolidusInTag);
+                self.state = State::BeforeAttributeName;
                 self.before_attribute_name(c)
             }
         }
@@ -1246,22 +1752,20 @@ impl HtmlTokenizer {
         match c {
             Some('>') => {
                 self.state = State::Data;
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment)])
             }
             None => {
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment), Token::Eof])
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.comment.as_mut().unwrap().value.push('\u{FFFD}');
+                self.cur_comment().value.push('\u{FFFD}');
                 None
             }
             Some(c) => {
-                self.comment.as_mut().unwrap().value.push(c);
+                self.cur_comment().value.push(c);
                 None
             }
         }
@@ -1269,7 +1773,7 @@ impl HtmlTokenizer {
 
     fn markup_declaration_open(&mut self, c: Option<char>) -> Option<Vec<Token>> {
         // section 12.2.5.42
-        // NOTE: this fn only returns `None`
+        // NOTE: this fn only returns `None` unless EOF is reached
         match c {
             Some(c) => {
                 let mut peek: [char; 6] = ['\0'; 6];
@@ -1297,29 +1801,36 @@ impl HtmlTokenizer {
                 // The string "[CDATA[" (the five uppercase letters "CDATA"  with
                 //   a U+005B LEFT SQUARE BRACKET character before and after)
                 if c == '[' && peeked == "CDATA[" {
-                    // consume
-                    // TODO: Consume those characters. If there is an adjusted
-                    //   current node and it is not an element in the HTML
-                    //   namespace, then switch to the CDATA section state.
-                    //   Otherwise, this is a cdata-in-html-content parse error.
-                    //   Create a comment token whose data is the "[CDATA[" string.
-                    //   Switch to the bogus comment state.
-                    panic!();
+                    // consume and switch state depending on the adjusted current node
+                    if self.adjusted_current_node_foreign {
+                        self.state = State::CdataSection;
+                        return None;
+                    }
+                    self.error(ParseHtmlError::CDataInHtmlContext);
+                    let mut comment = Comment::new();
+                    comment.value.push_str("[CDATA[");
+                    self.comment = Some(comment);
+                    self.state = State::BogusComment;
+                    return None;
                 }
 
                 // anything else
                 self.error(ParseHtmlError::IncorrectlyOpenedComment);
                 self.comment = Some(Comment::new());
                 self.state = State::BogusComment;
-                // consume nothing here, so backtrack 7 (because `next()` read 1
-                //   and this fn read 6)
-                self.html.backtrack_multiple(7);
+                // consume nothing here, so backtrack everything (because
+                //   `next()` read 1 and this fn read `read`)
+                self.html.backtrack_multiple(read + 1);
+                None
             }
-            _ => {
+            None => {
                 // anything else, but this is an EOF, so don't backtrack
+                self.error(ParseHtmlError::IncorrectlyOpenedComment);
+                self.comment = Some(Comment::new());
+                self.state = State::BogusComment;
+                self.bogus_comment(None)
             }
         }
-        None
     }
 
     fn comment_start(&mut self, c: Option<char>) -> Option<Vec<Token>> {
@@ -1332,11 +1843,13 @@ impl HtmlTokenizer {
             Some('>') => {
                 self.error(ParseHtmlError::AbruptClosingOfEmptyComment);
                 self.state = State::Data;
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment)])
             }
-            _ => self.comment(c),
+            _ => {
+                self.state = State::Comment;
+                self.comment(c)
+            }
         }
     }
 
@@ -1350,18 +1863,17 @@ impl HtmlTokenizer {
             Some('>') => {
                 self.error(ParseHtmlError::AbruptClosingOfEmptyComment);
                 self.state = State::Data;
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment)])
             }
             None => {
                 self.error(ParseHtmlError::EofInComment);
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment), Token::Eof])
             }
             _ => {
-                self.comment.as_mut().unwrap().value.push('-');
+                self.cur_comment().value.push('-');
+                self.state = State::Comment;
                 self.comment(c)
             }
         }
@@ -1371,7 +1883,7 @@ impl HtmlTokenizer {
         // section 12.2.5.45
         match c {
             Some('<') => {
-                self.comment.as_mut().unwrap().value.push('<');
+                self.cur_comment().value.push('<');
                 self.state = State::CommentLessThanSign;
                 None
             }
@@ -1381,17 +1893,16 @@ impl HtmlTokenizer {
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.comment.as_mut().unwrap().value.push('\u{FFFD}');
+                self.cur_comment().value.push('\u{FFFD}');
                 None
             }
             None => {
                 self.error(ParseHtmlError::EofInComment);
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment), Token::Eof])
             }
             Some(c) => {
-                self.comment.as_mut().unwrap().value.push(c);
+                self.cur_comment().value.push(c);
                 None
             }
         }
@@ -1401,15 +1912,18 @@ impl HtmlTokenizer {
         // section 12.2.5.46
         match c {
             Some('!') => {
-                self.comment.as_mut().unwrap().value.push('!');
+                self.cur_comment().value.push('!');
                 self.state = State::CommentLessThanSignBang;
                 None
             }
             Some('<') => {
-                self.comment.as_mut().unwrap().value.push('<');
+                self.cur_comment().value.push('<');
                 None
             }
-            _ => self.comment(c),
+            _ => {
+                self.state = State::Comment;
+                self.comment(c)
+            }
         }
     }
 
@@ -1420,7 +1934,10 @@ impl HtmlTokenizer {
                 self.state = State::CommentLessThanSignBangDash;
                 None
             }
-            _ => self.comment(c),
+            _ => {
+                self.state = State::Comment;
+                self.comment(c)
+            }
         }
     }
 
@@ -1431,17 +1948,23 @@ impl HtmlTokenizer {
                 self.state = State::CommentLessThanSignBangDashDash;
                 None
             }
-            _ => self.comment_end_dash(c),
+            _ => {
+                self.state = State::CommentEndDash;
+                self.comment_end_dash(c)
+            }
         }
     }
 
     fn comment_less_than_sign_bang_dash_dash(&mut self, c: Option<char>) -> Option<Vec<Token>> {
         // section 12.2.5.49
         match c {
-            Some('<') => self.comment_end(Some('<')),
-            None => self.comment_end(None),
+            Some('>') | None => {
+                self.state = State::CommentEnd;
+                self.comment_end(c)
+            }
             _ => {
                 self.error(ParseHtmlError::NestedComment);
+                self.state = State::CommentEnd;
                 self.comment_end(c)
             }
         }
@@ -1456,12 +1979,12 @@ impl HtmlTokenizer {
             }
             None => {
                 self.error(ParseHtmlError::EofInComment);
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment), Token::Eof])
             }
             _ => {
-                self.comment.as_mut().unwrap().value.push('-');
+                self.cur_comment().value.push('-');
+                self.state = State::Comment;
                 self.comment(c)
             }
         }
@@ -1472,8 +1995,7 @@ impl HtmlTokenizer {
         match c {
             Some('>') => {
                 self.state = State::Data;
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment)])
             }
             Some('!') => {
@@ -1481,17 +2003,17 @@ impl HtmlTokenizer {
                 None
             }
             Some('-') => {
-                self.comment.as_mut().unwrap().value.push('-');
+                self.cur_comment().value.push('-');
                 None
             }
             None => {
                 self.error(ParseHtmlError::EofInComment);
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment), Token::Eof])
             }
             _ => {
-                self.comment.as_mut().unwrap().value.push_str("--");
+                self.cur_comment().value.push_str("--");
+                self.state = State::Comment;
                 self.comment(c)
             }
         }
@@ -1501,25 +2023,24 @@ impl HtmlTokenizer {
         // section 12.2.5.52
         match c {
             Some('-') => {
-                self.comment.as_mut().unwrap().value.push_str("--!");
+                self.cur_comment().value.push_str("--!");
                 self.state = State::CommentEndDash;
                 None
             }
             Some('>') => {
                 self.error(ParseHtmlError::IncorrectlyClosedComment);
                 self.state = State::Data;
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment)])
             }
             None => {
                 self.error(ParseHtmlError::EofInComment);
-                let comment = self.comment.clone().unwrap();
-                self.comment = None;
+                let comment = self.take_comment();
                 Some(vec![Token::Comment(comment), Token::Eof])
             }
             _ => {
-                self.comment.as_mut().unwrap().value.push_str("--!");
+                self.cur_comment().value.push_str("--!");
+                self.state = State::Comment;
                 self.comment(c)
             }
         }
@@ -1532,7 +2053,10 @@ impl HtmlTokenizer {
                 self.state = State::BeforeDoctypeName;
                 None
             }
-            Some('>') => self.before_doctype_name(Some('>')),
+            Some('>') => {
+                self.state = State::BeforeDoctypeName;
+                self.before_doctype_name(Some('>'))
+            }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
                 let mut doctype = Doctype::new();
@@ -1541,6 +2065,7 @@ impl HtmlTokenizer {
             }
             _ => {
                 self.error(ParseHtmlError::MissingWhitespaceBeforeDoctypeName);
+                self.state = State::BeforeDoctypeName;
                 self.before_doctype_name(c)
             }
         }
@@ -1569,6 +2094,7 @@ impl HtmlTokenizer {
                 self.error(ParseHtmlError::MissingDoctypeName);
                 let mut doctype = Doctype::new();
                 doctype.force_quirks = true;
+                self.state = State::Data;
                 Some(vec![Token::Doctype(doctype)])
             }
             None => {
@@ -1591,35 +2117,31 @@ impl HtmlTokenizer {
         // section 12.2.5.55
         match c {
             Some(c) if ascii_whitespace(c as u32) => {
-                self.state = State::AfterAttributeName;
+                self.state = State::AfterDoctypeName;
                 None
             }
             Some('>') => {
                 self.state = State::Data;
-                let doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let doctype = self.take_doctype();
                 Some(vec![Token::Doctype(doctype)])
             }
             Some(c) if ascii_upper_alpha(c as u32) => {
-                self.doctype
-                    .as_mut()
-                    .unwrap()
-                    .append_to_name(c.to_ascii_lowercase());
+                self.cur_doctype().append_to_name(c.to_ascii_lowercase());
                 None
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.doctype.as_mut().unwrap().append_to_name('\u{FFFD}');
+                self.cur_doctype().append_to_name('\u{FFFD}');
                 None
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = Doctype::new();
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             Some(c) => {
-                self.doctype.as_mut().unwrap().append_to_name(c);
+                self.cur_doctype().append_to_name(c);
                 None
             }
         }
@@ -1631,28 +2153,19 @@ impl HtmlTokenizer {
             Some(c) if ascii_whitespace(c as u32) => return None,
             Some('>') => {
                 self.state = State::Data;
-                let doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let doctype = self.take_doctype();
                 return Some(vec![Token::Doctype(doctype)]);
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = Doctype::new();
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 return Some(vec![Token::Doctype(doctype), Token::Eof]);
             }
             Some(c) => {
                 let mut peek: [char; 5] = ['\0'; 5];
                 let read = self.html.read_multiple(&mut peek);
-                if read != 5 {
-                    // backtrack and reconsume `c`
-                    self.html.backtrack_multiple(read);
-                    self.error(ParseHtmlError::InvalidCharacterSequenceAfterDoctypeName);
-                    self.doctype.as_mut().unwrap().force_quirks = true;
-                    return self.bogus_doctype(Some(c));
-                }
-
-                let peeked = peek.iter().collect::<String>().to_ascii_lowercase();
+                let peeked = peek[..read].iter().collect::<String>().to_ascii_lowercase();
                 if c.to_ascii_lowercase() == 'p' && peeked == "ublic" {
                     // consume and switch state
                     self.state = State::AfterDoctypePublicKeyword;
@@ -1664,8 +2177,11 @@ impl HtmlTokenizer {
                     return None;
                 }
 
+                // backtrack and reconsume `c`
+                self.html.backtrack_multiple(read);
                 self.error(ParseHtmlError::InvalidCharacterSequenceAfterDoctypeName);
-                self.doctype.as_mut().unwrap().force_quirks = true;
+                self.cur_doctype().force_quirks = true;
+                self.state = State::BogusDoctype;
                 return self.bogus_doctype(Some(c));
             }
         }
@@ -1680,34 +2196,33 @@ impl HtmlTokenizer {
             }
             Some('"') => {
                 self.error(ParseHtmlError::MissingWhitespaceAfterDoctypePublicKeyword);
-                self.doctype.as_mut().unwrap().public_id = Some("".into());
+                self.cur_doctype().public_id = Some("".into());
                 self.state = State::DoctypePublicIdentifierDoubleQuoted;
                 None
             }
             Some('\'') => {
                 self.error(ParseHtmlError::MissingWhitespaceAfterDoctypePublicKeyword);
-                self.doctype.as_mut().unwrap().public_id = Some("".into());
+                self.cur_doctype().public_id = Some("".into());
                 self.state = State::DoctypePublicIdentifierSingleQuoted;
                 None
             }
             Some('>') => {
                 self.error(ParseHtmlError::MissingDoctypePublicIdentifier);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 self.state = State::Data;
                 Some(vec![Token::Doctype(doctype)])
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             _ => {
                 self.error(ParseHtmlError::MissingQuoteBeforeDoctypePublicIdentifier);
-                self.doctype.as_mut().unwrap().force_quirks = true;
+                self.cur_doctype().force_quirks = true;
+                self.state = State::BogusDoctype;
                 self.bogus_doctype(c)
             }
         }
@@ -1718,33 +2233,32 @@ impl HtmlTokenizer {
         match c {
             Some(c) if ascii_whitespace(c as u32) => None,
             Some('"') => {
-                self.doctype.as_mut().unwrap().public_id = Some("".into());
+                self.cur_doctype().public_id = Some("".into());
                 self.state = State::DoctypePublicIdentifierDoubleQuoted;
                 None
             }
             Some('\'') => {
-                self.doctype.as_mut().unwrap().public_id = Some("".into());
+                self.cur_doctype().public_id = Some("".into());
                 self.state = State::DoctypePublicIdentifierSingleQuoted;
                 None
             }
             Some('>') => {
                 self.error(ParseHtmlError::MissingDoctypePublicIdentifier);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 self.state = State::Data;
                 Some(vec![Token::Doctype(doctype)])
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             _ => {
                 self.error(ParseHtmlError::MissingQuoteBeforeDoctypePublicIdentifier);
-                self.doctype.as_mut().unwrap().force_quirks = true;
+                self.cur_doctype().force_quirks = true;
+                self.state = State::BogusDoctype;
                 self.bogus_doctype(c)
             }
         }
@@ -1759,29 +2273,24 @@ impl HtmlTokenizer {
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.doctype
-                    .as_mut()
-                    .unwrap()
-                    .append_to_public_id('\u{FFFD}');
+                self.cur_doctype().append_to_public_id('\u{FFFD}');
                 None
             }
             Some('>') => {
                 self.error(ParseHtmlError::AbruptDoctypePublicIdentifier);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 self.state = State::Data;
                 Some(vec![Token::Doctype(doctype)])
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             Some(c) => {
-                self.doctype.as_mut().unwrap().append_to_public_id(c);
+                self.cur_doctype().append_to_public_id(c);
                 None
             }
         }
@@ -1796,29 +2305,24 @@ impl HtmlTokenizer {
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.doctype
-                    .as_mut()
-                    .unwrap()
-                    .append_to_public_id('\u{FFFD}');
+                self.cur_doctype().append_to_public_id('\u{FFFD}');
                 None
             }
             Some('>') => {
                 self.error(ParseHtmlError::AbruptDoctypePublicIdentifier);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 self.state = State::Data;
                 Some(vec![Token::Doctype(doctype)])
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             Some(c) => {
-                self.doctype.as_mut().unwrap().append_to_public_id(c);
+                self.cur_doctype().append_to_public_id(c);
                 None
             }
         }
@@ -1833,15 +2337,14 @@ impl HtmlTokenizer {
             }
             Some('>') => {
                 self.state = State::Data;
-                let doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let doctype = self.take_doctype();
                 Some(vec![Token::Doctype(doctype)])
             }
             Some('"') => {
                 self.error(
                     ParseHtmlError::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
                 );
-                self.doctype.as_mut().unwrap().system_id = Some("".into());
+                self.cur_doctype().system_id = Some("".into());
                 self.state = State::DoctypeSystemIdentifierDoubleQuoted;
                 None
             }
@@ -1849,20 +2352,20 @@ impl HtmlTokenizer {
                 self.error(
                     ParseHtmlError::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
                 );
-                self.doctype.as_mut().unwrap().system_id = Some("".into());
+                self.cur_doctype().system_id = Some("".into());
                 self.state = State::DoctypeSystemIdentifierSingleQuoted;
                 None
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             _ => {
                 self.error(ParseHtmlError::MissingQuoteBeforeDoctypeSystemIdentifier);
-                self.doctype.as_mut().unwrap().force_quirks = true;
+                self.cur_doctype().force_quirks = true;
+                self.state = State::BogusDoctype;
                 self.bogus_doctype(c)
             }
         }
@@ -1877,30 +2380,29 @@ impl HtmlTokenizer {
             Some(c) if ascii_whitespace(c as u32) => None,
             Some('>') => {
                 self.state = State::Data;
-                let doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let doctype = self.take_doctype();
                 Some(vec![Token::Doctype(doctype)])
             }
             Some('"') => {
-                self.doctype.as_mut().unwrap().system_id = Some("".into());
+                self.cur_doctype().system_id = Some("".into());
                 self.state = State::DoctypeSystemIdentifierDoubleQuoted;
                 None
             }
             Some('\'') => {
-                self.doctype.as_mut().unwrap().system_id = Some("".into());
+                self.cur_doctype().system_id = Some("".into());
                 self.state = State::DoctypeSystemIdentifierSingleQuoted;
                 None
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             _ => {
                 self.error(ParseHtmlError::MissingQuoteBeforeDoctypeSystemIdentifier);
-                self.doctype.as_mut().unwrap().force_quirks = true;
+                self.cur_doctype().force_quirks = true;
+                self.state = State::BogusDoctype;
                 self.bogus_doctype(c)
             }
         }
@@ -1915,34 +2417,33 @@ impl HtmlTokenizer {
             }
             Some('"') => {
                 self.error(ParseHtmlError::MissingWhitespaceAfterDoctypeSystemKeyword);
-                self.doctype.as_mut().unwrap().system_id = Some("".into());
+                self.cur_doctype().system_id = Some("".into());
                 self.state = State::DoctypeSystemIdentifierDoubleQuoted;
                 None
             }
             Some('\'') => {
                 self.error(ParseHtmlError::MissingWhitespaceAfterDoctypeSystemKeyword);
-                self.doctype.as_mut().unwrap().system_id = Some("".into());
+                self.cur_doctype().system_id = Some("".into());
                 self.state = State::DoctypeSystemIdentifierSingleQuoted;
                 None
             }
             Some('>') => {
                 self.error(ParseHtmlError::MissingDoctypeSystemIdentifier);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 self.state = State::Data;
                 Some(vec![Token::Doctype(doctype)])
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             _ => {
                 self.error(ParseHtmlError::MissingQuoteBeforeDoctypeSystemIdentifier);
-                self.doctype.as_mut().unwrap().force_quirks = true;
+                self.cur_doctype().force_quirks = true;
+                self.state = State::BogusDoctype;
                 self.bogus_doctype(c)
             }
         }
@@ -1953,33 +2454,32 @@ impl HtmlTokenizer {
         match c {
             Some(c) if ascii_whitespace(c as u32) => None,
             Some('"') => {
-                self.doctype.as_mut().unwrap().system_id = Some("".into());
+                self.cur_doctype().system_id = Some("".into());
                 self.state = State::DoctypeSystemIdentifierDoubleQuoted;
                 None
             }
             Some('\'') => {
-                self.doctype.as_mut().unwrap().system_id = Some("".into());
+                self.cur_doctype().system_id = Some("".into());
                 self.state = State::DoctypeSystemIdentifierSingleQuoted;
                 None
             }
             Some('>') => {
                 self.error(ParseHtmlError::MissingDoctypeSystemIdentifier);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 self.state = State::Data;
                 Some(vec![Token::Doctype(doctype)])
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             _ => {
                 self.error(ParseHtmlError::MissingQuoteBeforeDoctypeSystemIdentifier);
-                self.doctype.as_mut().unwrap().force_quirks = true;
+                self.cur_doctype().force_quirks = true;
+                self.state = State::BogusDoctype;
                 self.bogus_doctype(c)
             }
         }
@@ -1994,29 +2494,24 @@ impl HtmlTokenizer {
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.doctype
-                    .as_mut()
-                    .unwrap()
-                    .append_to_system_id('\u{FFFD}');
+                self.cur_doctype().append_to_system_id('\u{FFFD}');
                 None
             }
             Some('>') => {
                 self.error(ParseHtmlError::AbruptDoctypeSystemIdentifier);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 self.state = State::Data;
                 Some(vec![Token::Doctype(doctype)])
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             Some(c) => {
-                self.doctype.as_mut().unwrap().append_to_system_id(c);
+                self.cur_doctype().append_to_system_id(c);
                 None
             }
         }
@@ -2031,29 +2526,24 @@ impl HtmlTokenizer {
             }
             Some('\0') => {
                 self.error(ParseHtmlError::UnexpectedNullCharacter);
-                self.doctype
-                    .as_mut()
-                    .unwrap()
-                    .append_to_system_id('\u{FFFD}');
+                self.cur_doctype().append_to_system_id('\u{FFFD}');
                 None
             }
             Some('>') => {
                 self.error(ParseHtmlError::AbruptDoctypeSystemIdentifier);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 self.state = State::Data;
                 Some(vec![Token::Doctype(doctype)])
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             Some(c) => {
-                self.doctype.as_mut().unwrap().append_to_system_id(c);
+                self.cur_doctype().append_to_system_id(c);
                 None
             }
         }
@@ -2065,20 +2555,19 @@ impl HtmlTokenizer {
             Some(c) if ascii_whitespace(c as u32) => None,
             Some('>') => {
                 self.state = State::Data;
-                let doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let doctype = self.take_doctype();
                 Some(vec![Token::Doctype(doctype)])
             }
             None => {
                 self.error(ParseHtmlError::EofInDoctype);
-                let mut doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let mut doctype = self.take_doctype();
                 doctype.force_quirks = true;
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             _ => {
                 self.error(ParseHtmlError::UnexpectedCharacterAfterDoctypeSystemIdentifier);
                 // do NOT set the force-quirks flag
+                self.state = State::BogusDoctype;
                 self.bogus_doctype(c)
             }
         }
@@ -2089,8 +2578,7 @@ impl HtmlTokenizer {
         match c {
             Some('>') => {
                 self.state = State::Data;
-                let doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let doctype = self.take_doctype();
                 Some(vec![Token::Doctype(doctype)])
             }
             Some('\0') => {
@@ -2098,8 +2586,7 @@ impl HtmlTokenizer {
                 None
             }
             None => {
-                let doctype = self.doctype.clone().unwrap();
-                self.doctype = None;
+                let doctype = self.take_doctype();
                 Some(vec![Token::Doctype(doctype), Token::Eof])
             }
             _ => None,
@@ -2129,6 +2616,7 @@ impl HtmlTokenizer {
                 None
             }
             _ => {
+                self.state = State::CdataSection;
                 let mut tok = vec![Token::Character(']')];
                 let mut reconsumed = self.cdata_section(c).unwrap_or_default();
                 tok.append(&mut reconsumed);
@@ -2146,6 +2634,7 @@ impl HtmlTokenizer {
                 None
             }
             _ => {
+                self.state = State::CdataSection;
                 let mut tok = vec![Token::Character(']'), Token::Character(']')];
                 let mut reconsumed = self.cdata_section(c).unwrap_or_default();
                 tok.append(&mut reconsumed);
@@ -2158,25 +2647,93 @@ impl HtmlTokenizer {
         // section 12.2.5.72
         self.temp_buf = "&".into();
         match c {
-            Some(c) if ascii_alphanumeric(c as u32) => self.named_character_reference(Some(c)),
+            Some(c) if ascii_alphanumeric(c as u32) => {
+                self.state = State::NamedCharacterReference;
+                self.named_character_reference(Some(c))
+            }
             Some('#') => {
                 self.temp_buf.push('#');
                 self.state = State::NumericCharacterReference;
                 None
             }
             _ => {
-                // TODO: flush code points consumed as a character reference
-                self.tokenize(*self.return_state.as_ref().unwrap(), c)
+                let flushed = self.flush_code_points_consumed_as_char_ref();
+                self.reconsume_in_return_state(flushed, c)
             }
         }
     }
 
-    fn named_character_reference(&mut self, _c: Option<char>) -> Option<Vec<Token>> {
+    fn named_character_reference(&mut self, c: Option<char>) -> Option<Vec<Token>> {
         // section 12.2.5.73
-        // TODO: implement
-        // see https://html.spec.whatwg.org/multipage/parsing.html#named-character-reference-state
-        // see https://html.spec.whatwg.org/multipage/named-characters.html#named-character-references
-        panic!();
+        // `c` is the first character of the name; the rest are read ahead to
+        //   find the longest match, and anything past it is given back
+        let mut name = String::new();
+        let mut read = 0usize;
+        // tuple of (chars read past `c`, length of name, replacement)
+        let mut longest: Option<(usize, usize, &'static str)> = None;
+
+        let mut next = c;
+        while let Some(next_char) = next {
+            name.push(next_char);
+            if !entities::is_prefix(&name) {
+                break;
+            }
+            if let Some(replacement) = entities::lookup(&name) {
+                longest = Some((read, name.len(), replacement));
+            }
+            next = self.html.read();
+            if next.is_some() {
+                read += 1;
+            }
+        }
+
+        match longest {
+            Some((matched_read, name_len, replacement)) => {
+                // only consume the characters that are part of the match
+                self.html.backtrack_multiple(read - matched_read);
+                let matched = &name[..name_len];
+                self.temp_buf.push_str(matched);
+
+                let last_is_semicolon = matched.ends_with(';');
+                if !last_is_semicolon && self.consumed_as_part_of_attribute() {
+                    // for historical reasons, `&copy=` and `&copyx` in attribute
+                    //   values are not character references
+                    match self.html.peek() {
+                        Some(next_char)
+                            if next_char == '=' || ascii_alphanumeric(next_char as u32) =>
+                        {
+                            let flushed = self.flush_code_points_consumed_as_char_ref();
+                            self.state = self.return_state.unwrap_or(State::Data);
+                            return flushed;
+                        }
+                        _ => (),
+                    }
+                }
+
+                if !last_is_semicolon {
+                    self.error(ParseHtmlError::MissingSemicolonAfterCharacterReference);
+                }
+                self.temp_buf = replacement.into();
+                let flushed = self.flush_code_points_consumed_as_char_ref();
+                self.state = self.return_state.unwrap_or(State::Data);
+                flushed
+            }
+            None => {
+                // give back everything, including `c`, and let the ambiguous
+                //   ampersand state consume it
+                self.html.backtrack_multiple(read);
+                let mut tok = self
+                    .flush_code_points_consumed_as_char_ref()
+                    .unwrap_or_default();
+                self.state = State::AmbiguousAmpersand;
+                let mut reconsumed = self.ambiguous_ampersand(c).unwrap_or_default();
+                tok.append(&mut reconsumed);
+                if tok.is_empty() {
+                    return None;
+                }
+                Some(tok)
+            }
+        }
     }
 
     fn ambiguous_ampersand(&mut self, c: Option<char>) -> Option<Vec<Token>> {
@@ -2184,16 +2741,16 @@ impl HtmlTokenizer {
         match c {
             Some(c) if ascii_alphanumeric(c as u32) => {
                 if self.consumed_as_part_of_attribute() {
-                    self.tag.as_mut().unwrap().append_to_cur_attr_value(c);
+                    self.cur_tag().append_to_cur_attr_value(c);
                     return None;
                 }
                 Some(vec![Token::Character(c)])
             }
             Some(';') => {
                 self.error(ParseHtmlError::UnknownNamedCharacterReference);
-                self.tokenize(*self.return_state.as_ref().unwrap(), Some(';'))
+                self.reconsume_in_return_state(None, Some(';'))
             }
-            _ => self.tokenize(*self.return_state.as_ref().unwrap(), c),
+            _ => self.reconsume_in_return_state(None, c),
         }
     }
 
@@ -2201,23 +2758,29 @@ impl HtmlTokenizer {
         // section 12.2.5.75
         self.char_ref_code = 0;
         match c {
-            Some('X') | Some('x') => {
-                self.temp_buf.push(c.unwrap());
+            Some(c) if c == 'X' || c == 'x' => {
+                self.temp_buf.push(c);
                 self.state = State::HexadecimalCharacterReferenceStart;
                 None
             }
-            _ => self.decimal_character_reference_start(c),
+            _ => {
+                self.state = State::DecimalCharacterReferenceStart;
+                self.decimal_character_reference_start(c)
+            }
         }
     }
 
     fn hexadecimal_character_reference_start(&mut self, c: Option<char>) -> Option<Vec<Token>> {
         // section 12.2.5.76
         match c {
-            Some(c) if ascii_hex_digit(c as u32) => self.hexadecimal_character_reference(Some(c)),
+            Some(c) if ascii_hex_digit(c as u32) => {
+                self.state = State::HexadecimalCharacterReference;
+                self.hexadecimal_character_reference(Some(c))
+            }
             _ => {
                 self.error(ParseHtmlError::AbsenseOfDigitsInNumericCharacterReference);
-                // TODO: flush code points consumed as a character reference
-                self.tokenize(*self.return_state.as_ref().unwrap(), c)
+                let flushed = self.flush_code_points_consumed_as_char_ref();
+                self.reconsume_in_return_state(flushed, c)
             }
         }
     }
@@ -2225,11 +2788,14 @@ impl HtmlTokenizer {
     fn decimal_character_reference_start(&mut self, c: Option<char>) -> Option<Vec<Token>> {
         // section 12.2.5.77
         match c {
-            Some(c) if ascii_digit(c as u32) => self.decimal_character_reference(Some(c)),
+            Some(c) if ascii_digit(c as u32) => {
+                self.state = State::DecimalCharacterReference;
+                self.decimal_character_reference(Some(c))
+            }
             _ => {
                 self.error(ParseHtmlError::AbsenseOfDigitsInNumericCharacterReference);
-                // TODO: flush code points consumed as a character reference
-                self.tokenize(*self.return_state.as_ref().unwrap(), c)
+                let flushed = self.flush_code_points_consumed_as_char_ref();
+                self.reconsume_in_return_state(flushed, c)
             }
         }
     }
@@ -2237,19 +2803,26 @@ impl HtmlTokenizer {
     fn hexadecimal_character_reference(&mut self, c: Option<char>) -> Option<Vec<Token>> {
         // section 12.2.5.78
         match c {
+            // saturating, since anything past 0x10FFFF is out of range anyway
             Some(c) if ascii_digit(c as u32) => {
-                self.char_ref_code *= 16;
-                self.char_ref_code += (c as u32) - 0x30;
+                self.char_ref_code = self
+                    .char_ref_code
+                    .saturating_mul(16)
+                    .saturating_add((c as u32) - 0x30);
                 None
             }
             Some(c) if ascii_upper_hex_digit(c as u32) => {
-                self.char_ref_code *= 16;
-                self.char_ref_code += (c as u32) - 0x37;
+                self.char_ref_code = self
+                    .char_ref_code
+                    .saturating_mul(16)
+                    .saturating_add((c as u32) - 0x37);
                 None
             }
             Some(c) if ascii_lower_hex_digit(c as u32) => {
-                self.char_ref_code *= 16;
-                self.char_ref_code += (c as u32) - 0x57;
+                self.char_ref_code = self
+                    .char_ref_code
+                    .saturating_mul(16)
+                    .saturating_add((c as u32) - 0x57);
                 None
             }
             Some(';') => {
@@ -2267,8 +2840,10 @@ impl HtmlTokenizer {
         // section 12.2.5.79
         match c {
             Some(c) if ascii_digit(c as u32) => {
-                self.char_ref_code *= 10;
-                self.char_ref_code += (c as u32) - 0x30;
+                self.char_ref_code = self
+                    .char_ref_code
+                    .saturating_mul(10)
+                    .saturating_add((c as u32) - 0x30);
                 None
             }
             Some(';') => {
@@ -2346,25 +2921,14 @@ impl HtmlTokenizer {
             _ => (),
         }
 
-        // NOTE: will panic on invalid character!
-        // TODO: will that happen?
+        // the checks above leave only scalar values, but don't trust that
         self.temp_buf = "".into();
         self.temp_buf
-            .push(char::from_u32(self.char_ref_code).unwrap());
+            .push(char::from_u32(self.char_ref_code).unwrap_or('\u{FFFD}'));
 
-        let mut ret: Option<Vec<Token>> = None;
-
-        // flush code points consumed as a character reference
-        if self.consumed_as_part_of_attribute() {
-            self.tag
-                .as_mut()
-                .unwrap()
-                .append_to_cur_attr_value_str(&self.temp_buf);
-        } else {
-            ret = Some(self.temp_buf_to_tokens());
-        }
+        let ret = self.flush_code_points_consumed_as_char_ref();
 
-        self.state = self.return_state.unwrap();
+        self.state = self.return_state.unwrap_or(State::Data);
         self.return_state = None;
 
         ret
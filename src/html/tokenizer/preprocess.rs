@@ -0,0 +1,98 @@
+/* ============================================================================
+ * File:   preprocess.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Implements <https://html.spec.whatwg.org/multipage/parsing.html#preprocessing-the-input-stream>
+use crate::html::parser::detail::ParseHtmlError;
+use crate::infra::code_point::*;
+
+/// The parse errors and dropped LFs `InputPreprocessor` found in a chunk
+pub struct PreprocessedChunk {
+    /// The characters that are parse errors, by byte offset in the output
+    pub errors: Vec<(usize, ParseHtmlError)>,
+    /// The offsets just past each newline that was a CRLF, i.e. where a LF
+    ///   was dropped
+    pub crlf_offsets: Vec<usize>,
+}
+
+/// Sits between the decoder and the tokenizer.
+/// Normalizes newlines (CR and CRLF become LF) and finds the characters that
+///   are parse errors just by appearing in the input stream.
+///
+/// Input can be given in chunks; a CR at the end of one chunk and a LF at the
+///   start of the next are still one newline.
+#[derive(Default)]
+pub struct InputPreprocessor {
+    // the last character processed was a CR, so a LF right after it is dropped
+    last_was_cr: bool,
+}
+
+impl InputPreprocessor {
+    pub fn new() -> InputPreprocessor {
+        InputPreprocessor { last_was_cr: false }
+    }
+
+    /// Preprocesses a chunk of input, appending the result to `output`.
+    /// Offsets in the result are relative to the start of this chunk's
+    ///   output.
+    pub fn process(&mut self, chunk: &str, output: &mut String) -> PreprocessedChunk {
+        let mut errors = vec![];
+        let mut crlf_offsets = vec![];
+        let start = output.len();
+        output.reserve(chunk.len());
+
+        // runs without a CR are copied over in one go
+        let mut run_start = 0;
+        for (offset, c) in chunk.char_indices() {
+            if c == '\r' || (c == '\n' && self.last_was_cr) {
+                output.push_str(&chunk[run_start..offset]);
+                if c == '\r' {
+                    output.push('\n');
+                } else {
+                    crlf_offsets.push(output.len() - start);
+                }
+                run_start = offset + c.len_utf8();
+            } else if let Some(err) = InputPreprocessor::stream_error(c as u32) {
+                errors.push((output.len() - start + offset - run_start, err));
+            }
+            self.last_was_cr = c == '\r';
+        }
+        output.push_str(&chunk[run_start..]);
+
+        PreprocessedChunk {
+            errors,
+            crlf_offsets,
+        }
+    }
+
+    // section 12.2.3.5
+    fn stream_error(code_point: u32) -> Option<ParseHtmlError> {
+        if surrogate(code_point) {
+            Some(ParseHtmlError::SurrogateCharacterInInputStream)
+        } else if noncharacter(code_point) {
+            Some(ParseHtmlError::NoncharacterInInputStream)
+        } else if control(code_point) && !ascii_whitespace(code_point) && code_point != 0 {
+            Some(ParseHtmlError::ControlCharacterInInputStream)
+        } else {
+            None
+        }
+    }
+}
@@ -108,6 +108,9 @@ pub fn ascii_digit(code_point: u32) -> bool {
 }
 
 pub fn ascii_upper_hex_digit(code_point: u32) -> bool {
+    if ascii_digit(code_point) {
+        return true;
+    }
     match code_point {
         0x41..=0x46 => true,
         _ => false,
@@ -115,6 +118,9 @@ pub fn ascii_upper_hex_digit(code_point: u32) -> bool {
 }
 
 pub fn ascii_lower_hex_digit(code_point: u32) -> bool {
+    if ascii_digit(code_point) {
+        return true;
+    }
     match code_point {
         0x61..=0x66 => true,
         _ => false,
@@ -122,11 +128,7 @@ pub fn ascii_lower_hex_digit(code_point: u32) -> bool {
 }
 
 pub fn ascii_hex_digit(code_point: u32) -> bool {
-    match code_point {
-        0x41..=0x46 => true,
-        0x61..=0x66 => true,
-        _ => false,
-    }
+    ascii_upper_hex_digit(code_point) || ascii_lower_hex_digit(code_point)
 }
 
 pub fn ascii_upper_alpha(code_point: u32) -> bool {
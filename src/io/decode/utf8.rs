@@ -23,6 +23,7 @@
 use crate::io::decode::{ConvertByteResult, Decoder};
 use std::char;
 use std::io::Read;
+use std::str;
 
 // Implements the UTF-8 decoder as spec'd at <https://encoding.spec.whatwg.org/#utf-8-decoder>
 pub struct Utf8Decoder {
@@ -123,3 +124,59 @@ impl Decoder for Utf8Decoder {
         //Err(-1)
     }
 }
+
+/// Decodes UTF-8 that arrives in chunks, which may split a code point
+/// Invalid sequences are replaced with U+FFFD like the UTF-8 decoder above.
+#[derive(Default)]
+pub struct Utf8ChunkDecoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8ChunkDecoder {
+    pub fn new() -> Utf8ChunkDecoder {
+        Utf8ChunkDecoder { pending: vec![] }
+    }
+
+    /// Decodes as much of `bytes` (after any bytes held back from the previous
+    ///   chunk) as possible
+    /// An incomplete sequence at the end is held back unless `last` is set,
+    ///   in which case it is replaced with U+FFFD.
+    pub fn decode(&mut self, bytes: &[u8], last: bool) -> String {
+        self.pending.extend_from_slice(bytes);
+        let mut output = String::with_capacity(self.pending.len());
+
+        let mut start = 0usize;
+        loop {
+            match str::from_utf8(&self.pending[start..]) {
+                Ok(valid) => {
+                    output.push_str(valid);
+                    start = self.pending.len();
+                    break;
+                }
+                Err(err) => {
+                    let valid_end = start + err.valid_up_to();
+                    // `from_utf8` already checked this part
+                    output.push_str(str::from_utf8(&self.pending[start..valid_end]).unwrap());
+                    match err.error_len() {
+                        Some(len) => {
+                            output.push('\u{FFFD}');
+                            start = valid_end + len;
+                        }
+                        None => {
+                            // incomplete sequence at the end
+                            start = valid_end;
+                            if last {
+                                output.push('\u{FFFD}');
+                                start = self.pending.len();
+                            }
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending.drain(..start);
+        output
+    }
+}
@@ -23,106 +23,176 @@
 use std::io::BufRead;
 
 /// A line-based char iterator.
-/// EOF is stored internally as setting `line` to `buffer.len()` or `line_offsets.len()`
+/// The input is kept as one string and the position as a byte offset into
+///   it; the line and line offset (in chars) are tracked alongside so all of
+///   them are available without rescanning.
+/// EOF is stored internally as `pos` being `buffer.len()`
+///
+/// Input can also be appended in chunks with `push_str`.
+/// Until `finish` is called, running out of input is not EOF; `read` returns
+///   `None` and callers should check `finished` to tell the two apart.
+/// Input before the current position is dropped as more is appended, so
+///   only what's been read since the last `push_str` can be backtracked
+///   over. Offsets and states are still from the start of the whole input.
 pub struct LineOffsetIterator {
-    buffer: Vec<String>,
+    buffer: String,
+    // the offset of `buffer` in the whole input, and the line and line
+    //   offset it starts at
+    base: usize,
+    base_line: usize,
+    base_line_pos: usize,
+    // the offset in the whole input of the start of each line from
+    //   `base_line` on
     line_offsets: Vec<usize>,
-    eof_offset: usize,
+    // the position in `buffer`
+    pos: usize,
     line: usize,
     line_pos: usize,
+    finished: bool,
 }
 
+// the consumed input is only dropped once there's at least this much of it
+//   and it's at least half of the buffer, so it isn't copied around too often
+const MIN_TRIM: usize = 4096;
+
 impl LineOffsetIterator {
     pub fn new<T: BufRead>(buffer: &mut T) -> LineOffsetIterator {
-        let buf = LineOffsetIterator::read_all_lines(buffer);
+        let buf = LineOffsetIterator::read_all(buffer);
         let offsets = LineOffsetIterator::get_line_offsets(&buf);
         LineOffsetIterator {
             buffer: buf,
-            line_offsets: offsets.0,
-            eof_offset: offsets.1,
+            base: 0,
+            base_line: 0,
+            base_line_pos: 0,
+            line_offsets: offsets,
+            pos: 0,
+            line: 0,
+            line_pos: 0,
+            finished: true,
+        }
+    }
+
+    /// Creates an iterator with no input that will be given input in chunks
+    pub fn new_streaming() -> LineOffsetIterator {
+        LineOffsetIterator {
+            buffer: String::new(),
+            base: 0,
+            base_line: 0,
+            base_line_pos: 0,
+            line_offsets: vec![0],
+            pos: 0,
             line: 0,
             line_pos: 0,
+            finished: false,
         }
     }
 
-    fn read_all_lines<T: BufRead>(buffer: &mut T) -> Vec<String> {
-        let mut vec: Vec<String> = vec![];
+    /// Appends a chunk of input
+    /// Panics if `finish` was already called.
+    pub fn push_str(&mut self, chunk: &str) {
+        assert!(!self.finished, "`push_str` called after `finish`");
 
-        loop {
-            let mut line = String::new();
-            match buffer.read_line(&mut line) {
-                Ok(0) => break, // EOF
-                Ok(_) => vec.push(line),
-                Err(_) => panic!(),
+        self.trim();
+        let end = self.base + self.buffer.len();
+        for (offset, c) in chunk.char_indices() {
+            if c == '\n' {
+                self.line_offsets.push(end + offset + 1);
             }
         }
-
-        vec
+        self.buffer.push_str(chunk);
     }
 
-    // returns a tuple with `0` containing the line offsets and `1` containing the EOF offset
-    fn get_line_offsets(lines: &Vec<String>) -> (Vec<usize>, usize) {
-        let mut vec: Vec<usize> = Vec::with_capacity(lines.len());
-
-        let mut offset = 0usize;
-        for line in lines {
-            vec.push(offset);
-            offset += line.len();
+    // drops the input before the current position
+    fn trim(&mut self) {
+        if self.pos < MIN_TRIM || self.pos < self.buffer.len() - self.pos {
+            return;
         }
+        self.buffer.drain(..self.pos);
+        self.base += self.pos;
+        self.pos = 0;
+        self.line_offsets.drain(..self.line - self.base_line);
+        self.base_line = self.line;
+        self.base_line_pos = self.line_pos;
+    }
 
-        (vec, offset)
+    /// Marks the end of the input; reaching the end of the buffer is EOF after this
+    pub fn finish(&mut self) {
+        self.finished = true;
     }
 
-    pub fn seek(&mut self, new_pos: usize) {
-        // TODO: add error handling
-        if new_pos > self.eof_offset {
-            panic!();
-        }
+    /// Checks if all input has been given; see `finish`
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Gets the input that can be read before running out, without consuming it
+    pub fn remaining(&self) -> &str {
+        &self.buffer[self.pos..]
+    }
 
-        // TODO: FIXME: fix
-        if new_pos == self.eof_offset {
-            unimplemented!();
+    fn read_all<T: BufRead>(buffer: &mut T) -> String {
+        let mut string = String::new();
+        match buffer.read_to_string(&mut string) {
+            Ok(_) => string,
+            Err(_) => panic!(),
         }
+    }
 
-        // find what line `new_pos` is in
-        let new_line = {
-            let mut line = 0usize;
-            for (i, this_off) in self.line_offsets.iter().enumerate() {
-                line = i;
-                if *this_off >= new_pos {
-                    break;
-                }
+    // returns the byte offset of the start of each line
+    fn get_line_offsets(buffer: &str) -> Vec<usize> {
+        let mut vec: Vec<usize> = vec![0];
+        for (offset, c) in buffer.char_indices() {
+            if c == '\n' {
+                vec.push(offset + 1);
             }
-            line
-        };
-
-        let new_line_off = new_pos - self.line_offsets.get(new_line).unwrap();
+        }
+        vec
+    }
 
-        self.line = new_line;
-        self.line_pos = new_line_off;
+    // the line containing byte offset `offset` of the whole input
+    fn line_of(&self, offset: usize) -> usize {
+        self.base_line + self.line_offsets.partition_point(|&start| start <= offset) - 1
     }
 
-    // same as `peek`, but increments the pointer before returning
-    pub fn read(&mut self) -> Option<char> {
-        // possible EOF?
-        if self.line == self.buffer.len() {
-            return None;
+    // the line offset of byte offset `offset` of the whole input, which is on
+    //   line `line`
+    fn line_pos_of(&self, line: usize, offset: usize) -> usize {
+        let start = self.line_offsets[line - self.base_line];
+        if start >= self.base {
+            self.buffer[start - self.base..offset - self.base]
+                .chars()
+                .count()
+        } else {
+            // the start of the line was dropped
+            self.base_line_pos + self.buffer[..offset - self.base].chars().count()
         }
+    }
 
-        let cur_line = self.buffer.get(self.line).unwrap();
-        let cur_line_len = cur_line.chars().count();
-        if self.line_pos >= cur_line_len {
+    pub fn seek(&mut self, new_pos: usize) {
+        // TODO: add error handling
+        if new_pos < self.base
+            || new_pos > self.base + self.buffer.len()
+            || !self.buffer.is_char_boundary(new_pos - self.base)
+        {
             panic!();
         }
-        let c = cur_line.chars().nth(self.line_pos).unwrap();
+
+        self.pos = new_pos - self.base;
+        self.line = self.line_of(new_pos);
+        self.line_pos = self.line_pos_of(self.line, new_pos);
+    }
+
+    // same as `peek`, but increments the pointer before returning
+    pub fn read(&mut self) -> Option<char> {
+        let c = self.peek()?;
 
         // increment pointer
-        let new_line_pos = self.line_pos + 1;
-        if new_line_pos == cur_line_len {
+        self.pos += c.len_utf8();
+        if c == '\n' {
             self.line += 1;
             self.line_pos = 0;
         } else {
-            self.line_pos = new_line_pos;
+            self.line_pos += 1;
         }
 
         Some(c)
@@ -146,6 +216,20 @@ impl LineOffsetIterator {
         self.read();
     }
 
+    /// Consumes the next `len` bytes of input at once
+    /// `len` must end on a char boundary; see `remaining`
+    pub fn consume_bytes(&mut self, len: usize) {
+        let consumed = &self.buffer[self.pos..self.pos + len];
+        match consumed.rfind('\n') {
+            Some(last_newline) => {
+                self.line += consumed.bytes().filter(|&b| b == b'\n').count();
+                self.line_pos = consumed[last_newline + 1..].chars().count();
+            }
+            None => self.line_pos += consumed.chars().count(),
+        }
+        self.pos += len;
+    }
+
     pub fn consume_multiple(&mut self, count: usize) {
         for _ in 0..count {
             match self.read() {
@@ -157,26 +241,19 @@ impl LineOffsetIterator {
 
     pub fn backtrack(&mut self) {
         // beginning of buffer?
-        if self.line == 0 && self.line_pos == 0 {
-            return;
-        }
-
-        // EOF?
-        if self.line == self.buffer.len() {
-            self.line -= 1;
-            self.line_pos = self.buffer.get(self.line).unwrap().chars().count() - 1;
-            return;
-        }
+        let c = match self.buffer[..self.pos].chars().next_back() {
+            Some(c) => c,
+            None => return,
+        };
 
-        // beginning of line?
-        if self.line_pos == 0 {
+        self.pos -= c.len_utf8();
+        if c == '\n' {
+            // back to the end of the previous line
             self.line -= 1;
-            self.line_pos = self.buffer.get(self.line).unwrap().chars().count() - 1;
-            return;
+            self.line_pos = self.line_pos_of(self.line, self.base + self.pos);
+        } else {
+            self.line_pos -= 1;
         }
-
-        // middle of line
-        self.line_pos -= 1;
     }
 
     pub fn backtrack_multiple(&mut self, count: usize) {
@@ -187,50 +264,17 @@ impl LineOffsetIterator {
 
     // same as `read`, but doesn't increment the pointer
     pub fn peek(&self) -> Option<char> {
-        // possible EOF?
-        if self.line == self.buffer.len() {
-            return None;
-        }
-
-        let cur_line = self.buffer.get(self.line).unwrap();
-        let cur_line_len = cur_line.chars().count();
-        if self.line_pos >= cur_line_len {
-            panic!();
-        }
-        let c = cur_line.chars().nth(self.line_pos).unwrap();
-
-        Some(c)
+        self.buffer[self.pos..].chars().next()
     }
 
     pub fn peek_multiple(&mut self, buf: &mut [char]) -> usize {
         // attempts to peek `buf.len()` chars, but aborts early if EOF is reached
-        let mut line_to_peek = self.line;
-        let mut line_to_peek_off = self.line_pos;
-
+        let mut chars = self.buffer[self.pos..].chars();
         for n in 0..buf.len() {
-            // EOF?
-            if line_to_peek == self.buffer.len() {
-                return n;
-            }
-
-            // peek a char
-            let mut peeked_line = self.buffer.get(line_to_peek).unwrap();
-            let peeked_line_len = peeked_line.chars().count(); // TODO: don't do this every iteration
-
-            // at end of line?
-            if line_to_peek_off == peeked_line_len {
-                line_to_peek += 1;
-                line_to_peek_off = 0;
-                peeked_line = match self.buffer.get(line_to_peek) {
-                    None => {
-                        // now at EOF
-                        return n;
-                    }
-                    Some(line) => line,
-                }
+            match chars.next() {
+                Some(c) => buf[n] = c,
+                None => return n,
             }
-
-            buf[n] = peeked_line.chars().nth(line_to_peek_off).unwrap();
         }
 
         // `buf.len()` characters read successfully
@@ -244,7 +288,51 @@ impl LineOffsetIterator {
     }
 
     pub fn set_state(&mut self, state: (usize, usize)) {
+        let offset = self.offset_of(state);
+        self.pos = offset - self.base;
         self.line = state.0;
         self.line_pos = state.1;
     }
+
+    /// Gets the current byte offset from the start of the input
+    pub fn offset(&self) -> usize {
+        self.base + self.pos
+    }
+
+    /// Converts a byte offset from the start of the input into a state like
+    ///   the ones returned by `state`
+    /// The offset can't be before input that was dropped.
+    pub fn state_of(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_of(offset);
+        (line, self.line_pos_of(line, offset))
+    }
+
+    /// Converts a state returned by `state` into a byte offset from the start
+    ///   of the input
+    /// States in input that was dropped give the first offset still kept.
+    pub fn offset_of(&self, state: (usize, usize)) -> usize {
+        let (line, line_pos) = state;
+        let end = self.base + self.buffer.len();
+        if line < self.base_line {
+            return self.base;
+        }
+        match self.line_offsets.get(line - self.base_line) {
+            None => end,
+            Some(&line_offset) => {
+                // where counting starts, if the start of the line was dropped
+                let (start, skip) = if line_offset >= self.base {
+                    (line_offset, line_pos)
+                } else if line_pos >= self.base_line_pos {
+                    (self.base, line_pos - self.base_line_pos)
+                } else {
+                    return self.base;
+                };
+                let text = &self.buffer[start - self.base..];
+                match text.char_indices().nth(skip) {
+                    Some((offset, _)) => start + offset,
+                    None => end,
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,155 @@
+/* ============================================================================
+ * File:   html5lib_encoding.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Runs the html5lib-tests encoding suite against the encoding sniffer.
+// The `.dat` files are vendored in `tests/html5lib-tests/encoding`. Each case
+//   is a `#data` section with the document and an `#encoding` section with
+//   the label of the encoding it should be decoded with.
+// The sniffer is given no transport-layer hint. If it only finds a tentative
+//   encoding, the document is also parsed, as a `<meta>` past the prescanned
+//   bytes can still change it.
+// Cases in `KNOWN_FAILURES` are expected to diverge. The test fails if any
+//   other case diverges, or if a known failure starts passing.
+use encoding_rs::Encoding;
+use iron::html::parser::detail::EncodingCertainty;
+use iron::html::parser::encoding::sniff_encoding;
+use iron::html::parser::HtmlParser;
+use std::fs;
+
+const TEST_DIR: &str = "tests/html5lib-tests/encoding";
+
+// (file, data) of the cases that are known to diverge from the expected
+//   output
+const KNOWN_FAILURES: &[(&str, &str)] = &[
+    // the `<meta>` is written by a script, and scripts aren't run
+    (
+        "tests1.dat",
+        "<!DOCTYPE HTML>\n<script>document.write('<meta charset=\"ISO-8859-' + '2\">')</script>",
+    ),
+];
+
+// splits a `.dat` file into (data, expected encoding) pairs
+// the files aren't all UTF-8, so this works on bytes
+fn read_cases(bytes: &[u8]) -> Vec<(Vec<u8>, String)> {
+    let mut cases = vec![];
+    let mut data: Option<Vec<u8>> = None;
+    let mut section = "";
+    for line in bytes.split(|&b| b == b'\n') {
+        match line {
+            b"#data" => {
+                data = Some(vec![]);
+                section = "data";
+            }
+            b"#encoding" => section = "encoding",
+            _ if section == "data" => {
+                let data = data.as_mut().unwrap();
+                data.extend_from_slice(line);
+                data.push(b'\n');
+            }
+            _ if section == "encoding" => {
+                let mut data = data.take().unwrap();
+                // the newline before `#encoding` isn't part of the data
+                data.pop();
+                cases.push((data, String::from_utf8_lossy(line).trim().into()));
+                section = "";
+            }
+            _ => (),
+        }
+    }
+    cases
+}
+
+fn determine_encoding(bytes: &[u8]) -> &'static Encoding {
+    let (encoding, certainty) = sniff_encoding(bytes, None);
+    if let EncodingCertainty::Certain(_) = certainty {
+        return encoding;
+    }
+    let mut parser = HtmlParser::new();
+    parser.set_character_encoding(certainty);
+    let (html, _) = encoding.decode_with_bom_removal(bytes);
+    parser.feed(&html);
+    parser.encoding_change_requested().unwrap_or(encoding)
+}
+
+#[test]
+fn html5lib_encoding() {
+    let mut files: Vec<_> = fs::read_dir(TEST_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dat"))
+        .collect();
+    files.sort();
+
+    let mut failures = vec![];
+    let mut fixed = vec![];
+    let mut count = 0;
+    for path in files {
+        let file = path.file_name().unwrap().to_string_lossy().into_owned();
+        for (data, expected) in read_cases(&fs::read(&path).unwrap()) {
+            count += 1;
+            let expected = Encoding::for_label(expected.as_bytes()).unwrap();
+            let actual = determine_encoding(&data);
+            let known = KNOWN_FAILURES
+                .iter()
+                .any(|&(f, d)| f == file && d.as_bytes() == &data[..]);
+            if actual == expected {
+                if known {
+                    fixed.push(format!("{}: {:?}", file, String::from_utf8_lossy(&data)));
+                }
+            } else if !known {
+                failures.push(format!(
+                    "{}: {:?}: expected {}, got {}",
+                    file,
+                    String::from_utf8_lossy(&data),
+                    expected.name(),
+                    actual.name()
+                ));
+            }
+        }
+    }
+
+    assert!(count > 0, "no cases found in {}", TEST_DIR);
+    assert!(
+        failures.is_empty(),
+        "{} of {} cases failed:\n{}",
+        failures.len(),
+        count,
+        failures.join("\n")
+    );
+    assert!(
+        fixed.is_empty(),
+        "known failures now pass; remove them from KNOWN_FAILURES:\n{}",
+        fixed.join("\n")
+    );
+}
+
+#[test]
+fn parse_bytes_restarts_on_late_meta() {
+    // the `<meta>` is past the prescanned bytes; 0xB1 is `ą` in ISO-8859-2,
+    //   but `±` in windows-1252
+    let mut bytes = b"<!DOCTYPE html><title>".to_vec();
+    bytes.extend(std::iter::repeat_n(b'a', 1024));
+    bytes.extend_from_slice(b"</title><meta charset=iso-8859-2><body>\xB1");
+    let dom = HtmlParser::parse_bytes(&bytes, None);
+    let html = iron::html::serializer::serialize_document(&dom, true);
+    assert!(html.ends_with("<body>\u{105}</body></html>"), "{}", html);
+}
@@ -0,0 +1,422 @@
+/* ============================================================================
+ * File:   html5lib_tokenizer.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Runs the html5lib-tests tokenizer suite against `HtmlTokenizer`.
+// The `.test` files are vendored in `tests/html5lib-tests/tokenizer`; see the
+//   README there for the format.
+// Every case is run once for each of its initial states. Tokens are compared
+//   exactly; parse errors are compared by count, as the expected output only
+//   says where an error happens relative to the tokens.
+// Cases in `KNOWN_FAILURES` are expected to diverge. The test fails if any
+//   other case diverges, or if a known failure starts passing (so the list
+//   doesn't go stale).
+use iron::html::parser::error::CollectErrors;
+use iron::html::tokenizer::detail::{State, Token};
+use iron::html::tokenizer::HtmlTokenizer;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+const TEST_DIR: &str = "tests/html5lib-tests/tokenizer";
+
+// (file, description, initial state) of the cases that are known to diverge
+//   from the expected output
+// The vendored tests predate some spec changes, so these are the cases where
+//   the tests disagree with the current spec; they only differ in the number
+//   of parse errors.
+const KNOWN_FAILURES: &[(&str, &str, &str)] = &[
+    // `--` and `--!` in the middle of a comment used to be parse errors
+    ("domjs.test", "--!NUL in comment ", "Data state"),
+    ("pendingSpecChanges.test", "<!---- >", "Data state"),
+    ("test1.test", "Comment, Central dash no space", "Data state"),
+    ("test1.test", "Comment, two central dashes", "Data state"),
+    ("test3.test", "<!----\\u0009", "Data state"),
+    ("test3.test", "<!----\\u000A", "Data state"),
+    ("test3.test", "<!----\\u000B", "Data state"),
+    ("test3.test", "<!----\\u000C", "Data state"),
+    ("test3.test", "<!---- ", "Data state"),
+    ("test3.test", "<!---- -", "Data state"),
+    ("test3.test", "<!---- --", "Data state"),
+    ("test3.test", "<!---- -->", "Data state"),
+    ("test3.test", "<!----  -->", "Data state"),
+    ("test3.test", "<!---- a-->", "Data state"),
+    ("test3.test", "<!----!", "Data state"),
+    ("test3.test", "<!----!a", "Data state"),
+    ("test3.test", "<!----!a-", "Data state"),
+    ("test3.test", "<!----!a--", "Data state"),
+    ("test3.test", "<!----!a-->", "Data state"),
+    ("test3.test", "<!----!-", "Data state"),
+    ("test3.test", "<!----!--", "Data state"),
+    ("test3.test", "<!----!-->", "Data state"),
+    ("test3.test", "<!----\"", "Data state"),
+    ("test3.test", "<!----&", "Data state"),
+    ("test3.test", "<!----'", "Data state"),
+    ("test3.test", "<!----,", "Data state"),
+    ("test3.test", "<!-----", "Data state"),
+    ("test3.test", "<!----.", "Data state"),
+    ("test3.test", "<!----/", "Data state"),
+    ("test3.test", "<!----0", "Data state"),
+    ("test3.test", "<!----1", "Data state"),
+    ("test3.test", "<!----9", "Data state"),
+    ("test3.test", "<!----<", "Data state"),
+    ("test3.test", "<!----=", "Data state"),
+    ("test3.test", "<!----?", "Data state"),
+    ("test3.test", "<!----@", "Data state"),
+    ("test3.test", "<!----A", "Data state"),
+    ("test3.test", "<!----B", "Data state"),
+    ("test3.test", "<!----Y", "Data state"),
+    ("test3.test", "<!----Z", "Data state"),
+    ("test3.test", "<!----`", "Data state"),
+    ("test3.test", "<!----a", "Data state"),
+    ("test3.test", "<!----b", "Data state"),
+    ("test3.test", "<!----y", "Data state"),
+    ("test3.test", "<!----z", "Data state"),
+    ("test3.test", "<!----{", "Data state"),
+    ("test3.test", "<!----\\uDBC0\\uDC00", "Data state"),
+    // a NULL in a bogus comment or bogus DOCTYPE is now an
+    //   unexpected-null-character error
+    ("test3.test", "<!\\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPE a \\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPE a PUBLIC\\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPE a PUBLIC''\\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPE a SYSTEM\\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPE a SYSTEM''\\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPE a a\\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPEa \\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPEa PUBLIC\\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPEa PUBLIC''\\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPEa SYSTEM\\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPEa SYSTEM''\\u0000", "Data state"),
+    ("test3.test", "<!DOCTYPEa a\\u0000", "Data state"),
+    ("test3.test", "</\\u0000", "Data state"),
+    ("test3.test", "<?\\u0000", "Data state"),
+    ("test4.test", "U+0000 in lookahead region", "Data state"),
+    // `<!DOCTYPE>` used to have a second error for the missing whitespace
+    ("test2.test", "DOCTYPE without name", "Data state"),
+    ("test3.test", "<!DOCTYPE>", "Data state"),
+];
+
+// a token in the format the tests use; character tokens are merged and parse
+//   errors are counted separately
+#[derive(Debug, PartialEq)]
+enum TestToken {
+    Doctype(Option<String>, Option<String>, Option<String>, bool),
+    StartTag(String, BTreeMap<String, String>, bool),
+    EndTag(String),
+    Comment(String),
+    Character(String),
+}
+
+struct TestCase {
+    description: String,
+    input: String,
+    expected: Vec<TestToken>,
+    expected_errors: usize,
+    initial_states: Vec<String>,
+    last_start_tag: Option<String>,
+}
+
+fn state_named(name: &str) -> Option<State> {
+    match name {
+        "Data state" | "data state" => Some(State::Data),
+        "PLAINTEXT state" => Some(State::Plaintext),
+        "RCDATA state" => Some(State::Rcdata),
+        "RAWTEXT state" => Some(State::Rawtext),
+        "Script data state" | "script data state" => Some(State::ScriptData),
+        "CDATA section state" => Some(State::CdataSection),
+        _ => None,
+    }
+}
+
+// undoes the extra level of `\uXXXX` escaping that `doubleEscaped` tests use
+// returns `None` if the string has a lone surrogate, which a Rust string
+//   can't hold
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = s.chars().peekable();
+    let mut high_surrogate: Option<u32> = None;
+    while let Some(c) = chars.next() {
+        if c != '\\' || chars.peek() != Some(&'u') {
+            if high_surrogate.is_some() {
+                return None;
+            }
+            out.push(c);
+            continue;
+        }
+        chars.next();
+        let hex: String = chars.by_ref().take(4).collect();
+        let code = u32::from_str_radix(&hex, 16).ok()?;
+        match (high_surrogate.take(), code) {
+            (None, 0xD800..=0xDBFF) => high_surrogate = Some(code),
+            (Some(high), 0xDC00..=0xDFFF) => {
+                let code = 0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00);
+                out.push(std::char::from_u32(code)?);
+            }
+            (None, _) => out.push(std::char::from_u32(code)?),
+            (Some(_), _) => return None,
+        }
+    }
+    if high_surrogate.is_some() {
+        return None;
+    }
+    Some(out)
+}
+
+fn string(value: &Value, double_escaped: bool) -> Option<String> {
+    let s = value.as_str()?;
+    if double_escaped {
+        unescape(s)
+    } else {
+        Some(s.into())
+    }
+}
+
+fn optional_string(value: &Value, double_escaped: bool) -> Option<Option<String>> {
+    if value.is_null() {
+        return Some(None);
+    }
+    string(value, double_escaped).map(Some)
+}
+
+// appends a character token, merging it with the previous one
+fn push_characters(tokens: &mut Vec<TestToken>, s: &str) {
+    if let Some(TestToken::Character(prev)) = tokens.last_mut() {
+        prev.push_str(s);
+        return;
+    }
+    tokens.push(TestToken::Character(s.into()));
+}
+
+// parses the expected output of a test; returns `None` if it can't be
+//   represented (because of lone surrogates)
+fn parse_output(output: &[Value], double_escaped: bool) -> Option<(Vec<TestToken>, usize)> {
+    let mut tokens = vec![];
+    let mut errors = 0;
+    for token in output {
+        if token.as_str() == Some("ParseError") {
+            errors += 1;
+            continue;
+        }
+        let token = token.as_array().expect("token isn't an array");
+        let kind = token[0].as_str().expect("token kind isn't a string");
+        match kind {
+            "DOCTYPE" => tokens.push(TestToken::Doctype(
+                optional_string(&token[1], double_escaped)?,
+                optional_string(&token[2], double_escaped)?,
+                optional_string(&token[3], double_escaped)?,
+                token[4]
+                    .as_bool()
+                    .expect("DOCTYPE correctness isn't a bool"),
+            )),
+            "StartTag" => {
+                let mut attributes = BTreeMap::new();
+                for (name, value) in token[2].as_object().expect("attributes aren't an object") {
+                    let name = if double_escaped {
+                        unescape(name)?
+                    } else {
+                        name.clone()
+                    };
+                    attributes.insert(name, string(value, double_escaped)?);
+                }
+                let self_closing = token.get(3).and_then(Value::as_bool).unwrap_or(false);
+                tokens.push(TestToken::StartTag(
+                    string(&token[1], double_escaped)?,
+                    attributes,
+                    self_closing,
+                ));
+            }
+            "EndTag" => tokens.push(TestToken::EndTag(string(&token[1], double_escaped)?)),
+            "Comment" => tokens.push(TestToken::Comment(string(&token[1], double_escaped)?)),
+            "Character" => push_characters(&mut tokens, &string(&token[1], double_escaped)?),
+            _ => panic!("unknown token kind {:?}", kind),
+        }
+    }
+    Some((tokens, errors))
+}
+
+// loads the cases in a `.test` file, along with the number that were skipped
+//   because they can't be represented
+fn load_tests(path: &Path) -> (Vec<TestCase>, usize) {
+    let text = fs::read_to_string(path).expect("couldn't read test file");
+    let json: Value = serde_json::from_str(&text).expect("couldn't parse test file");
+    // `xmlViolation.test` only has `xmlViolationTests`, which are for tools
+    //   that coerce their output into XML; a browser doesn't
+    let tests = match json["tests"].as_array() {
+        Some(tests) => tests,
+        None => return (vec![], 0),
+    };
+
+    let mut cases = vec![];
+    let mut skipped = 0;
+    for test in tests {
+        let double_escaped = test["doubleEscaped"].as_bool().unwrap_or(false);
+        let description = test["description"].as_str().unwrap_or("").to_string();
+        let input = string(&test["input"], double_escaped);
+        let output = parse_output(
+            test["output"].as_array().expect("output isn't an array"),
+            double_escaped,
+        );
+        let (input, (expected, expected_errors)) = match (input, output) {
+            (Some(input), Some(output)) => (input, output),
+            _ => {
+                skipped += 1;
+                continue;
+            }
+        };
+        let initial_states = match test["initialStates"].as_array() {
+            Some(states) => states
+                .iter()
+                .map(|state| state.as_str().expect("state isn't a string").to_string())
+                .collect(),
+            None => vec!["Data state".to_string()],
+        };
+        cases.push(TestCase {
+            description,
+            input,
+            expected,
+            expected_errors,
+            initial_states,
+            last_start_tag: test["lastStartTag"].as_str().map(String::from),
+        });
+    }
+    (cases, skipped)
+}
+
+// tokenizes the input of a test case, returning the tokens and the number of
+//   parse errors
+fn tokenize(case: &TestCase, state: State) -> (Vec<TestToken>, usize) {
+    let errors = Rc::new(RefCell::new(CollectErrors::new()));
+    let mut tokenizer = HtmlTokenizer::new_streaming();
+    tokenizer.set_coalesce_characters(true);
+    tokenizer.set_error_handler(Box::new(errors.clone()));
+    tokenizer.switch_to(state);
+    if let Some(tag) = &case.last_start_tag {
+        tokenizer.set_last_start_tag(tag);
+    }
+    tokenizer.feed(&case.input);
+    tokenizer.finish();
+
+    let mut tokens = vec![];
+    for token in tokenizer {
+        match token.token {
+            Token::Attribute(_) => panic!("attribute token outside of a tag"),
+            Token::Character(c) => push_characters(&mut tokens, &c.to_string()),
+            Token::Characters(s) => push_characters(&mut tokens, &s),
+            Token::Comment(comment) => tokens.push(TestToken::Comment(comment.value)),
+            Token::Doctype(doctype) => tokens.push(TestToken::Doctype(
+                doctype.name,
+                doctype.public_id,
+                doctype.system_id,
+                !doctype.force_quirks,
+            )),
+            Token::Eof => break,
+            Token::Tag(tag) => {
+                if tag.is_end_tag {
+                    tokens.push(TestToken::EndTag(tag.name));
+                } else {
+                    let attributes = tag
+                        .attributes
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|attr| (attr.name, attr.value))
+                        .collect();
+                    tokens.push(TestToken::StartTag(tag.name, attributes, tag.self_closing));
+                }
+            }
+        }
+    }
+    let count = errors.borrow().errors.len();
+    (tokens, count)
+}
+
+#[test]
+fn html5lib_tokenizer_tests() {
+    let mut paths: Vec<_> = fs::read_dir(TEST_DIR)
+        .expect("couldn't read the test directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "test"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no tests in {}", TEST_DIR);
+
+    let mut passed = 0;
+    let mut skipped = 0;
+    let mut failures = vec![];
+    let mut known_failures = 0;
+    let mut fixed = vec![];
+    for path in &paths {
+        let file = path.file_name().unwrap().to_str().unwrap();
+        let (cases, file_skipped) = load_tests(path);
+        skipped += file_skipped;
+        for case in &cases {
+            for state_name in &case.initial_states {
+                let state = state_named(state_name)
+                    .unwrap_or_else(|| panic!("unknown initial state {:?}", state_name));
+                let (tokens, errors) = tokenize(case, state);
+                let ok = tokens == case.expected && errors == case.expected_errors;
+                let known = KNOWN_FAILURES.contains(&(file, &case.description, state_name));
+                match (ok, known) {
+                    (true, false) => passed += 1,
+                    (true, true) => fixed.push(format!(
+                        "{}: {:?} ({})",
+                        file, case.description, state_name
+                    )),
+                    (false, true) => known_failures += 1,
+                    (false, false) => failures.push(format!(
+                        "{}: {:?} ({})\n  input:    {:?}\n  expected: {:?} with {} errors\n  actual:   {:?} with {} errors",
+                        file,
+                        case.description,
+                        state_name,
+                        case.input,
+                        case.expected,
+                        case.expected_errors,
+                        tokens,
+                        errors
+                    )),
+                }
+            }
+        }
+    }
+
+    println!(
+        "{} passed, {} failed, {} known failures, {} skipped",
+        passed,
+        failures.len(),
+        known_failures,
+        skipped
+    );
+    for failure in &failures {
+        println!("FAIL {}", failure);
+    }
+    for case in &fixed {
+        println!("FIXED {}", case);
+    }
+    assert!(
+        failures.is_empty() && fixed.is_empty(),
+        "{} unexpected failures and {} known failures that now pass",
+        failures.len(),
+        fixed.len()
+    );
+}
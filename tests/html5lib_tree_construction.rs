@@ -0,0 +1,221 @@
+/* ============================================================================
+ * File:   html5lib_tree_construction.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Runs the html5lib-tests tree construction suite against `HtmlParser`.
+// The `.dat` files are vendored in `tests/html5lib-tests/tree-construction`;
+//   see the README there for the format. The `scripted` tests, which need
+//   a script engine, aren't included.
+// Every case is run with scripting on and off, unless it asks for one. Only
+//   the trees are compared, not the parse errors.
+// Cases in `KNOWN_FAILURES` are expected to diverge. The test fails if any
+//   other case diverges, or if a known failure starts passing (so the list
+//   doesn't go stale).
+use iron::html::element::{QualName, RcDom};
+use iron::html::parser::HtmlParser;
+use iron::html::serializer::dump::{dump_document, dump_nodes};
+use iron::infra::namespace::Namespace;
+use std::fs;
+use std::path::Path;
+
+const TEST_DIR: &str = "tests/html5lib-tests/tree-construction";
+
+// (file, index of the case in the file, scripting) of the cases that are
+//   known to diverge from the expected tree
+// The vendored tests predate some spec changes, so these are the cases where
+//   the tests disagree with the current spec.
+const KNOWN_FAILURES: &[(&str, usize, bool)] = &[
+    // breaking out of foreign content used to be skipped when parsing a
+    //   fragment with a foreign context element
+    ("foreign-fragment.dat", 0, true),
+    ("foreign-fragment.dat", 0, false),
+    ("foreign-fragment.dat", 1, true),
+    ("foreign-fragment.dat", 1, false),
+    ("foreign-fragment.dat", 38, true),
+    ("foreign-fragment.dat", 38, false),
+    ("foreign-fragment.dat", 40, true),
+    ("foreign-fragment.dat", 40, false),
+    ("foreign-fragment.dat", 47, true),
+    ("foreign-fragment.dat", 47, false),
+    ("foreign-fragment.dat", 48, true),
+    ("foreign-fragment.dat", 48, false),
+];
+
+struct TestCase {
+    data: String,
+    // the context element of a fragment case, as "svg path", "math mi" or
+    //   just the local name
+    fragment_context: Option<String>,
+    // `None` if the case should be run both ways
+    scripting: Option<bool>,
+    expected: String,
+}
+
+const SECTIONS: &[&str] = &[
+    "#data",
+    "#errors",
+    "#new-errors",
+    "#document-fragment",
+    "#script-off",
+    "#script-on",
+    "#document",
+];
+
+fn load_tests(path: &Path) -> Vec<TestCase> {
+    let text = fs::read_to_string(path).expect("couldn't read test file");
+
+    // split each case into its sections
+    let mut cases: Vec<Vec<(&str, Vec<&str>)>> = vec![];
+    for line in text.split('\n') {
+        if SECTIONS.contains(&line) {
+            if line == "#data" {
+                cases.push(vec![]);
+            }
+            cases
+                .last_mut()
+                .expect("test file doesn't start with #data")
+                .push((line, vec![]));
+        } else if let Some((_, lines)) = cases.last_mut().and_then(|case| case.last_mut()) {
+            lines.push(line);
+        }
+    }
+
+    cases
+        .into_iter()
+        .map(|sections| {
+            let section = |name: &str| {
+                sections
+                    .iter()
+                    .find(|(section, _)| *section == name)
+                    .map(|(_, lines)| lines)
+            };
+            let scripting = match (section("#script-on"), section("#script-off")) {
+                (Some(_), _) => Some(true),
+                (_, Some(_)) => Some(false),
+                _ => None,
+            };
+
+            // cases are separated by a blank line
+            let mut expected = section("#document").expect("case has no #document").clone();
+            if expected.last() == Some(&"") {
+                expected.pop();
+            }
+            let mut expected = expected.join("\n");
+            expected.push('\n');
+
+            TestCase {
+                data: section("#data").unwrap().join("\n"),
+                fragment_context: section("#document-fragment").map(|lines| lines[0].into()),
+                scripting,
+                expected,
+            }
+        })
+        .collect()
+}
+
+// parses a case and dumps the resulting tree
+fn run_case(case: &TestCase, scripting: bool) -> String {
+    let context = match &case.fragment_context {
+        Some(context) => context,
+        None => {
+            let mut parser = HtmlParser::new();
+            parser.set_scripting(scripting);
+            parser.feed(&case.data);
+            parser.finish();
+            return dump_document(&parser.into_document());
+        }
+    };
+
+    let (ns, local) = if let Some(local) = context.strip_prefix("svg ") {
+        (Namespace::Svg, local)
+    } else if let Some(local) = context.strip_prefix("math ") {
+        (Namespace::MathML, local)
+    } else {
+        (Namespace::Html, &context[..])
+    };
+    let context = RcDom::new().create_element(QualName::new(None, ns, local.into()), vec![]);
+    let mut parser = HtmlParser::new_fragment(&context);
+    parser.set_scripting(scripting);
+    parser.feed(&case.data);
+    parser.finish();
+    dump_nodes(&parser.into_fragment())
+}
+
+#[test]
+fn html5lib_tree_construction_tests() {
+    let mut paths: Vec<_> = fs::read_dir(TEST_DIR)
+        .expect("couldn't read the test directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dat"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no tests in {}", TEST_DIR);
+
+    let mut passed = 0;
+    let mut failures = vec![];
+    let mut known_failures = 0;
+    let mut fixed = vec![];
+    for path in &paths {
+        let file = path.file_name().unwrap().to_str().unwrap();
+        for (i, case) in load_tests(path).iter().enumerate() {
+            let modes = match case.scripting {
+                Some(scripting) => vec![scripting],
+                None => vec![true, false],
+            };
+            for scripting in modes {
+                let actual = run_case(case, scripting);
+                let ok = actual == case.expected;
+                let known = KNOWN_FAILURES.contains(&(file, i, scripting));
+                match (ok, known) {
+                    (true, false) => passed += 1,
+                    (true, true) => fixed.push(format!(
+                        "{} #{} (scripting {})",
+                        file, i, scripting
+                    )),
+                    (false, true) => known_failures += 1,
+                    (false, false) => failures.push(format!(
+                        "{} #{} (scripting {})\n  data: {:?}\n  context: {:?}\nexpected:\n{}actual:\n{}",
+                        file, i, scripting, case.data, case.fragment_context, case.expected, actual
+                    )),
+                }
+            }
+        }
+    }
+
+    println!(
+        "{} passed, {} failed, {} known failures",
+        passed,
+        failures.len(),
+        known_failures
+    );
+    for failure in &failures {
+        println!("FAIL {}", failure);
+    }
+    for case in &fixed {
+        println!("FIXED {}", case);
+    }
+    assert!(
+        failures.is_empty() && fixed.is_empty(),
+        "{} unexpected failures and {} known failures that now pass",
+        failures.len(),
+        fixed.len()
+    );
+}
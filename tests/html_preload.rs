@@ -0,0 +1,211 @@
+/* ============================================================================
+ * File:   html_preload.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Checks the resources found by `PreloadScanner`, alone and running ahead of
+//   `HtmlParser`
+use iron::html::element::Handle;
+use iron::html::parser::preload::{CollectPreloads, PreloadRequest, PreloadScanner, ResourceKind};
+use iron::html::parser::script::{ScriptAction, ScriptHandler};
+use iron::html::parser::HtmlParser;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn kind_name(kind: ResourceKind) -> &'static str {
+    match kind {
+        ResourceKind::Script => "script",
+        ResourceKind::Style => "style",
+        ResourceKind::Image => "image",
+        ResourceKind::Preload => "preload",
+    }
+}
+
+// each request as "kind url", with " as=..." and " base=..." if it has them
+fn describe(requests: &[PreloadRequest]) -> Vec<String> {
+    requests
+        .iter()
+        .map(|request| {
+            let mut out = format!("{} {}", kind_name(request.kind), request.url);
+            if let Some(destination) = &request.destination {
+                out.push_str(&format!(" as={}", destination));
+            }
+            if let Some(base) = &request.base {
+                out.push_str(&format!(" base={}", base));
+            }
+            out
+        })
+        .collect()
+}
+
+// blocks on every script
+struct BlockScripts;
+
+impl ScriptHandler<Handle> for BlockScripts {
+    fn prepare_script(&mut self, _script: &Handle, _nesting_level: u32) -> ScriptAction {
+        ScriptAction::Block
+    }
+
+    fn execute_script(&mut self, _script: &Handle, _nesting_level: u32) {}
+}
+
+#[test]
+fn resources() {
+    let html = "<script src=a.js></script>\
+                <link rel=stylesheet href=b.css><link rel='Preload icon' href=c.woff as=font>\
+                <link rel=icon href=ignored.ico>\
+                <img src=d.png srcset=' e.png 1x,f.png, g(1).png 100w , h.png'>";
+    assert_eq!(
+        describe(&PreloadScanner::scan(html)),
+        vec![
+            "script a.js",
+            "style b.css",
+            "preload c.woff as=font",
+            "image d.png",
+            "image e.png",
+            "image f.png",
+            "image g(1).png",
+            "image h.png",
+        ]
+    );
+}
+
+#[test]
+fn base() {
+    let html = "<img src=a.png><base target=_top><base href=/x/><base href=/y/><img src=b.png>";
+    assert_eq!(
+        describe(&PreloadScanner::scan(html)),
+        vec!["image a.png", "image b.png base=/x/"]
+    );
+
+    // a <base> in a <template> is inert
+    let html = "<template><base href=/x/></template><img src=a.png><base href=/y/><img src=b.png>";
+    assert_eq!(
+        describe(&PreloadScanner::scan(html)),
+        vec!["image a.png", "image b.png base=/y/"]
+    );
+}
+
+#[test]
+fn text_and_inert_content() {
+    let html = "<script>'<img src=a.png>'</script><style><img src=b.png></style>\
+                <textarea><img src=c.png></textarea><!-- <img src=d.png> -->\
+                <template><img src=e.png><template></template><img src=f.png></template>\
+                <svg><style><img src=g.png></style><script href=h.js></script></svg>\
+                <img src=i.png>";
+    assert_eq!(describe(&PreloadScanner::scan(html)), vec!["image i.png"]);
+}
+
+#[test]
+fn scripting() {
+    let html = "<script src=a.js></script><noscript><img src=b.png></noscript>";
+    assert_eq!(describe(&PreloadScanner::scan(html)), vec!["script a.js"]);
+
+    let preloads = Rc::new(RefCell::new(CollectPreloads::new()));
+    let mut scanner = PreloadScanner::new(Box::new(preloads.clone()));
+    scanner.set_scripting(false);
+    scanner.feed(html);
+    scanner.finish();
+    assert_eq!(describe(&preloads.borrow().requests), vec!["image b.png"]);
+}
+
+#[test]
+fn parser_scans_ahead_while_blocked() {
+    let preloads = Rc::new(RefCell::new(CollectPreloads::new()));
+    let mut parser = HtmlParser::new();
+    parser.set_script_handler(Box::new(BlockScripts));
+    parser.set_preload_handler(Box::new(preloads.clone()));
+
+    parser.feed("<base href=/x/><img src=before.png><script src=a.js></script><img src=b.png>");
+    assert!(parser.paused());
+    assert_eq!(
+        describe(&preloads.borrow().requests),
+        vec!["image b.png base=/x/"]
+    );
+
+    // input that comes in while paused is scanned as it arrives
+    parser.feed("<script src=c.js></script><link rel=stylesheet href=d.css>");
+    parser.finish();
+    assert_eq!(
+        describe(&preloads.borrow().requests),
+        vec![
+            "image b.png base=/x/",
+            "script c.js base=/x/",
+            "style d.css base=/x/"
+        ]
+    );
+
+    // the scanner is already past everything
+    parser.resume();
+    assert!(parser.paused());
+    parser.resume();
+    assert!(!parser.paused());
+    assert_eq!(preloads.borrow().requests.len(), 3);
+}
+
+#[test]
+fn parser_ignores_base_in_template() {
+    let preloads = Rc::new(RefCell::new(CollectPreloads::new()));
+    let mut parser = HtmlParser::new();
+    parser.set_script_handler(Box::new(BlockScripts));
+    parser.set_preload_handler(Box::new(preloads.clone()));
+    parser.feed("<template><base href=/x/></template><script src=a.js></script><img src=b.png>");
+    assert!(parser.paused());
+    assert_eq!(describe(&preloads.borrow().requests), vec!["image b.png"]);
+}
+
+#[test]
+fn parser_scans_again_after_catching_up() {
+    let preloads = Rc::new(RefCell::new(CollectPreloads::new()));
+    let mut parser = HtmlParser::new();
+    parser.set_script_handler(Box::new(BlockScripts));
+    parser.set_preload_handler(Box::new(preloads.clone()));
+
+    parser.feed("<script src=a.js></script><script src=b.js></script><img src=c.png>");
+    assert_eq!(
+        describe(&preloads.borrow().requests),
+        vec!["script b.js", "image c.png"]
+    );
+
+    // still blocked on b.js; the scanner continues past c.png
+    parser.resume();
+    assert!(parser.paused());
+    parser.feed("<img src=d.png>");
+    parser.resume();
+    assert!(!parser.paused());
+    assert_eq!(preloads.borrow().requests.len(), 3);
+
+    // input the parser gets to by itself isn't scanned, and the scanner
+    //   starts over from where the parser blocks next
+    parser.feed("<img src=e.png><script src=f.js></script><img src=g.png>");
+    assert!(parser.paused());
+    parser.feed("<base href=/x/><img src=h.png>");
+    parser.finish();
+    assert_eq!(
+        describe(&preloads.borrow().requests),
+        vec![
+            "script b.js",
+            "image c.png",
+            "image d.png",
+            "image g.png",
+            "image h.png base=/x/",
+        ]
+    );
+}
@@ -0,0 +1,132 @@
+/* ============================================================================
+ * File:   html_quirks.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Checks the document mode `HtmlParser` picks from the DOCTYPE
+use iron::html::element::NodeData;
+use iron::html::parser::quirks::QuirksMode;
+use iron::html::parser::HtmlParser;
+use iron::html::serializer::serialize_document;
+
+fn mode(html: &str, iframe_srcdoc: bool) -> &'static str {
+    let mut parser = HtmlParser::new();
+    parser.set_iframe_srcdoc(iframe_srcdoc);
+    parser.feed(html);
+    parser.finish();
+    match parser.into_document().quirks {
+        QuirksMode::None => "no-quirks",
+        QuirksMode::Limited => "limited-quirks",
+        QuirksMode::Full => "quirks",
+    }
+}
+
+#[test]
+fn no_quirks() {
+    for html in &[
+        "<!DOCTYPE html>",
+        "<!doctype HTML>",
+        "<!DOCTYPE html SYSTEM \"about:legacy-compat\">",
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\">",
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \
+         \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\">",
+    ] {
+        assert_eq!(mode(html, false), "no-quirks", "{}", html);
+    }
+}
+
+#[test]
+fn limited_quirks() {
+    for html in &[
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\">",
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Frameset//EN\">",
+        // with a system identifier, the HTML 4.01 ones are only limited
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\" \
+         \"http://www.w3.org/TR/html4/loose.dtd\">",
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Frameset//EN\" \
+         \"http://www.w3.org/TR/html4/frameset.dtd\">",
+    ] {
+        assert_eq!(mode(html, false), "limited-quirks", "{}", html);
+    }
+}
+
+#[test]
+fn full_quirks() {
+    for html in &[
+        "<p>no doctype",
+        "<!DOCTYPE>",
+        "<!DOCTYPE svg>",
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 3.2 Final//EN\">",
+        "<!DOCTYPE html PUBLIC \"-//W3O//DTD W3 HTML Strict 3.0//EN//\">",
+        "<!DOCTYPE html PUBLIC \"HTML\">",
+        "<!DOCTYPE html SYSTEM \
+         \"http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd\">",
+        // without a system identifier, the HTML 4.01 ones are full quirks
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\">",
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Frameset//EN\">",
+    ] {
+        assert_eq!(mode(html, false), "quirks", "{}", html);
+    }
+}
+
+#[test]
+fn iframe_srcdoc() {
+    // a srcdoc document without a doctype isn't in quirks mode
+    assert_eq!(mode("<p>no doctype", true), "no-quirks");
+    assert_eq!(mode("<!DOCTYPE html>", true), "no-quirks");
+    assert_eq!(
+        mode(
+            "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 3.2 Final//EN\">",
+            true
+        ),
+        "no-quirks"
+    );
+}
+
+#[test]
+fn doctype_node() {
+    let dom = HtmlParser::parse(
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\" \
+         \"http://www.w3.org/TR/html4/strict.dtd\">",
+    );
+    let children = dom.document.children.borrow();
+    match &children[0].data {
+        NodeData::Doctype {
+            name,
+            public_id,
+            system_id,
+        } => {
+            assert_eq!(name, "html");
+            assert_eq!(public_id, "-//W3C//DTD HTML 4.01//EN");
+            assert_eq!(system_id, "http://www.w3.org/TR/html4/strict.dtd");
+        }
+        _ => panic!("expected a doctype"),
+    }
+}
+
+#[test]
+fn quirks_mode_changes_the_tree() {
+    // a <table> only closes an open <p> outside of quirks mode
+    assert!(
+        serialize_document(&HtmlParser::parse("<!DOCTYPE html><p><table>"), true)
+            .contains("<p></p><table>")
+    );
+    assert!(serialize_document(&HtmlParser::parse("<p><table>"), true).contains("<p><table>"));
+}
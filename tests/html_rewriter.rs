@@ -0,0 +1,243 @@
+/* ============================================================================
+ * File:   html_rewriter.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Checks that `HtmlRewriter` writes out untouched input byte for byte and
+//   only re-serializes what handlers change
+use iron::html::rewriter::{ContentType, HtmlRewriter};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// splits `html` into chunks of `size` characters
+fn split(html: &str, size: usize) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut rest = html;
+    while !rest.is_empty() {
+        let end = rest
+            .char_indices()
+            .nth(size)
+            .map_or(rest.len(), |(offset, _)| offset);
+        chunks.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    chunks
+}
+
+// rewrites `chunks` with the handlers `setup` registers
+fn rewrite_chunks(chunks: &[&str], setup: &dyn Fn(&mut HtmlRewriter)) -> String {
+    let mut out = String::new();
+    {
+        let mut rewriter = HtmlRewriter::new(|html: &str| out.push_str(html));
+        setup(&mut rewriter);
+        for chunk in chunks {
+            rewriter.write(chunk);
+        }
+        rewriter.end();
+    }
+    out
+}
+
+fn rewrite(html: &str, setup: &dyn Fn(&mut HtmlRewriter)) -> String {
+    rewrite_chunks(&[html], setup)
+}
+
+fn no_handlers(_: &mut HtmlRewriter) {}
+
+// registers handlers for everything that don't change anything
+fn idle_handlers(rewriter: &mut HtmlRewriter) {
+    rewriter.on_element("*", |_| ());
+    rewriter.on_attribute("*", |_| ());
+    rewriter.on_text(|_| ());
+    rewriter.on_comment(|_| ());
+}
+
+const MESSY: &str = "<!DOCTYPE html>\r\n<HTML lang=en>\r<head><title>a &amp; b</title>\
+                     <script>if (a < b && c) { x = '</p>'; }</script></head>\r\n\
+                     <body class = \"x\" id='y' data-z=w  ><p>caf\u{E9} \u{1F600} &notin; &copy\0\
+                     <!-- a -- comment --><br/><img src=a.png alt=\"\">\u{1}</>&#0;\r\n\
+                     <svg><![CDATA[<raw>]]></svg><textarea><b>\r</textarea><div";
+
+#[test]
+fn identity() {
+    for setup in &[no_handlers as fn(&mut HtmlRewriter), idle_handlers] {
+        for size in &[1, 2, 3, 7, 64, MESSY.len()] {
+            let chunks = split(MESSY, *size);
+            assert_eq!(
+                rewrite_chunks(&chunks, setup),
+                MESSY,
+                "chunks of {} characters",
+                size
+            );
+        }
+    }
+}
+
+#[test]
+fn set_attribute() {
+    let html = "<IMG  SRC=a.png><img src='b.png'\r\nalt=b>";
+    let out = rewrite(html, &|rewriter| {
+        rewriter.on_element("img", |elem| {
+            if elem.get_attribute("src") == Some("a.png") {
+                elem.set_attribute("alt", "an \"<img>\" & more");
+            }
+        })
+    });
+    assert_eq!(
+        out,
+        "<img src=\"a.png\" alt=\"an &quot;<img>&quot; &amp; more\"><img src='b.png'\r\nalt=b>"
+    );
+}
+
+#[test]
+fn remove_attribute() {
+    let html = "<a href=x onclick=evil()>x</a> <a  href=y >y</a> <a onclick=f()>z</a>";
+    let out = rewrite(html, &|rewriter| {
+        rewriter.on_element("a", |elem| {
+            if elem.get_attribute("href") == Some("x") {
+                elem.remove_attribute("onclick");
+            }
+        });
+        // removing an attribute that isn't there changes nothing
+        rewriter.on_element("a", |elem| elem.remove_attribute("title"));
+    });
+    assert_eq!(
+        out,
+        "<a href=\"x\">x</a> <a  href=y >y</a> <a onclick=f()>z</a>"
+    );
+
+    let out = rewrite(html, &|rewriter| {
+        rewriter.on_attribute("onclick", |attr| attr.remove());
+        rewriter.on_attribute("href", |attr| {
+            if attr.value() == "y" {
+                attr.set_value("/y");
+            }
+        });
+    });
+    assert_eq!(out, "<a href=\"x\">x</a> <a href=\"/y\">y</a> <a>z</a>");
+}
+
+#[test]
+fn remove() {
+    let html = "<p>a<div class=ad>ad <div>nested</div> <!-- c --> <b>x</b></div>b</p>";
+    let out = rewrite(html, &|rewriter| {
+        rewriter.on_element("div", |elem| {
+            if elem.get_attribute("class") == Some("ad") {
+                elem.remove();
+            }
+        })
+    });
+    assert_eq!(out, "<p>ab</p>");
+}
+
+#[test]
+fn remove_and_keep_content() {
+    let html = "<p>a<div class=ad>ad <div>nested</div> <!-- c --> <b>x</b></div>b</p>";
+    let out = rewrite(html, &|rewriter| {
+        rewriter.on_element("div", |elem| {
+            if elem.get_attribute("class") == Some("ad") {
+                elem.remove_and_keep_content();
+            }
+        })
+    });
+    assert_eq!(out, "<p>aad <div>nested</div> <!-- c --> <b>x</b>b</p>");
+}
+
+#[test]
+fn text_mutations() {
+    let html = "<p>one</p><p>two</p><p>three</p><p>a&amp;b</p>";
+    let out = rewrite(html, &|rewriter| {
+        rewriter.on_text(|text| match text.as_str() {
+            "one" => {
+                text.before("<i>", ContentType::Html);
+                text.after("</i>", ContentType::Html);
+            }
+            "two" => text.replace("<b>2 & 2</b>", ContentType::Text),
+            "three" => text.remove(),
+            // the handler sees decoded text
+            "a&b" => text.after(" & c", ContentType::Text),
+            _ => (),
+        })
+    });
+    assert_eq!(
+        out,
+        "<p><i>one</i></p><p>&lt;b&gt;2 &amp; 2&lt;/b&gt;</p><p></p><p>a&amp;b &amp; c</p>"
+    );
+}
+
+#[test]
+fn comment_mutations() {
+    let html = "<!--a--><!--b--><!--c--><!--d-->";
+    let out = rewrite(html, &|rewriter| {
+        rewriter.on_comment(|comment| match comment.text() {
+            "a" => {
+                comment.before("<x>", ContentType::Text);
+                comment.after("<y>", ContentType::Html);
+            }
+            "b" => comment.replace("<b>", ContentType::Text),
+            "c" => comment.remove(),
+            "d" => comment.set_text("e"),
+            _ => (),
+        })
+    });
+    assert_eq!(out, "&lt;x&gt;<!--a--><y>&lt;b&gt;<!--e-->");
+}
+
+#[test]
+fn raw_text_elements() {
+    let html = "<script>document.write('<p>x</p>')</script><title><i>t</i></title>\
+                <textarea><b></textarea><style><a></style><xmp><u></xmp><em>after</em>";
+    let seen = Rc::new(RefCell::new(vec![]));
+    let out = {
+        let seen = seen.clone();
+        rewrite(html, &move |rewriter| {
+            let seen = seen.clone();
+            rewriter.on_element("*", move |elem| {
+                seen.borrow_mut().push(elem.tag_name().to_string())
+            });
+        })
+    };
+    assert_eq!(out, html);
+    assert_eq!(
+        *seen.borrow(),
+        vec!["script", "title", "textarea", "style", "xmp", "em"]
+    );
+
+    // the contents of <noscript> are only raw text with scripting on
+    for &(scripting, ref expected) in &[
+        (true, vec!["noscript", "em"]),
+        (false, vec!["noscript", "img", "em"]),
+    ] {
+        let html = "<noscript><img src=x></noscript><em>after</em>";
+        let seen = Rc::new(RefCell::new(vec![]));
+        let out = {
+            let seen = seen.clone();
+            rewrite(html, &move |rewriter| {
+                rewriter.set_scripting(scripting);
+                let seen = seen.clone();
+                rewriter.on_element("*", move |elem| {
+                    seen.borrow_mut().push(elem.tag_name().to_string())
+                });
+            })
+        };
+        assert_eq!(out, html);
+        assert_eq!(*seen.borrow(), *expected);
+    }
+}
@@ -0,0 +1,160 @@
+/* ============================================================================
+ * File:   html_script.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Checks the hooks `HtmlParser` gives to a script engine
+use iron::html::element::{Handle, RcDom};
+use iron::html::parser::script::{ScriptAction, ScriptHandler};
+use iron::html::parser::HtmlParser;
+use iron::html::serializer::{serialize, serialize_document, serialize_outer};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// records the source of every script, and blocks on the ones that start with
+//   "block"
+struct RecordScripts {
+    prepared: Vec<String>,
+    executed: Vec<String>,
+}
+
+impl ScriptHandler<Handle> for RecordScripts {
+    fn prepare_script(&mut self, script: &Handle, nesting_level: u32) -> ScriptAction {
+        assert_eq!(nesting_level, 1);
+        let source = serialize(script, true);
+        let action = if source.starts_with("block") {
+            ScriptAction::Block
+        } else {
+            ScriptAction::Continue
+        };
+        self.prepared.push(source);
+        action
+    }
+
+    fn execute_script(&mut self, script: &Handle, nesting_level: u32) {
+        assert_eq!(nesting_level, 1);
+        self.executed.push(serialize(script, true));
+    }
+}
+
+fn parser_with_handler() -> (HtmlParser, Rc<RefCell<RecordScripts>>) {
+    let handler = Rc::new(RefCell::new(RecordScripts {
+        prepared: vec![],
+        executed: vec![],
+    }));
+    let mut parser = HtmlParser::new();
+    parser.set_script_handler(Box::new(handler.clone()));
+    (parser, handler)
+}
+
+fn parse_with_scripting(html: &str, scripting: bool) -> RcDom {
+    let mut parser = HtmlParser::new();
+    parser.set_scripting(scripting);
+    parser.feed(html);
+    parser.finish();
+    parser.into_document()
+}
+
+#[test]
+fn scripts_are_prepared() {
+    let (mut parser, handler) = parser_with_handler();
+    parser.feed("<script>a</script><p>text<script>b</script><svg><script>c</script>");
+    parser.finish();
+    assert!(!parser.paused());
+    assert_eq!(handler.borrow().prepared, vec!["a", "b", "c"]);
+    assert!(handler.borrow().executed.is_empty());
+}
+
+#[test]
+fn blocking_script_pauses_parsing() {
+    let (mut parser, handler) = parser_with_handler();
+    parser.feed("<script>block</script><p>after");
+    parser.finish();
+    assert!(parser.paused());
+    assert!(parser.pending_parsing_blocking_script().is_some());
+    assert_eq!(
+        serialize_document(parser.document(), true),
+        "<html><head><script>block</script></head></html>"
+    );
+
+    parser.resume();
+    assert!(!parser.paused());
+    assert_eq!(handler.borrow().executed, vec!["block"]);
+    assert_eq!(
+        serialize_document(parser.document(), true),
+        "<html><head><script>block</script></head><body><p>after</p></body></html>"
+    );
+}
+
+#[test]
+fn input_is_kept_while_paused() {
+    let (mut parser, handler) = parser_with_handler();
+    parser.feed("<script>block1</script>");
+    parser.feed("<script>block2</script>");
+    parser.feed("<p>after");
+    parser.finish();
+    assert_eq!(handler.borrow().prepared, vec!["block1"]);
+
+    parser.resume();
+    assert!(parser.paused());
+    assert_eq!(handler.borrow().prepared, vec!["block1", "block2"]);
+
+    parser.resume();
+    assert!(!parser.paused());
+    assert_eq!(handler.borrow().executed, vec!["block1", "block2"]);
+    assert!(
+        serialize_document(parser.document(), true).ends_with("<body><p>after</p></body></html>")
+    );
+}
+
+#[test]
+fn noscript() {
+    let html = "<head><noscript><link rel=a></noscript></head><body><noscript><p>b</p></noscript>";
+    assert_eq!(
+        serialize_document(&parse_with_scripting(html, true), true),
+        "<html><head><noscript><link rel=a></noscript></head>\
+         <body><noscript><p>b</p></noscript></body></html>"
+    );
+    assert_eq!(
+        serialize_document(&parse_with_scripting(html, false), false),
+        "<html><head><noscript><link rel=\"a\"></noscript></head>\
+         <body><noscript><p>b</p></noscript></body></html>"
+    );
+}
+
+#[test]
+fn noscript_fragment() {
+    let dom = HtmlParser::parse("<noscript></noscript>");
+    let head = dom.document.children.borrow()[0].children.borrow()[0].clone();
+    let noscript = head.children.borrow()[0].clone();
+
+    let mut parser = HtmlParser::new_fragment(&noscript);
+    parser.set_scripting(false);
+    parser.feed("<b>x</b>");
+    parser.finish();
+    let nodes = parser.into_fragment();
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(serialize_outer(&nodes[0], false), "<b>x</b>");
+
+    let nodes = HtmlParser::parse_fragment(&noscript, "<b>x</b>");
+    // a lone text node, so the markup is escaped
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(serialize_outer(&nodes[0], true), "&lt;b&gt;x&lt;/b&gt;");
+}
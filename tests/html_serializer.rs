@@ -0,0 +1,129 @@
+/* ============================================================================
+ * File:   html_serializer.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Round-trips markup through `HtmlParser` and the serializer
+use iron::html::element::{QualName, RcDom};
+use iron::html::parser::HtmlParser;
+use iron::html::serializer::{serialize, serialize_document, serialize_outer};
+use iron::infra::namespace::Namespace;
+
+fn round_trip(html: &str) -> String {
+    serialize_document(&HtmlParser::parse(html), true)
+}
+
+#[test]
+fn document() {
+    assert_eq!(
+        round_trip("<!DOCTYPE html><title>a & b</title><p class=x>1<br>2"),
+        "<!DOCTYPE html><html><head><title>a &amp; b</title></head>\
+         <body><p class=\"x\">1<br>2</p></body></html>"
+    );
+}
+
+#[test]
+fn escaping() {
+    assert_eq!(
+        round_trip("<p title='\"&\u{A0}<>'>&lt;&gt;&amp;\u{A0}\"</p>"),
+        "<html><head></head><body><p title=\"&quot;&amp;&nbsp;<>\">\
+         &lt;&gt;&amp;&nbsp;\"</p></body></html>"
+    );
+}
+
+#[test]
+fn raw_text() {
+    assert_eq!(
+        round_trip("<script>if (a < b && c) {}</script><style>a > b {}</style>"),
+        "<html><head><script>if (a < b && c) {}</script>\
+         <style>a > b {}</style></head><body></body></html>"
+    );
+}
+
+// parses and serializes with the scripting flag on or off
+fn round_trip_with_scripting(html: &str, scripting: bool) -> String {
+    let mut parser = HtmlParser::new();
+    parser.set_scripting(scripting);
+    parser.feed(html);
+    parser.finish();
+    serialize_document(&parser.into_document(), scripting)
+}
+
+#[test]
+fn noscript() {
+    let html = "<body><noscript>&lt;/noscript&gt;&lt;img src=x onerror=alert(1)&gt;</noscript>";
+
+    // with scripting off, the text is escaped so it stays text when parsed
+    //   again
+    let once = round_trip_with_scripting(html, false);
+    assert_eq!(
+        once,
+        "<html><head></head><body><noscript>\
+         &lt;/noscript&gt;&lt;img src=x onerror=alert(1)&gt;</noscript></body></html>"
+    );
+    assert_eq!(round_trip_with_scripting(&once, false), once);
+
+    // with it on, they're raw text and written out as is
+    let html = "<body><noscript><img src=x></noscript>";
+    let once = round_trip_with_scripting(html, true);
+    assert_eq!(
+        once,
+        "<html><head></head><body><noscript><img src=x></noscript></body></html>"
+    );
+    assert_eq!(round_trip_with_scripting(&once, true), once);
+}
+
+#[test]
+fn template_and_comments() {
+    assert_eq!(
+        round_trip("<!--x--><template><td>1</td></template><!--y-->"),
+        "<!--x--><html><head><template><td>1</td></template><!--y--></head>\
+         <body></body></html>"
+    );
+}
+
+#[test]
+fn foreign_attributes() {
+    assert_eq!(
+        round_trip(
+            "<svg viewbox='0 0 1 1' xlink:href=#a xml:lang=en xmlns:xlink=x><clippath/></svg>"
+        ),
+        "<html><head></head><body><svg viewBox=\"0 0 1 1\" xlink:href=\"#a\" \
+         xml:lang=\"en\" xmlns:xlink=\"x\"><clipPath></clipPath></svg></body></html>"
+    );
+}
+
+#[test]
+fn fragment() {
+    let dom = RcDom::new();
+    let div = dom.create_element(QualName::new(None, Namespace::Html, "div".into()), vec![]);
+    let html = "<b>1<i>2</b>3</i><img src=a>";
+    for node in HtmlParser::parse_fragment(&div, html) {
+        dom.append(&div, iron::html::element::NodeOrText::AppendNode(node));
+    }
+    assert_eq!(
+        serialize(&div, true),
+        "<b>1<i>2</i></b><i>3</i><img src=\"a\">"
+    );
+    assert_eq!(
+        serialize_outer(&div, true),
+        "<div><b>1<i>2</i></b><i>3</i><img src=\"a\"></div>"
+    );
+}
@@ -0,0 +1,411 @@
+/* ============================================================================
+ * File:   html_tokenizer.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Focused checks of `HtmlTokenizer` behavior that the html5lib suite doesn't
+//   cover, such as token locations
+use iron::html::parser::detail::ParseHtmlError;
+use iron::html::parser::error::CollectErrors;
+use iron::html::tokenizer::detail::{Tag, Token, TokenMetadata, TokenWithLocation};
+use iron::html::tokenizer::preprocess::InputPreprocessor;
+use iron::html::tokenizer::HtmlTokenizer;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn tokenize(html: &str, coalesce: bool) -> Vec<TokenWithLocation> {
+    let mut tokenizer = HtmlTokenizer::new_streaming();
+    tokenizer.set_coalesce_characters(coalesce);
+    tokenizer.feed(html);
+    tokenizer.finish();
+    tokenizer.collect()
+}
+
+// a token as (kind and contents, source text, start line and column, end
+//   line and column)
+fn describe<'a>(
+    html: &'a str,
+    tok: &TokenWithLocation,
+) -> (String, &'a str, (u32, u32), (u32, u32)) {
+    let kind = match &tok.token {
+        Token::Tag(tag) if tag.is_end_tag => format!("</{}>", tag.name),
+        Token::Tag(tag) => format!("<{}>", tag.name),
+        Token::Character(c) => format!("{:?}", c),
+        Token::Characters(text) => format!("{:?}", text),
+        Token::Comment(comment) => format!("<!--{}-->", comment.value),
+        Token::Doctype(_) => "doctype".into(),
+        Token::Eof => "eof".into(),
+        Token::Attribute(_) => "attribute".into(),
+    };
+    (
+        kind,
+        &html[tok.start.file_offset..tok.end.file_offset],
+        (tok.start.line, tok.start.line_offset),
+        (tok.end.line, tok.end.line_offset),
+    )
+}
+
+const SPANS: &str = "<p class=x>\u{E9}t\u{E9}\n<!-- c --><br/>x</p>";
+
+#[test]
+fn token_spans() {
+    let html = SPANS;
+    let tokens: Vec<_> = tokenize(html, false)
+        .iter()
+        .map(|tok| describe(html, tok))
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            ("<p>".into(), "<p class=x>", (0, 0), (0, 11)),
+            // columns are in characters, offsets in bytes
+            ("'é'".into(), "\u{E9}", (0, 11), (0, 12)),
+            ("'t'".into(), "t", (0, 12), (0, 13)),
+            ("'é'".into(), "\u{E9}", (0, 13), (0, 14)),
+            ("'\\n'".into(), "\n", (0, 14), (1, 0)),
+            ("<!-- c -->".into(), "<!-- c -->", (1, 0), (1, 10)),
+            ("<br>".into(), "<br/>", (1, 10), (1, 15)),
+            ("'x'".into(), "x", (1, 15), (1, 16)),
+            ("</p>".into(), "</p>", (1, 16), (1, 20)),
+            ("eof".into(), "", (1, 20), (1, 20)),
+        ]
+    );
+}
+
+#[test]
+fn coalesced_token_spans() {
+    let html = SPANS;
+    let tokens: Vec<_> = tokenize(html, true)
+        .iter()
+        .map(|tok| describe(html, tok))
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            ("<p>".into(), "<p class=x>", (0, 0), (0, 11)),
+            // the run covers the multi-byte characters and the newline
+            ("\"été\\n\"".into(), "\u{E9}t\u{E9}\n", (0, 11), (1, 0)),
+            ("<!-- c -->".into(), "<!-- c -->", (1, 0), (1, 10)),
+            ("<br>".into(), "<br/>", (1, 10), (1, 15)),
+            ("\"x\"".into(), "x", (1, 15), (1, 16)),
+            ("</p>".into(), "</p>", (1, 16), (1, 20)),
+            ("eof".into(), "", (1, 20), (1, 20)),
+        ]
+    );
+}
+
+// tokenizes UTF-8 given in chunks split at `splits`, returning the tokens'
+//   contents
+fn tokenize_bytes(bytes: &[u8], splits: &[usize]) -> Vec<String> {
+    let mut tokenizer = HtmlTokenizer::new_streaming();
+    tokenizer.set_coalesce_characters(true);
+    let mut start = 0;
+    for &split in splits.iter().chain(std::iter::once(&bytes.len())) {
+        tokenizer.feed_bytes(&bytes[start..split]);
+        start = split;
+    }
+    tokenizer.finish();
+
+    let mut tokens: Vec<String> = vec![];
+    for tok in tokenizer {
+        let text = match tok.token {
+            Token::Characters(text) => text,
+            Token::Character(c) => c.to_string(),
+            Token::Tag(tag) if tag.is_end_tag => format!("</{}>", tag.name),
+            Token::Tag(tag) => format!("<{}>", tag.name),
+            _ => continue,
+        };
+        // a run of text can be emitted in pieces when the input runs out
+        match (tokens.last_mut(), text.starts_with('<')) {
+            (Some(last), false) if !last.starts_with('<') => last.push_str(&text),
+            _ => tokens.push(text),
+        }
+    }
+    tokens
+}
+
+#[test]
+fn code_point_split_between_chunks() {
+    let html = "<p>\u{1F600}\u{E9}\u{20AC}</p>";
+    let bytes = html.as_bytes();
+    let whole = tokenize_bytes(bytes, &[]);
+    assert_eq!(whole, vec!["<p>", "\u{1F600}\u{E9}\u{20AC}", "</p>"]);
+    for split in 1..bytes.len() {
+        assert_eq!(tokenize_bytes(bytes, &[split]), whole, "split at {}", split);
+    }
+    // every byte in its own chunk
+    let splits: Vec<_> = (1..bytes.len()).collect();
+    assert_eq!(tokenize_bytes(bytes, &splits), whole);
+}
+
+#[test]
+fn invalid_sequence_at_chunk_boundary() {
+    // a 4-byte sequence cut off by `x`, and a 3-byte one cut off by the end
+    //   of the input
+    let bytes = b"<p>a\xF0\x9Fxb\xE2\x82";
+    let expected = vec!["<p>", "a\u{FFFD}xb\u{FFFD}"];
+    assert_eq!(tokenize_bytes(bytes, &[]), expected);
+    for split in 1..bytes.len() {
+        assert_eq!(
+            tokenize_bytes(bytes, &[split]),
+            expected,
+            "split at {}",
+            split
+        );
+    }
+    // the same as a lossy decoding of the whole input
+    assert_eq!(String::from_utf8_lossy(&bytes[3..]), expected[1]);
+}
+
+// tokenizes `html`, returning its tags and the parse errors
+fn tags_and_errors(html: &str) -> (Vec<Tag>, Vec<ParseHtmlError>) {
+    let errors = Rc::new(RefCell::new(CollectErrors::new()));
+    let mut tokenizer = HtmlTokenizer::new_streaming();
+    tokenizer.set_error_handler(Box::new(errors.clone()));
+    tokenizer.feed(html);
+    tokenizer.finish();
+    let tags = tokenizer
+        .filter_map(|tok| match tok.token {
+            Token::Tag(tag) => Some(tag),
+            _ => None,
+        })
+        .collect();
+    let errors = errors.borrow().errors.iter().map(|(err, _)| *err).collect();
+    (tags, errors)
+}
+
+fn attributes(tag: &Tag) -> Vec<(&str, &str)> {
+    tag.attributes
+        .iter()
+        .flatten()
+        .map(|attr| (&attr.name[..], &attr.value[..]))
+        .collect()
+}
+
+#[test]
+fn duplicate_attributes_are_dropped() {
+    let (tags, errors) = tags_and_errors("<p a=1 B=2 A=3 b c=4 a>");
+    // names are lowercased before they're compared; the first one wins
+    assert_eq!(
+        attributes(&tags[0]),
+        vec![("a", "1"), ("b", "2"), ("c", "4")]
+    );
+    assert_eq!(
+        errors,
+        vec![
+            ParseHtmlError::DuplicateAttribute,
+            ParseHtmlError::DuplicateAttribute,
+            ParseHtmlError::DuplicateAttribute,
+        ]
+    );
+
+    // a duplicate right before the end of the tag or a self-closing slash
+    let (tags, errors) = tags_and_errors("<br x=1 x=\"2\"/><hr y y>");
+    assert_eq!(attributes(&tags[0]), vec![("x", "1")]);
+    assert!(tags[0].self_closing);
+    assert_eq!(attributes(&tags[1]), vec![("y", "")]);
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn end_tag_errors() {
+    let (tags, errors) = tags_and_errors("<p></p a=1></p/></p b/>");
+    // the tags are still emitted
+    assert_eq!(tags.len(), 4);
+    assert!(tags[1..]
+        .iter()
+        .all(|tag| tag.is_end_tag && tag.name == "p"));
+    assert_eq!(
+        errors,
+        vec![
+            ParseHtmlError::EndTagWithAttributes,
+            ParseHtmlError::EndTagWithTrailingSolidus,
+            ParseHtmlError::EndTagWithAttributes,
+            ParseHtmlError::EndTagWithTrailingSolidus,
+        ]
+    );
+
+    // an end tag in RCDATA is checked the same way
+    let (tags, errors) = tags_and_errors("<title></title x>");
+    assert_eq!(tags.len(), 2);
+    assert_eq!(errors, vec![ParseHtmlError::EndTagWithAttributes]);
+}
+
+#[test]
+fn preprocessor_newlines() {
+    let mut preprocessor = InputPreprocessor::new();
+    let mut output = String::new();
+    let found = preprocessor.process("a\rb\r", &mut output);
+    assert_eq!(output, "a\nb\n");
+    assert!(found.crlf_offsets.is_empty());
+
+    // the LF finishing the CR from the last chunk is dropped
+    // offsets are relative to this chunk's output
+    let found = preprocessor.process("\nc\r\nd\n", &mut output);
+    assert_eq!(output, "a\nb\nc\nd\n");
+    assert_eq!(found.crlf_offsets, vec![0, 2]);
+
+    // a LF after a LF is kept
+    let found = preprocessor.process("\n", &mut output);
+    assert_eq!(output, "a\nb\nc\nd\n\n");
+    assert!(found.crlf_offsets.is_empty());
+}
+
+#[test]
+fn preprocessor_stream_errors() {
+    let mut preprocessor = InputPreprocessor::new();
+    let mut output = String::from("ignored");
+    let found = preprocessor.process("x\u{1}y\u{FDD0}\u{7F}\r\n\u{80}\t\0\u{10FFFF}", &mut output);
+    assert_eq!(output, "ignoredx\u{1}y\u{FDD0}\u{7F}\n\u{80}\t\0\u{10FFFF}");
+    // whitespace and NULs aren't errors here
+    assert_eq!(
+        found.errors,
+        vec![
+            (1, ParseHtmlError::ControlCharacterInInputStream),
+            (3, ParseHtmlError::NoncharacterInInputStream),
+            (6, ParseHtmlError::ControlCharacterInInputStream),
+            (8, ParseHtmlError::ControlCharacterInInputStream),
+            (12, ParseHtmlError::NoncharacterInInputStream),
+        ]
+    );
+    assert_eq!(found.crlf_offsets, vec![8]);
+}
+
+#[test]
+fn crlf_split_between_chunks() {
+    let errors = Rc::new(RefCell::new(CollectErrors::new()));
+    let mut tokenizer = HtmlTokenizer::new_streaming();
+    tokenizer.set_error_handler(Box::new(errors.clone()));
+    tokenizer.feed("a\r");
+    tokenizer.feed("\n\u{1}<b>\r");
+    tokenizer.feed("\r\n");
+    tokenizer.finish();
+    let tokens: Vec<_> = tokenizer
+        .filter_map(|tok| {
+            let at = (tok.start.line, tok.start.line_offset);
+            match tok.token {
+                Token::Character(c) => Some((c.to_string(), at)),
+                Token::Tag(tag) => Some((format!("<{}>", tag.name), at)),
+                Token::Eof => None,
+                _ => panic!("unexpected token"),
+            }
+        })
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            ("a".into(), (0, 0)),
+            ("\n".into(), (0, 1)),
+            ("\u{1}".into(), (1, 0)),
+            ("<b>".into(), (1, 1)),
+            ("\n".into(), (1, 4)),
+            ("\n".into(), (2, 0)),
+        ]
+    );
+    let errors = &errors.borrow().errors;
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, ParseHtmlError::ControlCharacterInInputStream);
+    assert_eq!((errors[0].1.line, errors[0].1.line_offset), (1, 0));
+}
+
+// tokens and errors with their locations, as (offset, line, column)
+// text runs split between chunks are merged
+fn located_tokens(chunks: &[&str]) -> Vec<String> {
+    let errors = Rc::new(RefCell::new(CollectErrors::new()));
+    let mut tokenizer = HtmlTokenizer::new_streaming();
+    tokenizer.set_coalesce_characters(true);
+    tokenizer.set_error_handler(Box::new(errors.clone()));
+    let mut tokens = vec![];
+    for chunk in chunks {
+        tokenizer.feed(chunk);
+        tokens.extend(&mut tokenizer);
+    }
+    tokenizer.finish();
+    tokens.extend(tokenizer);
+
+    let mut out: Vec<String> = vec![];
+    let mut text: Option<(TokenMetadata, TokenMetadata, usize)> = None;
+    let at = |loc: &TokenMetadata| (loc.file_offset, loc.line, loc.line_offset);
+    for tok in tokens {
+        if let Token::Characters(chars) = &tok.token {
+            let len = chars.chars().count();
+            text = Some(match text.take() {
+                Some((start, _, count)) => (start, tok.end, count + len),
+                None => (tok.start, tok.end, len),
+            });
+            continue;
+        }
+        if let Some((start, end, count)) = text.take() {
+            out.push(format!("{} chars {:?} {:?}", count, at(&start), at(&end)));
+        }
+        let kind = match &tok.token {
+            Token::Tag(tag) => format!("<{}>", tag.name),
+            Token::Comment(_) => "comment".into(),
+            _ => "other".into(),
+        };
+        out.push(format!("{} {:?} {:?}", kind, at(&tok.start), at(&tok.end)));
+    }
+    for (err, loc) in errors.borrow().errors.iter() {
+        out.push(format!("{:?} {:?}", err, at(loc)));
+    }
+    out
+}
+
+#[test]
+fn locations_survive_dropping_consumed_input() {
+    // long lines, so input is dropped partway through them
+    let mut html = String::new();
+    for i in 0..200 {
+        html.push_str(&format!("<p id={}>", i));
+        html.push_str(&"\u{E9}t\u{E9} &amp; ".repeat(i * 3));
+        html.push_str("\u{1}<!-- x -->&not\r\n");
+    }
+    let whole = located_tokens(&[&html]);
+    for &size in &[1, 7, 100, 5000] {
+        let mut chunks = vec![];
+        let mut rest = &html[..];
+        while !rest.is_empty() {
+            let mut end = size.min(rest.len());
+            while !rest.is_char_boundary(end) {
+                end += 1;
+            }
+            chunks.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+        assert_eq!(located_tokens(&chunks), whole, "chunks of {}", size);
+    }
+}
+
+#[test]
+fn finish_twice() {
+    let mut tokenizer = HtmlTokenizer::new_streaming();
+    tokenizer.feed("a");
+    tokenizer.finish();
+    tokenizer.finish();
+    assert_eq!(tokenizer.count(), 2);
+}
+
+#[test]
+#[should_panic(expected = "input fed after `finish`")]
+fn feed_after_finish() {
+    let mut tokenizer = HtmlTokenizer::new_streaming();
+    tokenizer.finish();
+    tokenizer.feed("a");
+}
@@ -0,0 +1,106 @@
+/* ============================================================================
+ * File:   parse_errors.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Checks that parse errors reach the error handlers with their locations,
+//   and that a handler can abort parsing
+use iron::html::parser::detail::ParseHtmlError;
+use iron::html::parser::error::{CollectErrors, StrictErrors};
+use iron::html::parser::HtmlParser;
+use iron::html::serializer::serialize_document;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn collect_errors() {
+    let html = "<!DOCTYPE html>\n<p a=1 a=2>caf\u{E9} &#0;</p x>\n</div>";
+    let errors = Rc::new(RefCell::new(CollectErrors::new()));
+    let mut parser = HtmlParser::new();
+    parser.set_error_handler(Box::new(errors.clone()));
+    parser
+        .tokenizer()
+        .set_error_handler(Box::new(errors.clone()));
+    parser.feed(html);
+    parser.finish();
+
+    // (error, line, column, byte offset), all zero-based
+    let found: Vec<_> = errors
+        .borrow()
+        .errors
+        .iter()
+        .map(|(err, location)| {
+            (
+                *err,
+                location.line,
+                location.line_offset,
+                location.file_offset,
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            // at the `=` that ends the second `a`
+            (ParseHtmlError::DuplicateAttribute, 1, 8, 24),
+            // after the `;`; the `é` is two bytes
+            (ParseHtmlError::NullCharacterReference, 1, 20, 37),
+            (ParseHtmlError::EndTagWithAttributes, 1, 25, 42),
+            // tree construction errors are at the start of the token
+            (ParseHtmlError::UnexpectedEndTag, 2, 0, 44),
+        ]
+    );
+}
+
+#[test]
+fn strict_errors_stop_tokenizing() {
+    let strict = Rc::new(RefCell::new(StrictErrors::new()));
+    let mut parser = HtmlParser::new();
+    parser
+        .tokenizer()
+        .set_error_handler(Box::new(strict.clone()));
+    parser.feed("<!DOCTYPE html><p a=1 a=2>text</p><p>more</p>");
+    parser.finish();
+
+    assert!(parser.tokenizer().aborted());
+    let (err, location) = strict.borrow_mut().error.take().unwrap();
+    assert_eq!(err, ParseHtmlError::DuplicateAttribute);
+    // the `=` after the second `a`
+    assert_eq!(location.file_offset, 23);
+    // nothing after the error was tokenized, not even the end of the input
+    assert_eq!(
+        serialize_document(parser.document(), true),
+        "<!DOCTYPE html>"
+    );
+}
+
+#[test]
+fn strict_errors_stop_tree_construction() {
+    let mut parser = HtmlParser::new();
+    parser.set_error_handler(Box::new(StrictErrors::new()));
+    parser.feed("<!DOCTYPE html><p>a</div><p>b</p>");
+    parser.finish();
+
+    assert!(parser.aborted());
+    assert_eq!(
+        serialize_document(parser.document(), true),
+        "<!DOCTYPE html><html><head></head><body><p>a</p></body></html>"
+    );
+}
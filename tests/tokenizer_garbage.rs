@@ -0,0 +1,620 @@
+/* ============================================================================
+ * File:   tokenizer_garbage.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Feeds randomly generated garbage through `HtmlTokenizer` to make sure that
+//   no input can make it panic or loop forever.
+// The same input is then parsed into documents and fragments, which are
+//   serialized, and scanned for resources, to check the rest of the
+//   pipeline doesn't either.
+// The generator is seeded, so any failure can be reproduced from the seed in
+//   its message.
+use iron::html::element::{Handle, QualName, RcDom};
+use iron::html::parser::error::CollectErrors;
+use iron::html::parser::preload::{CollectPreloads, PreloadScanner};
+use iron::html::parser::script::{ScriptAction, ScriptHandler};
+use iron::html::parser::HtmlParser;
+use iron::html::serializer::dump::{dump_document, dump_nodes};
+use iron::html::serializer::{serialize, serialize_document, serialize_outer};
+use iron::html::tokenizer::detail::{State, Token, TokenWithLocation};
+use iron::html::tokenizer::HtmlTokenizer;
+use iron::infra::namespace::Namespace;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const CASES: u64 = 5000;
+
+// a test that takes this long is assumed to be stuck in a loop
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+// pieces of markup that lead into the less common tokenizer states
+const FRAGMENTS: &[&str] = &[
+    "<",
+    "</",
+    ">",
+    "/>",
+    "/",
+    "=",
+    "\"",
+    "'",
+    "`",
+    "-",
+    "--",
+    "!",
+    "?",
+    "&",
+    ";",
+    "#",
+    "#x",
+    "&amp",
+    "&amp;",
+    "&notin",
+    "&noti",
+    "&#",
+    "&#x",
+    "&#X",
+    "&#0;",
+    "&#x110000;",
+    "&#99999999999999999999;",
+    "&#xD800;",
+    "&#x80;",
+    "&#128;",
+    "&#xFFFF;",
+    "<!--",
+    "-->",
+    "--!>",
+    "<!-",
+    "<!",
+    "<!DOCTYPE",
+    "<!doctype html",
+    " PUBLIC ",
+    " SYSTEM ",
+    " public\"",
+    "system'",
+    "<![CDATA[",
+    "]]>",
+    "]",
+    "]]",
+    "<script>",
+    "</script>",
+    "<!--<script>",
+    "</script",
+    "<title>",
+    "</title>",
+    "<style>",
+    "</style>",
+    "<textarea>",
+    "<plaintext>",
+    "<a href=x>",
+    "<a b='c' d=\"e\" f>",
+    "<p a a>",
+    "</p x=y/>",
+    // these lead into the less common insertion modes
+    "<html>",
+    "<head>",
+    "<body>",
+    "</body>",
+    "<frameset>",
+    "<frame>",
+    "<table>",
+    "</table>",
+    "<caption>",
+    "<colgroup>",
+    "<col>",
+    "<tbody>",
+    "<tr>",
+    "<td>",
+    "</td>",
+    "<th>",
+    "<select>",
+    "<option>",
+    "<optgroup>",
+    "</select>",
+    "<template>",
+    "</template>",
+    "<b>",
+    "</b>",
+    "<i>",
+    "<a>",
+    "</a>",
+    "<nobr>",
+    "<p>",
+    "</p>",
+    "<li>",
+    "<dd>",
+    "<h1>",
+    "</h2>",
+    "<form>",
+    "</form>",
+    "<button>",
+    "<br>",
+    "</br>",
+    "<input type=hidden>",
+    "<image>",
+    "<ruby><rt>",
+    "<noscript>",
+    "<iframe>",
+    "<xmp>",
+    "<svg>",
+    "<math>",
+    "<mi>",
+    "<foreignObject>",
+    "<annotation-xml encoding=text/html>",
+    "<font color=red>",
+    "<base href=x>",
+    "<img src=y srcset='a 1x, b'>",
+    "<link rel=stylesheet href=z>",
+    "<script src=w>",
+    "a",
+    "Z",
+    "0",
+    "9",
+    "f",
+    " ",
+    "\t",
+    "\n",
+    "\r",
+    "\r\n",
+    "\u{C}",
+    "\0",
+    "\u{1}",
+    "\u{7F}",
+    "\u{85}",
+    "\u{FDD0}",
+    "\u{FFFD}",
+    "\u{FFFF}",
+    "\u{10FFFF}",
+    "é",
+    "日本",
+];
+
+const STATES: &[State] = &[
+    State::Data,
+    State::Rcdata,
+    State::Rawtext,
+    State::ScriptData,
+    State::Plaintext,
+    State::TagOpen,
+    State::EndTagOpen,
+    State::TagName,
+    State::RcdataLessThanSign,
+    State::RcdataEndTagOpen,
+    State::RcdataEndTagName,
+    State::RawtextLessThanSign,
+    State::RawtextEndTagOpen,
+    State::RawtextEndTagName,
+    State::ScriptDataLessThanSign,
+    State::ScriptDataEndTagOpen,
+    State::ScriptDataEndTagName,
+    State::ScriptDataEscapeStart,
+    State::ScriptDataEscapeStartDash,
+    State::ScriptDataEscaped,
+    State::ScriptDataEscapedDash,
+    State::ScriptDataEscapedDashDash,
+    State::ScriptDataEscapedLessThanSign,
+    State::ScriptDataEscapedEndTagOpen,
+    State::ScriptDataEscapedEndTagName,
+    State::ScriptDataDoubleEscapeStart,
+    State::ScriptDataDoubleEscaped,
+    State::ScriptDataDoubleEscapedDash,
+    State::ScriptDataDoubleEscapedDashDash,
+    State::ScriptDataDoubleEscapedLessThanSign,
+    State::ScriptDataDoubleEscapeEnd,
+    State::BeforeAttributeName,
+    State::AttributeName,
+    State::AfterAttributeName,
+    State::BeforeAttributeValue,
+    State::AttributeValueDoubleQuoted,
+    State::AttributeValueSingleQuoted,
+    State::AttributeValueUnquoted,
+    State::AfterAttributeValueQuoted,
+    State::SelfClosingStartTag,
+    State::BogusComment,
+    State::MarkupDeclarationOpen,
+    State::CommentStart,
+    State::CommentStartDash,
+    State::Comment,
+    State::CommentLessThanSign,
+    State::CommentLessThanSignBang,
+    State::CommentLessThanSignBangDash,
+    State::CommentLessThanSignBangDashDash,
+    State::CommentEndDash,
+    State::CommentEnd,
+    State::CommentEndBang,
+    State::Doctype,
+    State::BeforeDoctypeName,
+    State::DoctypeName,
+    State::AfterDoctypeName,
+    State::AfterDoctypePublicKeyword,
+    State::BeforeDoctypePublicIdentifier,
+    State::DoctypePublicIdentifierDoubleQuoted,
+    State::DoctypePublicIdentifierSingleQuoted,
+    State::AfterDoctypePublicIdentifier,
+    State::BetweenDoctypePublicAndSystemIdentifiers,
+    State::AfterDoctypeSystemKeyword,
+    State::BeforeDoctypeSystemIdentifier,
+    State::DoctypeSystemIdentifierDoubleQuoted,
+    State::DoctypeSystemIdentifierSingleQuoted,
+    State::AfterDoctypeSystemIdentifier,
+    State::BogusDoctype,
+    State::CdataSection,
+    State::CdataSectionBracket,
+    State::CdataSectionEnd,
+    State::CharacterReference,
+    State::NamedCharacterReference,
+    State::AmbiguousAmpersand,
+    State::NumericCharacterReference,
+    State::HexadecimalCharacterReferenceStart,
+    State::DecimalCharacterReferenceStart,
+    State::HexadecimalCharacterReference,
+    State::DecimalCharacterReference,
+    State::NumericCharacterReferenceEnd,
+];
+
+// xorshift64*; good enough for generating garbage
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // the state must never be zero
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, one_in: usize) -> bool {
+        self.below(one_in) == 0
+    }
+}
+
+fn random_bytes(rng: &mut Rng) -> Vec<u8> {
+    let len = rng.below(256);
+    (0..len).map(|_| rng.next() as u8).collect()
+}
+
+fn random_markup(rng: &mut Rng) -> Vec<u8> {
+    let mut markup = String::new();
+    for _ in 0..rng.below(64) {
+        markup.push_str(FRAGMENTS[rng.below(FRAGMENTS.len())]);
+    }
+    let mut bytes = markup.into_bytes();
+    // occasionally break the UTF-8 too
+    if !bytes.is_empty() && rng.chance(4) {
+        let i = rng.below(bytes.len());
+        bytes[i] = rng.next() as u8;
+    }
+    bytes
+}
+
+// the elements fragments are parsed in the context of
+const CONTEXTS: &[(Namespace, &str)] = &[
+    (Namespace::Html, "html"),
+    (Namespace::Html, "head"),
+    (Namespace::Html, "body"),
+    (Namespace::Html, "div"),
+    (Namespace::Html, "frameset"),
+    (Namespace::Html, "table"),
+    (Namespace::Html, "tbody"),
+    (Namespace::Html, "tr"),
+    (Namespace::Html, "td"),
+    (Namespace::Html, "select"),
+    (Namespace::Html, "template"),
+    (Namespace::Html, "title"),
+    (Namespace::Html, "textarea"),
+    (Namespace::Html, "style"),
+    (Namespace::Html, "script"),
+    (Namespace::Html, "noscript"),
+    (Namespace::Html, "plaintext"),
+    (Namespace::Svg, "svg"),
+    (Namespace::Svg, "foreignObject"),
+    (Namespace::MathML, "math"),
+    (Namespace::MathML, "annotation-xml"),
+];
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Attribute(attr) => format!("attribute {:?}={:?}", attr.name, attr.value),
+        Token::Character(c) => format!("character {:?}", c),
+        Token::Characters(s) => format!("characters {:?}", s),
+        Token::Comment(comment) => format!("comment {:?}", comment.value),
+        Token::Doctype(doctype) => format!(
+            "doctype {:?} {:?} {:?} {}",
+            doctype.name, doctype.public_id, doctype.system_id, doctype.force_quirks
+        ),
+        Token::Eof => "eof".into(),
+        Token::Tag(tag) => {
+            let attrs: Vec<String> = tag
+                .attributes
+                .iter()
+                .flatten()
+                .map(|attr| format!("{:?}={:?}", attr.name, attr.value))
+                .collect();
+            format!(
+                "tag {:?} end={} self_closing={} {}",
+                tag.name,
+                tag.is_end_tag,
+                tag.self_closing,
+                attrs.join(" ")
+            )
+        }
+    }
+}
+
+// tokenizes `input` the way a tree builder would drive the tokenizer, giving
+//   it the input in chunks of the given sizes
+fn tokenize(
+    input: &[u8],
+    chunk_sizes: &[usize],
+    start_state: State,
+    coalesce: bool,
+) -> Vec<TokenWithLocation> {
+    let mut tokenizer = HtmlTokenizer::new_streaming();
+    tokenizer.switch_to(start_state);
+    tokenizer.set_coalesce_characters(coalesce);
+    tokenizer.set_error_handler(Box::new(CollectErrors::new()));
+
+    let mut tokens = vec![];
+    let drain = |tokenizer: &mut HtmlTokenizer, tokens: &mut Vec<TokenWithLocation>| {
+        while let Some(token) = tokenizer.next() {
+            if let Token::Tag(tag) = &token.token {
+                if !tag.is_end_tag {
+                    match &tag.name[..] {
+                        "title" | "textarea" => tokenizer.switch_to(State::Rcdata),
+                        "style" => tokenizer.switch_to(State::Rawtext),
+                        "script" => tokenizer.switch_to(State::ScriptData),
+                        "plaintext" => tokenizer.switch_to(State::Plaintext),
+                        "svg" => tokenizer.set_adjusted_current_node_foreign(true),
+                        _ => (),
+                    }
+                }
+            }
+            tokens.push(token);
+        }
+    };
+
+    let mut rest = input;
+    let mut sizes = chunk_sizes.iter().cycle();
+    while !rest.is_empty() {
+        let size = (*sizes.next().unwrap()).max(1).min(rest.len());
+        tokenizer.feed_bytes(&rest[..size]);
+        rest = &rest[size..];
+        drain(&mut tokenizer, &mut tokens);
+    }
+    tokenizer.finish();
+    drain(&mut tokenizer, &mut tokens);
+    tokens
+}
+
+// splits `text` into chunks of about `size` bytes, on character boundaries
+fn split(text: &str, size: usize) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut end = size.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end += 1;
+        }
+        chunks.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    chunks
+}
+
+// blocks on every script, so the parser pauses and scans ahead
+struct BlockScripts;
+
+impl ScriptHandler<Handle> for BlockScripts {
+    fn prepare_script(&mut self, _script: &Handle, _nesting_level: u32) -> ScriptAction {
+        ScriptAction::Block
+    }
+
+    fn execute_script(&mut self, _script: &Handle, _nesting_level: u32) {}
+}
+
+// parses `input` as a document and as a fragment, serializes the results, and
+//   scans it for resources
+fn build_trees(rng: &mut Rng, input: &[u8]) {
+    let html = String::from_utf8_lossy(input);
+
+    // a document given in chunks, sometimes blocking on scripts
+    let mut parser = HtmlParser::new();
+    let scripting = !rng.chance(4);
+    parser.set_scripting(scripting);
+    parser.set_error_handler(Box::new(CollectErrors::new()));
+    let block = rng.chance(2);
+    if block {
+        parser.set_script_handler(Box::new(BlockScripts));
+        parser.set_preload_handler(Box::new(CollectPreloads::new()));
+    }
+    for chunk in split(&html, 1 + rng.below(32)) {
+        parser.feed(chunk);
+        if rng.chance(2) {
+            parser.resume();
+        }
+    }
+    parser.finish();
+    while parser.paused() {
+        parser.resume();
+    }
+    let dom = parser.into_document();
+    serialize_document(&dom, scripting);
+    dump_document(&dom);
+
+    HtmlParser::parse_bytes(input, None);
+
+    let (ns, name) = &CONTEXTS[rng.below(CONTEXTS.len())];
+    let context =
+        RcDom::new().create_element(QualName::new(None, ns.clone(), name.to_string()), vec![]);
+    let mut parser = HtmlParser::new_fragment(&context);
+    let scripting = !rng.chance(4);
+    parser.set_scripting(scripting);
+    parser.feed(&html);
+    parser.finish();
+    let nodes = parser.into_fragment();
+    for node in nodes.iter() {
+        serialize(node, scripting);
+        serialize_outer(node, scripting);
+    }
+    dump_nodes(&nodes);
+
+    PreloadScanner::scan(&html);
+}
+
+// merges character tokens so coalesced and uncoalesced output can be compared
+fn normalize(tokens: &[TokenWithLocation]) -> Vec<String> {
+    let mut normalized = vec![];
+    let mut text = String::new();
+    for token in tokens {
+        match &token.token {
+            Token::Character(c) => text.push(*c),
+            Token::Characters(s) => text.push_str(s),
+            other => {
+                if !text.is_empty() {
+                    normalized.push(format!("text {:?}", text));
+                    text.clear();
+                }
+                normalized.push(describe(other));
+            }
+        }
+    }
+    if !text.is_empty() {
+        normalized.push(format!("text {:?}", text));
+    }
+    normalized
+}
+
+fn check_case(seed: u64) {
+    let mut rng = Rng::new(seed);
+    let input = if rng.chance(3) {
+        random_bytes(&mut rng)
+    } else {
+        random_markup(&mut rng)
+    };
+    let start_state = if rng.chance(4) {
+        STATES[rng.below(STATES.len())]
+    } else {
+        State::Data
+    };
+    let chunk_sizes: Vec<usize> = (0..4).map(|_| 1 + rng.below(16)).collect();
+
+    let whole = tokenize(&input, &[input.len()], start_state, false);
+
+    // exactly one EOF token, at the end
+    let eofs = whole
+        .iter()
+        .filter(|token| matches!(token.token, Token::Eof))
+        .count();
+    assert_eq!(eofs, 1, "seed {}: expected one EOF token", seed);
+    assert!(
+        matches!(whole.last().map(|token| &token.token), Some(Token::Eof)),
+        "seed {}: EOF isn't the last token",
+        seed
+    );
+
+    // token locations stay in order
+    // tokens emitted by the same character share their location, so they
+    //   can overlap
+    let mut prev = (0, 0);
+    for token in &whole {
+        let location = (token.start.file_offset, token.end.file_offset);
+        assert!(
+            location.0 <= location.1,
+            "seed {}: token ends before it starts",
+            seed
+        );
+        assert!(
+            location.0 >= prev.0 && location.1 >= prev.1,
+            "seed {}: tokens out of order",
+            seed
+        );
+        prev = location;
+    }
+
+    // how the input is split up and whether text is coalesced don't change
+    //   the tokens
+    let expected = normalize(&whole);
+    let streamed = tokenize(&input, &chunk_sizes, start_state, false);
+    assert_eq!(
+        expected,
+        normalize(&streamed),
+        "seed {}: streaming in chunks of {:?} changed the tokens",
+        seed,
+        chunk_sizes
+    );
+    let coalesced = tokenize(&input, &chunk_sizes, start_state, true);
+    assert_eq!(
+        expected,
+        normalize(&coalesced),
+        "seed {}: coalescing characters changed the tokens",
+        seed
+    );
+
+    let built = panic::catch_unwind(AssertUnwindSafe(|| build_trees(&mut rng, &input)));
+    assert!(built.is_ok(), "seed {}: building trees panicked", seed);
+}
+
+// runs `test` on another thread so a tokenizer or parser stuck in a loop fails
+//   the test instead of hanging it
+fn with_timeout<F: FnOnce() + Send + 'static>(test: F) {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(test));
+        let _ = sender.send(result.is_ok());
+    });
+    match receiver.recv_timeout(TIMEOUT) {
+        Ok(passed) => assert!(passed, "a case panicked; see the message above"),
+        Err(_) => panic!("the tokenizer or parser is stuck in a loop"),
+    }
+}
+
+#[test]
+fn random_input_never_panics() {
+    with_timeout(|| {
+        for seed in 0..CASES {
+            check_case(seed);
+        }
+    });
+}
+
+#[test]
+fn long_runs_never_panic() {
+    // long runs of one fragment hit the lookahead and numeric paths hardest
+    with_timeout(|| {
+        for fragment in FRAGMENTS {
+            let input = fragment.repeat(200);
+            for &state in STATES {
+                tokenize(input.as_bytes(), &[7], state, false);
+                tokenize(input.as_bytes(), &[input.len()], state, true);
+            }
+        }
+    });
+}
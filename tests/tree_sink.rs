@@ -0,0 +1,388 @@
+/* ============================================================================
+ * File:   tree_sink.rs
+ * Author: Cole Johnson
+ * ============================================================================
+ * Copyright (c) 2020 Cole Johnson
+ *
+ * This file is part of Iron.
+ *
+ * Iron is free software: you can redistribute it and/or modify it under the
+ *   terms of the GNU General Public License as published by the Free Software
+ *   Foundation, either version 3 of the License, or (at your option) any later
+ *   version.
+ *
+ * Iron is distributed in the hope that it will be useful, but WITHOUT ANY
+ *   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ *   FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ *   details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ *   Iron. If not, see <http://www.gnu.org/licenses/>.
+ * ============================================================================
+ */
+// Builds trees through `TreeSink` with a sink that isn't `RcDom`, and checks
+//   `RcDom`'s own sink operations
+use iron::html::element::{Attribute, ExpandedName, NodeData, NodeOrText, QualName, RcDom};
+use iron::html::parser::quirks::QuirksMode;
+use iron::html::parser::sink::TreeSink;
+use iron::html::parser::HtmlParser;
+use iron::html::serializer::dump::dump_document;
+use iron::infra::namespace::Namespace;
+
+enum ArenaData {
+    Document,
+    Doctype(String),
+    Comment(String),
+    Text(String),
+    Element {
+        name: QualName,
+        attrs: Vec<Attribute>,
+        template_contents: Option<usize>,
+        integration_point: bool,
+    },
+}
+
+struct ArenaNode {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    data: ArenaData,
+}
+
+// nodes are indices into a `Vec`, and the document is the first one
+// every call to `append_before_sibling` is counted
+struct ArenaDom {
+    nodes: Vec<ArenaNode>,
+    quirks: bool,
+    inserted_before: usize,
+}
+
+impl ArenaDom {
+    fn new() -> ArenaDom {
+        let mut dom = ArenaDom {
+            nodes: vec![],
+            quirks: false,
+            inserted_before: 0,
+        };
+        dom.new_node(ArenaData::Document);
+        dom
+    }
+
+    fn new_node(&mut self, data: ArenaData) -> usize {
+        self.nodes.push(ArenaNode {
+            parent: None,
+            children: vec![],
+            data,
+        });
+        self.nodes.len() - 1
+    }
+
+    // appends to a text node at `children[i]`, if there is one
+    fn merge_text(&mut self, parent: usize, i: Option<usize>, text: &str) -> bool {
+        let node = match i {
+            Some(i) => self.nodes[parent].children[i],
+            None => return false,
+        };
+        match self.nodes[node].data {
+            ArenaData::Text(ref mut content) => {
+                content.push_str(text);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn insert(&mut self, parent: usize, i: usize, child: NodeOrText<usize>) {
+        let node = match child {
+            NodeOrText::AppendNode(node) => node,
+            NodeOrText::AppendText(text) => {
+                if self.merge_text(parent, i.checked_sub(1), &text) {
+                    return;
+                }
+                self.new_node(ArenaData::Text(text))
+            }
+        };
+        self.nodes[node].parent = Some(parent);
+        self.nodes[parent].children.insert(i, node);
+    }
+
+    // the html5lib-tests tree format, as `dump_document` writes it
+    fn dump(&self, node: usize, depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+        let mut line = |depth: &str, text: &str| {
+            out.push_str(&format!("| {}{}\n", depth, text));
+        };
+        match self.nodes[node].data {
+            ArenaData::Document => {}
+            ArenaData::Doctype(ref name) => line(&indent, &format!("<!DOCTYPE {}>", name)),
+            ArenaData::Comment(ref text) => line(&indent, &format!("<!-- {} -->", text)),
+            ArenaData::Text(ref text) => line(&indent, &format!("\"{}\"", text)),
+            ArenaData::Element {
+                ref name,
+                ref attrs,
+                template_contents,
+                ..
+            } => {
+                let prefix = match name.ns {
+                    Namespace::Svg => "svg ",
+                    Namespace::MathML => "math ",
+                    _ => "",
+                };
+                line(&indent, &format!("<{}{}>", prefix, name.local));
+                let mut attrs: Vec<_> = attrs
+                    .iter()
+                    .map(|attr| format!("{}  {}=\"{}\"", indent, attr.name.local, attr.value))
+                    .collect();
+                attrs.sort();
+                for attr in attrs {
+                    line("", &attr);
+                }
+                if let Some(contents) = template_contents {
+                    line(&indent, "  content");
+                    for &child in self.nodes[contents].children.iter() {
+                        self.dump(child, depth + 2, out);
+                    }
+                }
+            }
+        }
+        let depth = match self.nodes[node].data {
+            ArenaData::Document => depth,
+            _ => depth + 1,
+        };
+        for &child in self.nodes[node].children.iter() {
+            self.dump(child, depth, out);
+        }
+    }
+}
+
+impl TreeSink for ArenaDom {
+    type Handle = usize;
+
+    fn document(&self) -> usize {
+        0
+    }
+
+    fn elem_name(&self, target: &usize) -> ExpandedName {
+        match self.nodes[*target].data {
+            ArenaData::Element { ref name, .. } => ExpandedName {
+                ns: name.ns.clone(),
+                local: name.local.clone(),
+            },
+            _ => panic!("not an element"),
+        }
+    }
+
+    fn create_element(&mut self, name: QualName, attrs: Vec<Attribute>) -> usize {
+        let template_contents = if name.ns == Namespace::Html && name.local == "template" {
+            Some(self.new_node(ArenaData::Document))
+        } else {
+            None
+        };
+        let integration_point = name.ns == Namespace::MathML
+            && name.local == "annotation-xml"
+            && attrs.iter().any(|attr| {
+                attr.name.local == "encoding"
+                    && (attr.value.eq_ignore_ascii_case("text/html")
+                        || attr.value.eq_ignore_ascii_case("application/xhtml+xml"))
+            });
+        self.new_node(ArenaData::Element {
+            name,
+            attrs,
+            template_contents,
+            integration_point,
+        })
+    }
+
+    fn create_comment(&mut self, text: String) -> usize {
+        self.new_node(ArenaData::Comment(text))
+    }
+
+    fn append_doctype_to_document(&mut self, name: String, _: String, _: String) {
+        let doctype = self.new_node(ArenaData::Doctype(name));
+        self.append(&0, NodeOrText::AppendNode(doctype));
+    }
+
+    fn append(&mut self, parent: &usize, child: NodeOrText<usize>) {
+        if let NodeOrText::AppendNode(node) = child {
+            self.remove_from_parent(&node);
+        }
+        let i = self.nodes[*parent].children.len();
+        self.insert(*parent, i, child);
+    }
+
+    fn append_before_sibling(&mut self, sibling: &usize, child: NodeOrText<usize>) {
+        self.inserted_before += 1;
+        if let NodeOrText::AppendNode(node) = child {
+            self.remove_from_parent(&node);
+        }
+        let parent = self.nodes[*sibling].parent.expect("sibling has no parent");
+        let i = self.nodes[parent]
+            .children
+            .iter()
+            .position(|child| child == sibling)
+            .unwrap();
+        self.insert(parent, i, child);
+    }
+
+    fn parent(&self, target: &usize) -> Option<usize> {
+        self.nodes[*target].parent
+    }
+
+    fn children(&self, target: &usize) -> Vec<usize> {
+        self.nodes[*target].children.clone()
+    }
+
+    fn remove_from_parent(&mut self, target: &usize) {
+        if let Some(parent) = self.nodes[*target].parent.take() {
+            self.nodes[parent].children.retain(|child| child != target);
+        }
+    }
+
+    fn reparent_children(&mut self, node: &usize, new_parent: &usize) {
+        let children = std::mem::take(&mut self.nodes[*node].children);
+        for &child in children.iter() {
+            self.nodes[child].parent = Some(*new_parent);
+        }
+        self.nodes[*new_parent].children.extend(children);
+    }
+
+    fn template_contents(&self, target: &usize) -> Option<usize> {
+        match self.nodes[*target].data {
+            ArenaData::Element {
+                template_contents, ..
+            } => template_contents,
+            _ => None,
+        }
+    }
+
+    fn same_node(&self, x: &usize, y: &usize) -> bool {
+        x == y
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, target: &usize) -> bool {
+        match self.nodes[*target].data {
+            ArenaData::Element {
+                integration_point, ..
+            } => integration_point,
+            _ => false,
+        }
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &usize, new_attrs: Vec<Attribute>) {
+        if let ArenaData::Element { ref mut attrs, .. } = self.nodes[*target].data {
+            for attr in new_attrs {
+                if !attrs
+                    .iter()
+                    .any(|existing| existing.name.local == attr.name.local)
+                {
+                    attrs.push(attr);
+                }
+            }
+        }
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.quirks = matches!(mode, QuirksMode::Full);
+    }
+}
+
+fn parse_arena(html: &str) -> ArenaDom {
+    let mut parser = HtmlParser::with_sink(ArenaDom::new());
+    parser.feed(html);
+    parser.finish();
+    parser.into_document()
+}
+
+#[test]
+fn arena_sink_builds_the_same_tree() {
+    for html in &[
+        "<!DOCTYPE html><title>t</title><p class=x id=y>a<!--c-->b",
+        // the adoption agency algorithm
+        "<p><b><i>x</p>y</b>z",
+        "<a><p>x<a>y</a>z",
+        "<b>1<p>2</b>3</p>",
+        "<div><a><div><div>x</a>y",
+        // foster parenting, including text merged before the table
+        "<table>a<tr>b<td>c</td>d</tr>e</table>",
+        "x<table><b>y<tr><td>z</b>w",
+        "<table><a>1<p>2</a>3</table>",
+        "<template><td>x</td><tr></template><body>",
+        "<body a=1><body b=2 a=3><html c=4>",
+        "<svg><foreignObject><p>x</p></foreignObject><desc><b>y",
+        "<math><annotation-xml encoding=text/html><div>x</div>",
+        "<select><option>a<optgroup>b</select><frameset>",
+    ] {
+        let arena = parse_arena(html);
+        let mut dump = String::new();
+        arena.dump(0, 0, &mut dump);
+        assert_eq!(dump, dump_document(&HtmlParser::parse(html)), "{}", html);
+    }
+}
+
+#[test]
+fn arena_sink_state() {
+    let arena = parse_arena("<table>a<tr><td>x</table>");
+    // the foster parented text
+    assert_eq!(arena.inserted_before, 1);
+    assert!(arena.quirks);
+    assert!(!parse_arena("<!DOCTYPE html>").quirks);
+}
+
+#[test]
+fn rcdom_moves_nodes_before_siblings() {
+    let mut dom = RcDom::new();
+    let html = QualName::new(None, Namespace::Html, "html".into());
+    let parent = dom.create_element(html, vec![]);
+    let children: Vec<_> = (0..4).map(|_| dom.create_comment(String::new())).collect();
+    for child in children.iter() {
+        TreeSink::append(&mut dom, &parent, NodeOrText::AppendNode(child.clone()));
+    }
+    let order = |dom: &RcDom| -> Vec<usize> {
+        dom.children(&parent)
+            .iter()
+            .map(|node| {
+                children
+                    .iter()
+                    .position(|child| dom.same_node(child, node))
+                    .unwrap()
+            })
+            .collect()
+    };
+
+    // moving forward within the same parent shifts the sibling back
+    TreeSink::append_before_sibling(
+        &mut dom,
+        &children[2],
+        NodeOrText::AppendNode(children[0].clone()),
+    );
+    assert_eq!(order(&dom), vec![1, 0, 2, 3]);
+    TreeSink::append_before_sibling(
+        &mut dom,
+        &children[1],
+        NodeOrText::AppendNode(children[3].clone()),
+    );
+    assert_eq!(order(&dom), vec![3, 1, 0, 2]);
+    // moving before the node right after it doesn't change anything
+    TreeSink::append_before_sibling(
+        &mut dom,
+        &children[2],
+        NodeOrText::AppendNode(children[0].clone()),
+    );
+    assert_eq!(order(&dom), vec![3, 1, 0, 2]);
+
+    // moving between parents
+    let other = dom.create_comment(String::new());
+    TreeSink::append(
+        &mut dom,
+        &children[0],
+        NodeOrText::AppendNode(other.clone()),
+    );
+    TreeSink::append_before_sibling(
+        &mut dom,
+        &children[1],
+        NodeOrText::AppendNode(other.clone()),
+    );
+    assert_eq!(dom.children(&parent).len(), 5);
+    assert!(dom.children(&children[0]).is_empty());
+    assert!(dom.same_node(&dom.parent(&other).unwrap(), &parent));
+    assert!(matches!(other.data, NodeData::Comment { .. }));
+}